@@ -0,0 +1,107 @@
+// Duplicate lookup on synthetic 64-bit hashes: multi-index hashing and the
+// BK-tree the scans choose between, against comparing with every earlier
+// hash, as the scans used to.
+//
+//     cargo bench --bench similarity [-- <count>]
+
+// Its unit tests run with the binary's; here their helpers go unused
+#[path = "../src/hash_index.rs"]
+#[cfg_attr(test, allow(dead_code))]
+mod hash_index;
+
+use hash_index::{BkTree, HashIndex, MultiIndex};
+use image_hasher::ImageHash;
+use std::time::Instant;
+
+/// xorshift64*, so runs are repeatable without a rand dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// `count` hashes: random "originals", each followed by a few copies with
+/// up to three bits flipped, like a photo library with re-saved images.
+fn synthetic_hashes(count: usize) -> Vec<ImageHash> {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    let mut bits = Vec::with_capacity(count);
+    while bits.len() < count {
+        let original = rng.next();
+        bits.push(original);
+        for _ in 0..rng.next() % 4 {
+            let mut copy = original;
+            for _ in 0..rng.next() % 4 {
+                copy ^= 1 << (rng.next() % 64);
+            }
+            bits.push(copy);
+        }
+    }
+    bits.truncate(count);
+    bits.iter().map(|b| ImageHash::from_bytes(&b.to_le_bytes()).unwrap()).collect()
+}
+
+/// Group like the interactive scan: each hash joins the first earlier
+/// unique hash within `threshold`, else becomes unique. Returns the
+/// number of duplicates.
+fn linear(hashes: &[ImageHash], threshold: u32) -> usize {
+    let mut unique: Vec<&ImageHash> = Vec::new();
+    let mut duplicates = 0;
+    for hash in hashes {
+        if unique.iter().any(|u| u.dist(hash) <= threshold) {
+            duplicates += 1;
+        } else {
+            unique.push(hash);
+        }
+    }
+    duplicates
+}
+
+fn indexed(hashes: &[ImageHash], mut index: HashIndex) -> usize {
+    let mut unique = 0;
+    let mut duplicates = 0;
+    for hash in hashes {
+        if index.first_match(std::slice::from_ref(hash)).is_some() {
+            duplicates += 1;
+        } else {
+            index.insert(hash.clone(), unique);
+            unique += 1;
+        }
+    }
+    duplicates
+}
+
+/// Milliseconds taken by `f`, and its result.
+fn timed(f: impl FnOnce() -> usize) -> (f64, usize) {
+    let start = Instant::now();
+    let result = f();
+    (start.elapsed().as_secs_f64() * 1000.0, result)
+}
+
+fn main() {
+    // `cargo bench` passes "--bench"; the first number is the count
+    let count = std::env::args().skip(1).find_map(|a| a.parse().ok()).unwrap_or(50_000);
+    let hashes = synthetic_hashes(count);
+    println!("{} synthetic 64-bit hashes", count);
+
+    println!("threshold | duplicates | multi-index | bk-tree    | linear     | chosen");
+    for threshold in [0, 2, 4, 6, 8, 10, 12, 16] {
+        let (multi_ms, multi) = timed(|| indexed(&hashes, HashIndex::Multi(MultiIndex::new(64, threshold))));
+        let (tree_ms, tree) = timed(|| indexed(&hashes, HashIndex::Tree(BkTree::new(threshold))));
+        let (linear_ms, expected) = timed(|| linear(&hashes, threshold));
+
+        assert_eq!((multi, tree), (expected, expected), "indexes and linear scan disagree");
+        let chosen = match HashIndex::new(64, threshold) {
+            HashIndex::Multi(_) => "multi-index",
+            HashIndex::Tree(_) => "bk-tree",
+        };
+        println!(
+            "{:9} | {:10} | {:8.1} ms | {:7.1} ms | {:7.1} ms | {}",
+            threshold, expected, multi_ms, tree_ms, linear_ms, chosen
+        );
+    }
+}
@@ -0,0 +1,612 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, IsTerminal, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::cluster::Group;
+use crate::hash_cache::file_stamp;
+
+// ---------------------------------------------------------------------------
+// Acting on duplicates found by a headless scan (`--action`)
+// ---------------------------------------------------------------------------
+//
+// Every non-kept file of each group is trashed, moved, deleted or replaced
+// by a link to the kept one. The plan is always printed first; `--dry-run`
+// stops there, and nothing is changed until it's confirmed, at the prompt
+// or with `--yes`. Files whose size or modification time changed since the
+// scan are left alone.
+//
+// Each change is appended to an undo journal as soon as it's made, one tab
+// separated line (so paths can't hold tabs or newlines), with absolute
+// paths so `--undo` works from anywhere:
+//
+//     trash     path  trashed-file  trashinfo-file
+//     move      path  destination
+//     delete    path  kept-file
+//     hardlink  path  kept-file  mtime_ns
+//     symlink   path  kept-file  mtime_ns
+//
+// Journals are only ever appended to, by later `--action` runs as well, so
+// a change is known by its line number. `--undo` appends an `undone  line`
+// marker for each change it reverts, and skips marked ones next time.
+// Deletions are only recorded: there is nothing left to restore them from.
+
+const HEADER: &str = "# iv undo journal v1";
+
+/// What `--action` does with the duplicates that aren't kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum DuplicateAction {
+    /// Move to the freedesktop.org trash (~/.local/share/Trash)
+    Trash,
+    /// Move under --move-to, keeping their path below the scanned directory
+    Move,
+    /// Delete permanently; recorded in the journal, but --undo can't restore them
+    Delete,
+    /// Replace byte-identical copies with a hard link to the kept file
+    Hardlink,
+    /// Replace byte-identical copies with a symbolic link to the kept file
+    Symlink,
+}
+
+impl DuplicateAction {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Trash => "trash",
+            Self::Move => "move",
+            Self::Delete => "delete",
+            Self::Hardlink => "hardlink",
+            Self::Symlink => "symlink",
+        }
+    }
+
+    /// Links replace a file's contents with the kept file's, so they're
+    /// only made for byte-identical copies.
+    fn links(self) -> bool {
+        matches!(self, Self::Hardlink | Self::Symlink)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ActionOptions {
+    pub action: DuplicateAction,
+    /// Destination root for `move`.
+    pub move_to: Option<PathBuf>,
+    pub dry_run: bool,
+    /// Apply the plan without asking (`--yes`).
+    pub yes: bool,
+    pub journal: PathBuf,
+}
+
+/// One planned change: `path` goes, `kept` stays.
+struct Step {
+    path: PathBuf,
+    kept: PathBuf,
+    /// For `move`: where it goes.
+    destination: Option<PathBuf>,
+    size: u64,
+}
+
+/// A path as journaled: absolute, and without tabs or newlines.
+fn journal_path(path: &Path) -> Option<String> {
+    let path = std::path::absolute(path).ok()?;
+    path.to_str().filter(|p| !p.contains(['\t', '\n', '\r'])).map(str::to_string)
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Where `path` goes under `move_to`: its path below the scanned directory
+/// it was found in, or just its name if it was given directly.
+fn move_destination(path: &Path, roots: &[PathBuf], move_to: &Path) -> PathBuf {
+    let relative = roots
+        .iter()
+        .filter(|root| root.is_dir())
+        .filter_map(|root| path.strip_prefix(root).ok())
+        .min_by_key(|rel| rel.components().count())
+        .map(Path::to_path_buf)
+        .or_else(|| path.file_name().map(PathBuf::from))
+        .unwrap_or_default();
+    move_to.join(relative)
+}
+
+/// Why `path` must be left alone, if it must.
+fn refusal(
+    action: DuplicateAction,
+    path: &Path,
+    kept: &Path,
+    exact: bool,
+    path_stamp: Option<(u64, u128)>,
+    kept_stamp: Option<(u64, u128)>,
+) -> Option<&'static str> {
+    if action.links() && !exact {
+        return Some("not byte-identical to the kept file");
+    }
+    if path_stamp.is_none() || file_stamp(path) != path_stamp {
+        return Some("changed since the scan");
+    }
+    if kept_stamp.is_none() || file_stamp(kept) != kept_stamp {
+        return Some("kept file changed since the scan");
+    }
+    // The same file reached twice, e.g. through a followed symlink
+    if let (Ok(a), Ok(b)) = (fs::canonicalize(path), fs::canonicalize(kept))
+        && a == b
+    {
+        return Some("same file as the kept one");
+    }
+    if journal_path(path).is_none() || journal_path(kept).is_none() {
+        return Some("path can't be recorded in the undo journal");
+    }
+    None
+}
+
+/// Plan and, unless `--dry-run`, apply `options.action` to the duplicates of
+/// every group. `stamps` are each file's size and mtime at scan time.
+pub fn run_action(
+    options: &ActionOptions,
+    groups: &[Group],
+    files: &[PathBuf],
+    stamps: &[Option<(u64, u128)>],
+    roots: &[PathBuf],
+) {
+    let action = options.action;
+    let mut steps = Vec::new();
+    let mut skipped = 0;
+    println!("Plan ({}):", action.name());
+    for group in groups {
+        let kept = &files[group.original];
+        for dup in &group.duplicates {
+            let path = &files[dup.item];
+            let stamp = stamps[dup.item];
+            if let Some(reason) = refusal(action, path, kept, dup.exact, stamp, stamps[group.original]) {
+                println!("  skip {}: {}", path.display(), reason);
+                skipped += 1;
+                continue;
+            }
+            let destination = match (action, &options.move_to) {
+                (DuplicateAction::Move, Some(move_to)) => {
+                    let destination = move_destination(path, roots, move_to);
+                    if destination.exists() {
+                        println!("  skip {}: {} already exists", path.display(), destination.display());
+                        skipped += 1;
+                        continue;
+                    }
+                    Some(destination)
+                }
+                _ => None,
+            };
+            match &destination {
+                Some(destination) => println!("  move {} -> {}", path.display(), destination.display()),
+                None => println!("  {} {} (keeping {})", action.name(), path.display(), kept.display()),
+            }
+            let size = stamp.map_or(0, |(size, _)| size);
+            steps.push(Step { path: path.clone(), kept: kept.clone(), destination, size });
+        }
+    }
+    let total: u64 = steps.iter().map(|s| s.size).sum();
+    println!(
+        "{} file(s), {}, to {}; {} skipped.",
+        steps.len(),
+        megabytes(total),
+        action.name(),
+        skipped
+    );
+
+    if action == DuplicateAction::Delete && !steps.is_empty() {
+        println!("Deleted files can't be restored with --undo.");
+    }
+
+    if options.dry_run {
+        println!("Dry run: nothing was changed.");
+        return;
+    }
+    if steps.is_empty() {
+        return;
+    }
+    if options.yes {
+        println!("Applying the plan (--yes).");
+    } else if !confirm() {
+        println!("Nothing was changed.");
+        return;
+    }
+
+    let mut journal = match open_journal(&options.journal) {
+        Ok(journal) => journal,
+        Err(e) => {
+            eprintln!("Error: undo journal {}: {}", options.journal.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let (mut done, mut failed) = (0, 0);
+    for step in &steps {
+        match apply(action, step) {
+            Ok(entry) => {
+                if let Err(e) = writeln!(journal, "{}", entry) {
+                    // The change is made but couldn't be recorded: stop here
+                    eprintln!("Error: undo journal {}: {}", options.journal.display(), e);
+                    eprintln!("Not recorded: {}", entry);
+                    std::process::exit(1);
+                }
+                done += 1;
+            }
+            Err(e) => {
+                eprintln!("Failed to {} {}: {}", action.name(), step.path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "Done: {} file(s) {}, {} failed. {} {}",
+        done,
+        match action {
+            DuplicateAction::Trash => "trashed",
+            DuplicateAction::Move => "moved",
+            DuplicateAction::Delete => "deleted",
+            DuplicateAction::Hardlink | DuplicateAction::Symlink => "linked",
+        },
+        failed,
+        if action == DuplicateAction::Delete { "Recorded in" } else { "Undo with --undo" },
+        options.journal.display()
+    );
+}
+
+/// Ask on the terminal whether to go ahead with the printed plan. Without a
+/// terminal `main` has already insisted on `--yes`.
+fn confirm() -> bool {
+    print!("Apply this plan? [y/N] ");
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    io::stdin().is_terminal()
+        && io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+fn open_journal(path: &Path) -> io::Result<LineWriter<fs::File>> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    let empty = file.metadata()?.len() == 0;
+    let mut writer = LineWriter::new(file);
+    if empty {
+        writeln!(writer, "{}", HEADER)?;
+    }
+    Ok(writer)
+}
+
+/// Carry out one step, returning its journal line.
+fn apply(action: DuplicateAction, step: &Step) -> io::Result<String> {
+    // Both checked by `refusal`
+    let path = journal_path(&step.path).unwrap_or_default();
+    let kept = journal_path(&step.kept).unwrap_or_default();
+    match action {
+        DuplicateAction::Trash => {
+            let (trashed, info) = trash(&step.path)?;
+            Ok(format!("trash\t{}\t{}\t{}", path, trashed.display(), info.display()))
+        }
+        DuplicateAction::Move => {
+            let destination = step.destination.as_deref().unwrap_or(&step.path);
+            let destination = std::path::absolute(destination)?;
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            move_file(&step.path, &destination)?;
+            Ok(format!("move\t{}\t{}", path, destination.display()))
+        }
+        DuplicateAction::Delete => {
+            fs::remove_file(&step.path)?;
+            Ok(format!("delete\t{}\t{}", path, kept))
+        }
+        DuplicateAction::Hardlink | DuplicateAction::Symlink => {
+            let mtime = file_stamp(&step.path).map_or(0, |(_, mtime)| mtime);
+            let tmp = sibling(&step.path, ".iv-link");
+            if action == DuplicateAction::Hardlink {
+                fs::hard_link(&step.kept, &tmp)?;
+            } else {
+                symlink(&fs::canonicalize(&step.kept)?, &tmp)?;
+            }
+            // Swap the link in atomically
+            if let Err(e) = fs::rename(&tmp, &step.path) {
+                let _ = fs::remove_file(&tmp);
+                return Err(e);
+            }
+            Ok(format!("{}\t{}\t{}\t{}", action.name(), path, kept, mtime))
+        }
+    }
+}
+
+/// `path` with `suffix` appended to its file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Whether `a` and `b` are hard links to the same file.
+#[cfg(unix)]
+fn same_inode(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let (a, b) = (fs::metadata(a)?, fs::metadata(b)?);
+    Ok(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+#[cfg(not(unix))]
+fn same_inode(_a: &Path, _b: &Path) -> io::Result<bool> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "hard links are only checked on Unix"))
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "symbolic links are only made on Unix"))
+}
+
+/// Rename, or copy and delete across filesystems, keeping the mtime.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if to.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists", to.display())));
+    }
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let mtime = fs::metadata(from)?.modified()?;
+            fs::copy(from, to)?;
+            fs::File::options().write(true).open(to)?.set_modified(mtime)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+// -- freedesktop.org trash ----------------------------------------------------------
+
+/// `$XDG_DATA_HOME/Trash`, i.e. `~/.local/share/Trash`.
+fn trash_dir() -> Option<PathBuf> {
+    let data = env::var_os("XDG_DATA_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+    Some(data.join("Trash"))
+}
+
+/// `path` percent-encoded as the trash spec wants, keeping '/'.
+fn url_escape(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Move `path` to the home trash, with the `.trashinfo` file that lets file
+/// managers restore it. Returns the trashed file and its info file. Files
+/// on other filesystems fail rather than being copied.
+fn trash(path: &Path) -> io::Result<(PathBuf, PathBuf)> {
+    let trash = trash_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory for the trash"))?;
+    let (files, info) = (trash.join("files"), trash.join("info"));
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&info)?;
+
+    let absolute = journal_path(path).unwrap_or_default();
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem.to_string(), format!(".{}", ext)),
+        _ => (name.clone(), String::new()),
+    };
+    for n in 0.. {
+        let candidate = if n == 0 { name.clone() } else { format!("{}.{}{}", stem, n, extension) };
+        let info_path = info.join(format!("{}.trashinfo", candidate));
+        // Creating the info file reserves the name
+        let mut info_file = match fs::OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        let trashed = files.join(&candidate);
+        if trashed.exists() {
+            let _ = fs::remove_file(&info_path);
+            continue;
+        }
+        let written = write!(
+            info_file,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            url_escape(&absolute),
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+        )
+        .and_then(|_| fs::rename(path, &trashed));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&info_path);
+            return Err(e);
+        }
+        return Ok((trashed, info_path));
+    }
+    unreachable!()
+}
+
+// -- Undo -------------------------------------------------------------------------
+
+/// The changes in a journal's `lines` that haven't been undone yet, newest
+/// first, with their line numbers. Deletions can't be undone, so they're
+/// never pending.
+fn pending_changes(lines: &[String]) -> Vec<(usize, &str)> {
+    let undone: HashSet<usize> = lines
+        .iter()
+        .filter_map(|line| line.strip_prefix("undone\t")?.parse().ok())
+        .collect();
+    lines
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(n, line)| {
+            !line.is_empty()
+                && !line.starts_with('#')
+                && !line.starts_with("undone\t")
+                && !line.starts_with("delete\t")
+                && !undone.contains(n)
+        })
+        .map(|(n, line)| (n, line.as_str()))
+        .collect()
+}
+
+/// Revert the changes recorded in `journal`, newest first. With `dry_run`,
+/// only print what would be restored.
+pub fn run_undo(journal: &Path, dry_run: bool) {
+    let lines: Vec<String> = match fs::File::open(journal) {
+        Ok(file) => BufReader::new(file).lines().map_while(Result::ok).collect(),
+        Err(e) => {
+            eprintln!("Error: undo journal {}: {}", journal.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let changes = pending_changes(&lines);
+    let deleted = lines.iter().filter(|line| line.starts_with("delete\t")).count();
+    if deleted > 0 {
+        println!("{} file(s) were deleted permanently and can't be restored.", deleted);
+    }
+    if dry_run {
+        for (_, line) in &changes {
+            let fields: Vec<&str> = line.split('\t').collect();
+            println!("  restore {} ({})", fields.get(1).unwrap_or(&""), fields[0]);
+        }
+        println!("{} file(s) to restore. Dry run: nothing was changed.", changes.len());
+        return;
+    }
+
+    let mut marker = match open_journal(journal) {
+        Ok(marker) => marker,
+        Err(e) => {
+            eprintln!("Error: undo journal {}: {}", journal.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let (mut restored, mut failed) = (0, 0);
+    for (n, line) in changes {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let path = fields.get(1).unwrap_or(&"");
+        println!("  restore {} ({})", path, fields[0]);
+        match undo(&fields) {
+            Ok(()) => {
+                restored += 1;
+                if let Err(e) = writeln!(marker, "undone\t{}", n) {
+                    eprintln!("Error: undo journal {}: {}", journal.display(), e);
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to restore {}: {}", path, e);
+                failed += 1;
+            }
+        }
+    }
+    println!("Restored {} file(s), {} failed.", restored, failed);
+}
+
+fn undo(fields: &[&str]) -> io::Result<()> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed journal line");
+    match *fields {
+        ["trash", path, trashed, info] => {
+            let path = Path::new(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            move_file(Path::new(trashed), path)?;
+            let _ = fs::remove_file(info);
+            Ok(())
+        }
+        ["move", path, destination] => {
+            let path = Path::new(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            move_file(Path::new(destination), path)
+        }
+        [kind @ ("hardlink" | "symlink"), path, kept, mtime] => {
+            let path = Path::new(path);
+            let mtime: u64 = mtime.parse().map_err(|_| invalid())?;
+            let is_link = match kind {
+                "symlink" => fs::symlink_metadata(path)?.file_type().is_symlink(),
+                _ => same_inode(path, Path::new(kept))?,
+            };
+            if !is_link {
+                return Err(io::Error::other("no longer the link that was made"));
+            }
+            // The link's target has the same bytes the file had
+            let tmp = sibling(path, ".iv-restore");
+            fs::copy(kept, &tmp)?;
+            let restored = fs::File::options()
+                .write(true)
+                .open(&tmp)
+                .and_then(|f| f.set_modified(UNIX_EPOCH + Duration::from_nanos(mtime)))
+                .and_then(|_| fs::rename(&tmp, path));
+            if restored.is_err() {
+                let _ = fs::remove_file(&tmp);
+            }
+            restored
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_escape_keeps_only_unreserved_bytes() {
+        assert_eq!(url_escape("/home/me/a b%.jpg"), "/home/me/a%20b%25.jpg");
+        assert_eq!(url_escape("/é"), "/%C3%A9");
+    }
+
+    #[test]
+    fn moves_keep_the_path_below_the_scanned_directory() {
+        let root = env::temp_dir();
+        let file = root.join("trip").join("a.jpg");
+        let to = Path::new("/dupes");
+        assert_eq!(move_destination(&file, std::slice::from_ref(&root), to), to.join("trip/a.jpg"));
+        // Given directly, or outside every root: just the name
+        assert_eq!(move_destination(&file, std::slice::from_ref(&file), to), to.join("a.jpg"));
+        assert_eq!(move_destination(Path::new("/elsewhere/b.jpg"), &[root], to), to.join("b.jpg"));
+    }
+
+    #[test]
+    fn undo_skips_reverted_changes() {
+        let lines: Vec<String> = [
+            HEADER,
+            "trash\t/a\t/t/a\t/t/a.trashinfo",
+            "move\t/b\t/d/b",
+            "undone\t2",
+            "hardlink\t/c\t/k\t0",
+            "",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+        let pending: Vec<usize> = pending_changes(&lines).iter().map(|&(n, _)| n).collect();
+        assert_eq!(pending, vec![4, 1]);
+    }
+
+    #[test]
+    fn undo_leaves_deletions_alone() {
+        let lines: Vec<String> = [HEADER, "delete\t/a\t/k", "move\t/b\t/d/b"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let pending: Vec<usize> = pending_changes(&lines).iter().map(|&(n, _)| n).collect();
+        assert_eq!(pending, vec![2]);
+    }
+
+    #[test]
+    fn undo_rejects_malformed_lines() {
+        assert_eq!(undo(&["move", "/only-one-path"]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(undo(&["hardlink", "/a", "/b", "soon"]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
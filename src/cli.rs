@@ -1,6 +1,12 @@
 use clap::Parser;
 use sysinfo;
 
+use crate::actions::DuplicateAction;
+use crate::cluster::ClusterPolicy;
+use crate::dedupe::{HashAlgorithm, HashFilter, Threshold};
+use crate::metadata::MetadataFormat;
+use crate::report::DumpFormat;
+
 pub const HELP_KEYS: &str = "\
 Key Bindings:
   Esc / q       : Quit
@@ -11,21 +17,28 @@ Key Bindings:
   s             : Cycle font size
   t             : Toggle thumbnail view
   i             : Toggle info overlay
-  M             : Dump metadata to stdout
+  I             : Toggle metadata panel (Up/Down / Wheel to scroll)
+  M             : Dump metadata to stdout (see --metadata-format)
+  g             : Toggle histogram
+  c             : Toggle clipping warning (highlights red, shadows blue)
   ?             : Toggle help overlay
   r / R         : Rotate 90° CCW / CW
   m             : Mark current file (write path to output)
+  x             : Retry loading current file after an error
+  F5            : Reload current file from disk
   z             : Toggle zoom (1:1 / Fit)
   + / - / Wheel : Zoom in / out
+  [ / ]         : Previous / next page (multi-page files)
   Home          : Go to first image
   End           : Go to last image
+Capital letters are Shift+key, whether or not Caps Lock is on.
 ";
 
 #[derive(Parser)]
 #[command(name = "iv", about = "A simple image viewer", after_help = HELP_KEYS)]
 pub struct Cli {
     /// Files or directories to view
-    #[arg(required_unless_present = "file_list")]
+    #[arg(required_unless_present_any = ["file_list", "undo"])]
     pub paths: Vec<std::path::PathBuf>,
 
     /// Load file list from a text file (one path per line)
@@ -36,10 +49,15 @@ pub struct Cli {
     #[arg(short = 'o', long, value_name = "FILE")]
     pub marked_file_output: Option<std::path::PathBuf>,
 
-    /// Memory budget for image cache (e.g. 512MB, 2GB). Default: 10% of RAM.
+    /// Memory budget for image cache (e.g. 512MB, 1.5GB, 2GiB, 25%). Default: 10% of RAM.
     #[arg(short, long)]
     pub memory: Option<String>,
 
+    /// Shrink the cache budget when system memory runs low, and grow it back
+    /// (up to --memory) once memory frees up
+    #[arg(long)]
+    pub adaptive_memory: bool,
+
     /// Recurse into subdirectories
     #[arg(short, long)]
     pub recursive: bool,
@@ -52,14 +70,147 @@ pub struct Cli {
     #[arg(short = 'D', long)]
     pub find_duplicates: bool,
 
-    /// Similarity threshold for duplicates (0-64, default: 2). Lower = stricter.
+    /// How close images must be to count as duplicates: a distance in bits
+    /// (0 up to the hash size, lower = stricter), or a minimum similarity
+    /// such as "95%" that scales with the hash size. Default: 2
     #[arg(long, default_value = "2")]
-    pub threshold: u32,
+    pub threshold: String,
+
+    /// Only find byte-identical copies (by size and content hash), without
+    /// decoding any image
+    #[arg(long)]
+    pub exact_only: bool,
+
+    /// How similar images are grouped: each joins the first group whose
+    /// first image is close enough, chains of matches form one group, or
+    /// every pair in a group must be close enough
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "representative")]
+    pub cluster: ClusterPolicy,
+
+    /// Which file of a duplicate group is the original: comma separated
+    /// rules, each breaking the ties of the ones before. resolution, size
+    /// (larger), format (RAW > lossless > lossy), exif, oldest, newest,
+    /// name, or under:DIR for files in DIR, e.g.
+    /// "under:/archive/master,format,resolution"
+    #[arg(long, value_name = "RULES", default_value = "resolution")]
+    pub keep: String,
+
+    /// Perceptual hash algorithm for finding duplicates
+    #[arg(long, value_enum, value_name = "ALG", default_value = "gradient")]
+    pub hash_alg: HashAlgorithm,
+
+    /// Hash grid size, as N or WxH (default: 8, i.e. 64 bits). Larger
+    /// hashes tell more images apart but take longer to compare
+    #[arg(long, value_name = "SIZE", default_value = "8")]
+    pub hash_size: String,
+
+    /// Filter used to shrink images before hashing; faster filters are
+    /// less precise
+    #[arg(long, value_enum, value_name = "FILTER", default_value = "lanczos3")]
+    pub hash_filter: HashFilter,
+
+    /// Run a DCT over the shrunk image before hashing (pHash-style; always
+    /// on with --hash-alg phash)
+    #[arg(long)]
+    pub hash_dct: bool,
+
+    /// Reduce the image to its edges (difference of Gaussians) before
+    /// hashing; meant for --hash-alg blockhash
+    #[arg(long)]
+    pub hash_diff_gauss: bool,
+
+    /// Also find rotated and mirrored copies: hash each image in all eight
+    /// orientations and match the closest. Slower, and loose thresholds
+    /// match more unrelated images
+    #[arg(long)]
+    pub hash_orientations: bool,
+
+    /// Hash every page/frame of multi-page files (TIFF, ICO, DDS) when
+    /// finding duplicates; files match if any of their pages do.
+    /// Default: first page only
+    #[arg(long)]
+    pub hash_all_pages: bool,
+
+    /// Keep perceptual hashes in this file between duplicate scans, so
+    /// unchanged files aren't decoded again and an interrupted scan resumes.
+    /// Default: $XDG_CACHE_HOME/iv/hashes.tsv (~/.cache/iv/hashes.tsv)
+    #[arg(long, value_name = "FILE")]
+    pub hash_cache: Option<std::path::PathBuf>,
+
+    /// Don't read or write the hash cache
+    #[arg(long, conflicts_with = "hash_cache")]
+    pub no_hash_cache: bool,
 
     /// Dump duplicates to the specified file and exit (requires -D)
     #[arg(long, value_name = "FILE")]
     pub dump: Option<std::path::PathBuf>,
 
+    /// Layout of the --dump report: the text listing, or JSON or CSV with
+    /// each file's size, dimensions, format, time and hash
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "text")]
+    pub dump_format: DumpFormat,
+
+    /// After --dump, do this with every duplicate that isn't kept (see
+    /// --keep). The plan is printed first; changed files are left alone
+    #[arg(long, value_enum, value_name = "ACTION", requires = "dump")]
+    pub action: Option<DuplicateAction>,
+
+    /// Directory --action move moves duplicates into, below their path in
+    /// the scanned directory
+    #[arg(long, value_name = "DIR")]
+    pub move_to: Option<std::path::PathBuf>,
+
+    /// Only print what --action or --undo would do
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Carry out --action without asking for confirmation; the plan is
+    /// still printed first. Needed when not run from a terminal
+    #[arg(long, requires = "action")]
+    pub yes: bool,
+
+    /// Where --action records its changes for --undo. Default: the --dump
+    /// file with ".undo" appended
+    #[arg(long, value_name = "FILE", requires = "action")]
+    pub undo_journal: Option<std::path::PathBuf>,
+
+    /// Revert the changes recorded in an --action undo journal and exit
+    #[arg(long, value_name = "FILE", conflicts_with = "action")]
+    pub undo: Option<std::path::PathBuf>,
+
+    /// Write the geotagged images as a GeoJSON FeatureCollection of points
+    /// to the specified file and exit
+    #[arg(long, value_name = "FILE")]
+    pub export_geojson: Option<std::path::PathBuf>,
+
+    /// Browse images grouped by where they were taken (GPS), e.g. by trip.
+    /// Groups come in time order; images without GPS go last
+    #[arg(long)]
+    pub group_by_location: bool,
+
+    /// Distance that puts two images in the same location group, in km
+    /// (default: 10)
+    #[arg(long, value_name = "KM", default_value = "10")]
+    pub location_radius: f64,
+
+    /// Number of decode worker threads. Default: CPU count, clamped to 4-16.
+    #[arg(long, value_name = "N")]
+    pub threads: Option<usize>,
+
+    /// Run decode workers at lower CPU and I/O priority (Linux only)
+    #[arg(long)]
+    pub low_priority: bool,
+
+    /// Vary the number of busy decode workers (up to --threads) depending on
+    /// whether decoding is CPU-bound or waiting on the disk
+    #[arg(long)]
+    pub adaptive_threads: bool,
+
+    /// Read metadata (EXIF/XMP/IPTC) for every file in the list in the
+    /// background once images are loaded, not just for the current one
+    #[arg(long)]
+    pub prefetch_metadata: bool,
+
     /// Initial delay in ms before key-hold repeat begins (default: 500)
     #[arg(long, default_value = "500")]
     pub initial_delay: u64,
@@ -71,21 +222,90 @@ pub struct Cli {
     /// Initial font size scaling factor (default: 2)
     #[arg(long, default_value = "2")]
     pub font_size: u32,
+
+    /// What the M key prints: the camera summary, every EXIF/XMP/IPTC
+    /// field, or every field as JSON (one object per line)
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "summary")]
+    pub metadata_format: MetadataFormat,
 }
 
-pub fn parse_memory_budget(s: &str) -> u64 {
-    let s = s.trim().to_uppercase();
-    if let Some(num) = s.strip_suffix("GB") {
-        num.trim().parse::<f64>().unwrap_or(1.0) as u64 * 1024 * 1024 * 1024
-    } else if let Some(num) = s.strip_suffix("MB") {
-        num.trim().parse::<f64>().unwrap_or(512.0) as u64 * 1024 * 1024
-    } else {
-        s.parse::<f64>().unwrap_or(512.0) as u64 * 1024 * 1024
+/// Size suffixes, longest first so "GB" wins over "B". Units are binary
+/// (1 GB = 1024 MB), with the IEC spellings accepted as synonyms.
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("TIB", 1 << 40),
+    ("GIB", 1 << 30),
+    ("MIB", 1 << 20),
+    ("KIB", 1 << 10),
+    ("TB", 1 << 40),
+    ("GB", 1 << 30),
+    ("MB", 1 << 20),
+    ("KB", 1 << 10),
+    ("T", 1 << 40),
+    ("G", 1 << 30),
+    ("M", 1 << 20),
+    ("K", 1 << 10),
+    ("B", 1),
+];
+
+/// Parse a memory budget: a size with optional unit ("1.5GB", "768 MiB";
+/// a bare number means MB) or a percentage of total RAM ("25%").
+pub fn parse_memory_budget(s: &str) -> Result<u64, String> {
+    let upper = s.trim().to_uppercase();
+
+    if let Some(pct) = upper.strip_suffix('%') {
+        let pct = pct.trim().parse::<f64>()
+            .map_err(|_| format!("invalid percentage '{}'", s))?;
+        if !(pct > 0.0 && pct <= 100.0) {
+            return Err(format!("percentage must be in (0, 100], got '{}'", s));
+        }
+        return Ok((total_memory() as f64 * pct / 100.0) as u64);
+    }
+
+    let (num, unit) = SIZE_UNITS.iter()
+        .find_map(|&(suffix, mult)| upper.strip_suffix(suffix).map(|n| (n, mult)))
+        .unwrap_or((upper.as_str(), 1 << 20));
+    let value = num.trim().parse::<f64>()
+        .map_err(|_| format!("invalid size '{}' (expected e.g. 512MB, 1.5GB, 25%)", s))?;
+    if !value.is_finite() || value <= 0.0 {
+        return Err(format!("size must be positive, got '{}'", s));
     }
+    Ok((value * unit as f64) as u64)
 }
 
-pub fn default_memory_budget() -> u64 {
+/// Parse a duplicate threshold: a bit distance ("4") or a similarity
+/// percentage ("95%").
+pub fn parse_threshold(s: &str) -> Result<Threshold, String> {
+    let s = s.trim();
+    if let Some(pct) = s.strip_suffix('%') {
+        let pct = pct.trim().parse::<f64>()
+            .map_err(|_| format!("invalid percentage '{}'", s))?;
+        if !(0.0..=100.0).contains(&pct) {
+            return Err(format!("similarity must be in [0, 100], got '{}'", s));
+        }
+        return Ok(Threshold::Similarity(pct));
+    }
+    s.parse::<u32>()
+        .map(Threshold::Bits)
+        .map_err(|_| format!("invalid threshold '{}' (expected bits, e.g. 4, or a similarity, e.g. 95%)", s))
+}
+
+/// Parse a hash size: "N" for a square grid or "WxH".
+pub fn parse_hash_size(s: &str) -> Result<(u32, u32), String> {
+    let lower = s.trim().to_lowercase();
+    let (w, h) = lower.split_once('x').unwrap_or((&lower, &lower));
+    let parse = |v: &str| v.trim().parse::<u32>().ok().filter(|n| (2..=64).contains(n));
+    match (parse(w), parse(h)) {
+        (Some(w), Some(h)) => Ok((w, h)),
+        _ => Err(format!("invalid hash size '{}' (expected N or WxH, each 2-64)", s)),
+    }
+}
+
+fn total_memory() -> u64 {
     let mut sys = sysinfo::System::new();
     sys.refresh_memory();
-    sys.total_memory() / 10
+    sys.total_memory()
+}
+
+pub fn default_memory_budget() -> u64 {
+    total_memory() / 10
 }
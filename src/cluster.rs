@@ -0,0 +1,362 @@
+use std::collections::HashSet;
+use image::metadata::Orientation;
+use image_hasher::ImageHash;
+
+use crate::hash_index::HashIndex;
+use crate::keep::Keeper;
+
+// ---------------------------------------------------------------------------
+// Grouping hashed files into duplicate clusters
+// ---------------------------------------------------------------------------
+//
+// Shared by the interactive scan and `--dump`, so both always group the same
+// files the same way. Files are added in scan order and identified by that
+// position; a cluster is identified by its first file.
+//
+// With `--hash-orientations` each page has eight hashes, one per entry of
+// `ORIENTATIONS`. Only the upright ones are indexed; a new file is looked up
+// with all eight, so it matches in whichever orientation is closest.
+
+/// The eight rotations and mirror images, in the order their hashes are
+/// stored.
+pub const ORIENTATIONS: [Orientation; 8] = [
+    Orientation::NoTransforms,
+    Orientation::Rotate90,
+    Orientation::Rotate180,
+    Orientation::Rotate270,
+    Orientation::FlipHorizontal,
+    Orientation::FlipVertical,
+    Orientation::Rotate90FlipH,
+    Orientation::Rotate270FlipH,
+];
+
+/// Name of a transform for the UI and reports.
+pub fn transform_name(transform: Orientation) -> &'static str {
+    match transform {
+        Orientation::NoTransforms => "none",
+        Orientation::Rotate90 => "rotate90",
+        Orientation::Rotate180 => "rotate180",
+        Orientation::Rotate270 => "rotate270",
+        Orientation::FlipHorizontal => "flip-horizontal",
+        Orientation::FlipVertical => "flip-vertical",
+        Orientation::Rotate90FlipH => "rotate90-flip-horizontal",
+        Orientation::Rotate270FlipH => "rotate270-flip-horizontal",
+    }
+}
+
+/// How files within the threshold of each other are grouped (`--cluster`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClusterPolicy {
+    /// Join the first cluster whose first file is within the threshold
+    #[default]
+    Representative,
+    /// Connected components: files chained by matches (A~B, B~C) group
+    /// together, however far apart the ends are
+    Connected,
+    /// Complete linkage: join the first cluster every member of which is
+    /// within the threshold
+    Complete,
+}
+
+impl ClusterPolicy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Representative => "representative",
+            Self::Connected => "connected",
+            Self::Complete => "complete",
+        }
+    }
+}
+
+/// Distance between two files, the closest pair of pages, and the transform
+/// that turns `original` into `copy`. `stride` is the number of hashes per
+/// page: 1, or 8 with all orientations.
+fn best_match(original: &[ImageHash], copy: &[ImageHash], stride: usize) -> (u32, Orientation) {
+    original
+        .iter()
+        .enumerate()
+        .flat_map(|(i, x)| copy.iter().step_by(stride).map(move |y| (x.dist(y), ORIENTATIONS[i % stride])))
+        // The first of equally close pairs, so upright wins ties
+        .min_by_key(|&(d, _)| d)
+        .unwrap_or((u32::MAX, Orientation::NoTransforms))
+}
+
+struct Item {
+    /// Empty if the file wasn't hashed; it then only groups with its copies.
+    hashes: Vec<ImageHash>,
+    pixels: u64,
+    /// First item with the same bytes (itself unless a copy).
+    contents: usize,
+}
+
+/// A cluster of more than one file.
+pub struct Group {
+    /// The file `--keep` prefers.
+    pub original: usize,
+    /// The rest, in scan order.
+    pub duplicates: Vec<Duplicate>,
+}
+
+#[derive(Clone)]
+pub struct Duplicate {
+    pub item: usize,
+    /// Distance to the original.
+    pub distance: u32,
+    /// Byte-identical to the original.
+    pub exact: bool,
+    /// How the original is turned to match, if not upright.
+    pub transform: Orientation,
+}
+
+pub struct Clusterer {
+    policy: ClusterPolicy,
+    /// Hashes per page.
+    stride: usize,
+    /// Representatives' hashes, or every item's for the other policies.
+    index: HashIndex,
+    items: Vec<Item>,
+    /// Union-find parents; a cluster's root is its first item.
+    parent: Vec<usize>,
+    /// Each cluster's items, kept at its root.
+    members: Vec<Vec<usize>>,
+}
+
+impl Clusterer {
+    pub fn new(policy: ClusterPolicy, hash_bits: u32, threshold: u32, orientations: bool) -> Self {
+        Self {
+            policy,
+            stride: if orientations { ORIENTATIONS.len() } else { 1 },
+            index: HashIndex::new(hash_bits, threshold),
+            items: Vec::new(),
+            parent: Vec::new(),
+            members: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, item: Item) -> usize {
+        let id = self.items.len();
+        self.items.push(item);
+        self.parent.push(id);
+        self.members.push(vec![id]);
+        id
+    }
+
+    /// The cluster `item` is in.
+    pub fn cluster_of(&mut self, mut item: usize) -> usize {
+        while self.parent[item] != item {
+            self.parent[item] = self.parent[self.parent[item]];
+            item = self.parent[item];
+        }
+        item
+    }
+
+    /// Merge two clusters under the earlier root.
+    fn union(&mut self, a: usize, b: usize) -> usize {
+        let (a, b) = (self.cluster_of(a), self.cluster_of(b));
+        if a == b {
+            return a;
+        }
+        let (keep, gone) = (a.min(b), a.max(b));
+        self.parent[gone] = keep;
+        let moved = std::mem::take(&mut self.members[gone]);
+        self.members[keep].extend(moved);
+        keep
+    }
+
+    /// Add the next file in scan order, with its page hashes (empty if it
+    /// couldn't be hashed) and first-page size. Returns its cluster.
+    /// Matches are found for any of its orientations, against the others
+    /// upright.
+    pub fn add(&mut self, hashes: Vec<ImageHash>, width: u32, height: u32) -> usize {
+        let id = self.items.len();
+        let mut cluster = self.push(Item {
+            hashes: Vec::new(),
+            pixels: width as u64 * height as u64,
+            contents: id,
+        });
+        if hashes.is_empty() {
+            return cluster;
+        }
+
+        match self.policy {
+            ClusterPolicy::Representative => match self.index.first_match(&hashes) {
+                Some((rep, _)) => cluster = self.union(rep, id),
+                None => {
+                    for hash in hashes.iter().step_by(self.stride) {
+                        self.index.insert(hash.clone(), id);
+                    }
+                }
+            },
+            ClusterPolicy::Connected => {
+                let mut matches = Vec::new();
+                self.index.for_each_match(&hashes, |item, _| matches.push(item));
+                for item in matches {
+                    cluster = self.union(item, id);
+                }
+            }
+            ClusterPolicy::Complete => {
+                let mut neighbours = HashSet::new();
+                self.index.for_each_match(&hashes, |item, _| {
+                    neighbours.insert(item);
+                });
+                let mut candidates: Vec<usize> = neighbours.iter().map(|&item| self.cluster_of(item)).collect();
+                candidates.sort_unstable();
+                candidates.dedup();
+                if let Some(&joined) = candidates
+                    .iter()
+                    .find(|&&c| self.members[c].iter().all(|m| neighbours.contains(m)))
+                {
+                    cluster = self.union(joined, id);
+                }
+            }
+        }
+        if self.policy != ClusterPolicy::Representative {
+            for hash in hashes.iter().step_by(self.stride) {
+                self.index.insert(hash.clone(), id);
+            }
+        }
+        self.items[id].hashes = hashes;
+        cluster
+    }
+
+    /// Add the next file in scan order as a byte-identical copy of the
+    /// earlier item `source`, joining its cluster without comparing hashes.
+    pub fn add_copy(&mut self, source: usize) -> usize {
+        let item = Item {
+            hashes: self.items[source].hashes.clone(),
+            pixels: self.items[source].pixels,
+            contents: self.items[source].contents,
+        };
+        let id = self.push(item);
+        // Complete linkage needs every member to turn up as a neighbour of
+        // later files, copies included
+        if self.policy != ClusterPolicy::Representative {
+            for hash in self.items[id].hashes.iter().step_by(self.stride) {
+                self.index.insert(hash.clone(), id);
+            }
+        }
+        self.union(source, id)
+    }
+
+    /// `cluster` with its original picked by `keeper`, or None if it's a
+    /// single file.
+    pub fn group(&mut self, cluster: usize, keeper: &mut Keeper) -> Option<Group> {
+        let cluster = self.cluster_of(cluster);
+        let members = &self.members[cluster];
+        if members.len() < 2 {
+            return None;
+        }
+        let (items, stride) = (&self.items, self.stride);
+        let original = keeper.pick(members, |m| items[m].pixels);
+        let mut duplicates: Vec<Duplicate> = members
+            .iter()
+            .filter(|&&m| m != original)
+            .map(|&m| {
+                let exact = items[m].contents == items[original].contents;
+                let (distance, transform) = if exact {
+                    (0, Orientation::NoTransforms)
+                } else {
+                    best_match(&items[original].hashes, &items[m].hashes, stride)
+                };
+                Duplicate { item: m, distance, exact, transform }
+            })
+            .collect();
+        duplicates.sort_by_key(|d| d.item);
+        Some(Group { original, duplicates })
+    }
+
+    /// Every cluster of more than one file, in order of their first file.
+    pub fn groups(&mut self, keeper: &mut Keeper) -> Vec<Group> {
+        let roots: Vec<usize> = (0..self.items.len()).filter(|&i| self.parent[i] == i).collect();
+        roots.into_iter().filter_map(|root| self.group(root, keeper)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keep::KeepPolicy;
+    use std::path::PathBuf;
+
+    fn hash(bits: u64) -> Vec<ImageHash> {
+        vec![ImageHash::from_bytes(&bits.to_le_bytes()).unwrap()]
+    }
+
+    /// Cluster `files` (each a hash, or a copy of an earlier file) with a
+    /// threshold of 2 bits; returns each group's members, original first.
+    fn cluster(policy: ClusterPolicy, files: &[Result<u64, usize>]) -> Vec<Vec<usize>> {
+        let mut clusterer = Clusterer::new(policy, 64, 2, false);
+        for file in files {
+            match *file {
+                Ok(bits) => clusterer.add(hash(bits), 100, 100),
+                Err(source) => clusterer.add_copy(source),
+            };
+        }
+        let paths: Vec<PathBuf> = (0..files.len()).map(|i| PathBuf::from(format!("{}.png", i))).collect();
+        let policy = KeepPolicy::default();
+        let mut keeper = Keeper::new(&policy, &paths);
+        clusterer
+            .groups(&mut keeper)
+            .into_iter()
+            .map(|g| std::iter::once(g.original).chain(g.duplicates.iter().map(|d| d.item)).collect())
+            .collect()
+    }
+
+    const POLICIES: [ClusterPolicy; 3] = [
+        ClusterPolicy::Representative,
+        ClusterPolicy::Connected,
+        ClusterPolicy::Complete,
+    ];
+
+    #[test]
+    fn copies_and_near_matches_join_under_every_policy() {
+        for policy in POLICIES {
+            // A, an exact copy of A, then a file one bit from A
+            let groups = cluster(policy, &[Ok(0), Err(0), Ok(1)]);
+            assert_eq!(groups, vec![vec![0, 1, 2]], "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn distant_files_stay_apart() {
+        for policy in POLICIES {
+            let groups = cluster(policy, &[Ok(0), Ok(u64::MAX), Ok(1)]);
+            assert_eq!(groups, vec![vec![0, 2]], "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn chains_depend_on_the_policy() {
+        // 0b11 is within 2 bits of both ends; the ends are 4 bits apart
+        let chain = [Ok(0b0000), Ok(0b0011), Ok(0b1111)];
+        assert_eq!(cluster(ClusterPolicy::Representative, &chain), vec![vec![0, 1]]);
+        assert_eq!(cluster(ClusterPolicy::Connected, &chain), vec![vec![0, 1, 2]]);
+        assert_eq!(cluster(ClusterPolicy::Complete, &chain), vec![vec![0, 1]]);
+    }
+
+    #[test]
+    fn copies_are_exact_and_matches_report_their_distance() {
+        let mut clusterer = Clusterer::new(ClusterPolicy::Complete, 64, 2, false);
+        clusterer.add(hash(0), 100, 100);
+        clusterer.add_copy(0);
+        clusterer.add(hash(0b11), 100, 100);
+        let paths: Vec<PathBuf> = (0..3).map(|i| PathBuf::from(i.to_string())).collect();
+        let policy = KeepPolicy::default();
+        let group = clusterer.group(0, &mut Keeper::new(&policy, &paths)).unwrap();
+        let found: Vec<(usize, u32, bool)> = group.duplicates.iter().map(|d| (d.item, d.distance, d.exact)).collect();
+        assert_eq!(group.original, 0);
+        assert_eq!(found, vec![(1, 0, true), (2, 2, false)]);
+    }
+
+    #[test]
+    fn best_match_finds_the_turned_copy() {
+        // Upright hash of a file, and of the same file in other orientations
+        let mut original = Vec::new();
+        for i in 0..ORIENTATIONS.len() as u64 {
+            original.extend(hash(i << 8));
+        }
+        let copy = hash(1 << 8);
+        assert_eq!(best_match(&original, &copy, ORIENTATIONS.len()), (0, Orientation::Rotate90));
+        assert_eq!(best_match(&original[..1], &original[..1], 1), (0, Orientation::NoTransforms));
+    }
+}
@@ -1,151 +1,445 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 use std::thread;
 use std::time::Instant;
 use winit::event_loop::EventLoopProxy;
-use image_hasher::{HasherConfig, ImageHash};
-use image::ImageReader;
+use image::metadata::Orientation;
+use image_hasher::{FilterType, HashAlg, HasherConfig, ImageHash};
 use rayon::prelude::*;
 
-use crate::files::is_image_file;
+use crate::actions::{run_action, ActionOptions};
+use crate::cluster::{ClusterPolicy, Clusterer, ORIENTATIONS};
+use crate::keep::{KeepPolicy, Keeper};
+use crate::files::{is_image_file, missing_feature, read_file_list};
+use crate::hash_cache::{file_stamp, HashCache};
+use crate::loader::for_each_page;
 use crate::loader::UserEvent;
+use crate::report::{write_report, DumpFormat, Report, Timings};
 
 #[derive(Clone, Debug)]
 pub struct DuplicateInfo {
     pub original_path: PathBuf,
     pub distance: u32,
     pub is_original: bool,
+    /// Byte-identical to the original, found without decoding.
+    pub exact: bool,
+    /// How the original is rotated or mirrored to match (see
+    /// `--hash-orientations`).
+    pub transform: Orientation,
 }
 
-struct SeenImage {
-    path: PathBuf,
-    hash: ImageHash,
+/// Perceptual hash algorithm for duplicate scans (`--hash-alg`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum HashAlgorithm {
+    /// Pixels above the mean (aHash); fastest, least robust
+    Mean,
+    /// Pixels above the median
+    Median,
+    /// Median of the DCT's low frequencies (pHash); most robust to edits, slower
+    Phash,
+    /// Brightness changes along rows (dHash)
+    #[default]
+    Gradient,
+    /// Brightness changes along columns
+    VertGradient,
+    /// Brightness changes along rows and columns, at half resolution
+    DoubleGradient,
+    /// Blockhash.io; no resizing, so fast on small images
+    Blockhash,
+}
+
+impl HashAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mean => "mean",
+            Self::Median => "median",
+            Self::Phash => "phash",
+            Self::Gradient => "gradient",
+            Self::VertGradient => "vert-gradient",
+            Self::DoubleGradient => "double-gradient",
+            Self::Blockhash => "blockhash",
+        }
+    }
+}
+
+/// Filter used to shrink images before hashing (`--hash-filter`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum HashFilter {
+    /// Fastest, noisiest
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    /// Sharpest, slowest
+    #[default]
+    Lanczos3,
+}
+
+impl HashFilter {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Nearest => "nearest",
+            Self::Triangle => "triangle",
+            Self::CatmullRom => "catmull-rom",
+            Self::Gaussian => "gaussian",
+            Self::Lanczos3 => "lanczos3",
+        }
+    }
+}
+
+/// How different two files may be and still count as duplicates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threshold {
+    /// Hamming distance in bits
+    Bits(u32),
+    /// Minimum share of equal bits, in percent
+    Similarity(f64),
+}
+
+/// Settings shared by the interactive and headless duplicate scans.
+#[derive(Clone, Debug)]
+pub struct DedupeOptions {
+    pub threshold: Threshold,
+    /// Hash every page/frame of multi-page files, not just the first.
+    pub all_pages: bool,
+    /// Only report byte-identical files; nothing is decoded.
+    pub exact_only: bool,
+    pub cluster: ClusterPolicy,
+    /// Which file of a group is the original.
+    pub keep: KeepPolicy,
+    pub hash_alg: HashAlgorithm,
+    /// Hash grid width and height; more bits tell more images apart.
+    pub hash_size: (u32, u32),
+    pub hash_filter: HashFilter,
+    /// DCT preprocessing (implied by pHash).
+    pub dct: bool,
+    /// Difference-of-Gaussians preprocessing, meant for blockhash.
+    pub diff_gauss: bool,
+    /// Hash all eight rotations and mirror images, to match turned copies.
+    pub orientations: bool,
+    /// Where hashes are kept between runs; `None` disables the cache.
+    pub cache_path: Option<PathBuf>,
+}
+
+impl DedupeOptions {
+    fn hasher_config(&self) -> HasherConfig {
+        let alg = match self.hash_alg {
+            HashAlgorithm::Mean => HashAlg::Mean,
+            HashAlgorithm::Median | HashAlgorithm::Phash => HashAlg::Median,
+            HashAlgorithm::Gradient => HashAlg::Gradient,
+            HashAlgorithm::VertGradient => HashAlg::VertGradient,
+            HashAlgorithm::DoubleGradient => HashAlg::DoubleGradient,
+            HashAlgorithm::Blockhash => HashAlg::Blockhash,
+        };
+        let filter = match self.hash_filter {
+            HashFilter::Nearest => FilterType::Nearest,
+            HashFilter::Triangle => FilterType::Triangle,
+            HashFilter::CatmullRom => FilterType::CatmullRom,
+            HashFilter::Gaussian => FilterType::Gaussian,
+            HashFilter::Lanczos3 => FilterType::Lanczos3,
+        };
+        let mut config = HasherConfig::new()
+            .hash_alg(alg)
+            .hash_size(self.hash_size.0, self.hash_size.1)
+            .resize_filter(filter);
+        if self.uses_dct() {
+            config = config.preproc_dct();
+        }
+        if self.diff_gauss {
+            config = config.preproc_diff_gauss();
+        }
+        config
+    }
+
+    pub fn uses_dct(&self) -> bool {
+        self.dct || self.hash_alg == HashAlgorithm::Phash
+    }
+
+    /// Bits per hash, which is the largest possible distance.
+    pub fn hash_bits(&self) -> u32 {
+        let (w, h) = self.hash_size;
+        match self.hash_alg {
+            HashAlgorithm::DoubleGradient => {
+                // Rounded up to even, then compared at half size both ways
+                let (w, h) = ((w + 1) & !1, (h + 1) & !1);
+                (h / 2 + 1) * (w / 2) + (w / 2 + 1) * (h / 2)
+            }
+            HashAlgorithm::Blockhash => ((w + 3) & !3) * ((h + 3) & !3),
+            _ => w * h,
+        }
+    }
+
+    /// The threshold as a Hamming distance.
+    pub fn max_distance(&self) -> u32 {
+        match self.threshold {
+            Threshold::Bits(bits) => bits,
+            Threshold::Similarity(pct) => ((100.0 - pct) / 100.0 * self.hash_bits() as f64).floor() as u32,
+        }
+    }
+
+    /// Hash settings for logs and the dump header, e.g.
+    /// "gradient 8x8 (64 bits), lanczos3".
+    pub fn describe_hash(&self) -> String {
+        let mut s = format!(
+            "{} {}x{} ({} bits)",
+            self.hash_alg.name(),
+            self.hash_size.0,
+            self.hash_size.1,
+            self.hash_bits()
+        );
+        // Blockhash doesn't resize
+        if self.hash_alg != HashAlgorithm::Blockhash {
+            s.push_str(", ");
+            s.push_str(self.hash_filter.name());
+        }
+        if self.dct && self.hash_alg != HashAlgorithm::Phash {
+            s.push_str(", dct");
+        }
+        if self.diff_gauss {
+            s.push_str(", diff-gauss");
+        }
+        if self.orientations {
+            s.push_str(", all orientations");
+        }
+        s
+    }
+
+    /// The threshold in bits and as a similarity, e.g. "2 bits (97% similar)".
+    pub fn describe_threshold(&self) -> String {
+        let bits = self.max_distance();
+        let similarity = 100.0 * (1.0 - bits as f64 / self.hash_bits() as f64);
+        format!("{} bits ({:.0}% similar)", bits, similarity)
+    }
+
+    /// Everything that changes what a file hashes to, so cached hashes
+    /// made with other settings aren't reused.
+    fn cache_key(&self) -> String {
+        format!(
+            "{}-{}x{}-{}{}{}{}-{}",
+            self.hash_alg.name(),
+            self.hash_size.0,
+            self.hash_size.1,
+            self.hash_filter.name(),
+            if self.uses_dct() { "-dct" } else { "" },
+            if self.diff_gauss { "-dog" } else { "" },
+            if self.orientations { "-dihedral" } else { "" },
+            if self.all_pages { "all" } else { "first" }
+        )
+    }
+
+    fn open_cache(&self, roots: &[PathBuf]) -> Option<HashCache> {
+        let path = self.cache_path.clone()?;
+        Some(HashCache::open(path, self.cache_key(), roots))
+    }
+}
+
+/// Largest side images are shrunk to before hashing all orientations.
+const ORIENTED_SIDE: u32 = 256;
+
+/// Hash the first page of `path`, or all of them; with `orientations`, each
+/// page in every entry of `ORIENTATIONS`.
+fn hash_pages(
+    path: &Path,
+    all_pages: bool,
+    orientations: bool,
+    hasher: &image_hasher::Hasher,
+) -> Option<(Vec<ImageHash>, u32, u32)> {
+    let mut hashes = Vec::new();
+    let mut size = (0, 0);
+    for_each_page(path, all_pages, |img| {
+        if hashes.is_empty() {
+            size = (img.width(), img.height());
+        }
+        if orientations {
+            // Shrinking to a square commutes with turning, so one small copy
+            // serves all eight instead of turning the full image
+            let side = ORIENTED_SIDE.min(img.width().max(img.height())).max(1);
+            let small = img.resize_exact(side, side, image::imageops::FilterType::Triangle);
+            for &orientation in &ORIENTATIONS {
+                let mut turned = small.clone();
+                turned.apply_orientation(orientation);
+                hashes.push(hasher.hash_image(&turned));
+            }
+        } else {
+            hashes.push(hasher.hash_image(&img));
+        }
+    })
+    .ok()?;
+    Some((hashes, size.0, size.1))
+}
+
+/// `hash_pages`, going through the cache if there is one.
+fn hash_file(
+    path: &Path,
+    all_pages: bool,
+    orientations: bool,
+    hasher_config: &HasherConfig,
+    cache: Option<&HashCache>,
+) -> Option<(Vec<ImageHash>, u32, u32)> {
+    // Stamped before reading, so an edit during hashing isn't cached as
+    // matching the old contents
+    let cached = cache.zip(file_stamp(path));
+    if let Some(hit) = cached.and_then(|(c, stamp)| c.get(path, stamp)) {
+        return Some(hit);
+    }
+    let (hashes, width, height) = hash_pages(path, all_pages, orientations, &hasher_config.to_hasher())?;
+    if let Some((cache, stamp)) = cached {
+        cache.put(path, stamp, &hashes, width, height);
+    }
+    Some((hashes, width, height))
+}
+
+/// Content digest of a whole file.
+fn content_hash(path: &Path) -> Option<blake3::Hash> {
+    let file = fs::File::open(path).ok()?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(file).ok()?;
+    Some(hasher.finalize())
+}
+
+/// Find byte-identical files without decoding anything: for each file, the
+/// index of the first earlier file with the same contents. Files are only
+/// read if another one has the same size.
+fn find_exact_copies(files: &[PathBuf]) -> Vec<Option<usize>> {
+    let sizes: Vec<Option<u64>> = files
+        .par_iter()
+        .map(|path| fs::metadata(path).ok().map(|m| m.len()))
+        .collect();
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, size) in sizes.iter().enumerate() {
+        if let Some(size) = size {
+            by_size.entry(*size).or_default().push(i);
+        }
+    }
+
+    let mut candidates: Vec<usize> = by_size.into_values().filter(|g| g.len() > 1).flatten().collect();
+    candidates.sort_unstable();
+    let digests: Vec<Option<blake3::Hash>> = candidates.par_iter().map(|&i| content_hash(&files[i])).collect();
+
+    let mut first: HashMap<(u64, blake3::Hash), usize> = HashMap::new();
+    let mut copy_of = vec![None; files.len()];
+    for (&i, digest) in candidates.iter().zip(digests) {
+        let (Some(size), Some(digest)) = (sizes[i], digest) else { continue };
+        let first = *first.entry((size, digest)).or_insert(i);
+        if first != i {
+            copy_of[i] = Some(first);
+        }
+    }
+    copy_of
 }
 
 pub fn spawn_dedupe_scanner(
-    paths: Vec<PathBuf>,
-    recursive: bool,
-    follow_links: bool,
-    threshold: u32,
+    sources: ScanPaths,
+    options: DedupeOptions,
     files_arc: Arc<RwLock<Vec<PathBuf>>>,
     dupe_info_arc: Arc<RwLock<HashMap<PathBuf, DuplicateInfo>>>,
     proxy: EventLoopProxy<UserEvent>,
 ) {
+    let threshold = options.max_distance();
     thread::spawn(move || {
-        log::info!("Starting background duplicate scan (threshold: {})...", threshold);
+        log::info!(
+            "Starting background duplicate scan (hash: {}, threshold: {}, clustering: {})...",
+            options.describe_hash(),
+            options.describe_threshold(),
+            options.cluster.name()
+        );
         let start_time = Instant::now();
         
-        // We will collect all files first, then process them.
-        let mut all_files = Vec::new();
-        for path in paths {
-            if path.is_dir() {
-                collect_files(&path, recursive, follow_links, &mut all_files);
-            } else if path.is_file() {
-                if is_image_file(&path) {
-                     all_files.push(path);
-                }
-            }
-        }
-        
-        // Sort files to ensure deterministic order (alphabetical)
-        // This ensures the "original" is always the first one alphabetically.
-        all_files.sort();
+        // We will collect all files first, then process them. Sorted, so
+        // ties the keep policy leaves go to the first one alphabetically.
+        let all_files = collect_images(&sources)
+            .unwrap_or_else(|e| {
+                log::error!("Reading the file list: {}", e);
+                Vec::new()
+            });
         
-        log::info!("Found {} candidates. Hashing and comparing...", all_files.len());
+        log::info!("Found {} candidates. Looking for byte-identical copies...", all_files.len());
+        let copy_of = find_exact_copies(&all_files);
+        log::info!(
+            "{} byte-identical copies. Hashing and comparing...",
+            copy_of.iter().flatten().count()
+        );
         
-        let hasher_config = HasherConfig::new(); // immutable config
-        let mut seen: Vec<SeenImage> = Vec::new();
+        let hasher_config = options.hasher_config(); // immutable config
+        let cache = options.open_cache(&sources.paths);
+        let mut clusterer = Clusterer::new(options.cluster, options.hash_bits(), threshold, options.orientations);
+        let mut keeper = Keeper::new(&options.keep, &all_files);
         let mut displayed_count = 0;
         
-        // We keep track of which "seen" images have already been "exposed" to the UI.
-        let mut exposed_indices: Vec<bool> = Vec::new();
+        // Which files are already in the UI's list
+        let mut published: Vec<bool> = vec![false; all_files.len()];
         
         // Process in chunks to allow progressive UI updates while using parallelism
         let chunk_size = 100;
         
-        for chunk in all_files.chunks(chunk_size) {
+        for (chunk_index, chunk) in all_files.chunks(chunk_size).enumerate() {
+            let chunk_start = chunk_index * chunk_size;
+
             // 1. Parallel Load & Hash
             // We use rayon to process this chunk in parallel.
             // The order is preserved in the output vector.
-            let results: Vec<Option<ImageHash>> = chunk.par_iter()
-                .map(|path| {
-                    let hasher = hasher_config.to_hasher();
-                    match ImageReader::open(path) {
-                        Ok(reader) => match reader.decode() {
-                            Ok(img) => Some(hasher.hash_image(&img)),
-                            Err(_) => None, 
-                        },
-                        Err(_) => None,
+            // Exact copies are settled by their first file instead.
+            let results: Vec<Option<(Vec<ImageHash>, u32, u32)>> = chunk.par_iter()
+                .enumerate()
+                .map(|(i, path)| {
+                    if options.exact_only || copy_of[chunk_start + i].is_some() {
+                        return None;
                     }
+                    hash_file(path, options.all_pages, options.orientations, &hasher_config, cache.as_ref())
                 })
                 .collect();
 
-            // 2. Serial Deduplication
-            // We must process comparison sequentially to maintain deterministic "original" detection.
-            let mut chunk_updates = Vec::new();
-            let mut info_updates = Vec::new();
-
-            for (i, hash_opt) in results.into_iter().enumerate() {
-                let path = &chunk[i];
-                let hash = if let Some(h) = hash_opt {
-                    h
-                } else {
-                    continue;
+            // 2. Serial Clustering
+            // Files must be added in order, the same as `--dump` does, so both group alike.
+            let mut touched = Vec::new();
+            for (i, result) in results.into_iter().enumerate() {
+                let cluster = match (copy_of[chunk_start + i], result) {
+                    (Some(source), _) => clusterer.add_copy(source),
+                    (None, Some((hashes, width, height))) => clusterer.add(hashes, width, height),
+                    (None, None) => clusterer.add(Vec::new(), 0, 0),
                 };
+                touched.push(cluster);
+            }
 
-                let mut found_match = false;
-                let mut match_index = 0;
-                let mut dist = 0;
-                
-                // Compare against all previously seen images
-                for (idx, seen_img) in seen.iter().enumerate() {
-                    let d = hash.dist(&seen_img.hash);
-                    if d <= threshold {
-                        found_match = true;
-                        match_index = idx;
-                        dist = d;
-                        break;
-                    }
-                }
-                
-                if found_match {
-                    // It's a duplicate of seen[match_index]
-                    let original = &seen[match_index];
-                    
-                    // If the "original" hasn't been shown yet, show it now
-                    if !exposed_indices[match_index] {
-                        chunk_updates.push(original.path.clone());
-                        info_updates.push((original.path.clone(), DuplicateInfo {
-                            original_path: original.path.clone(),
-                            distance: 0,
-                            is_original: true,
-                        }));
-                        exposed_indices[match_index] = true;
+            // 3. Batch Update UI
+            // Clusters that grew get all their entries rewritten: a merge
+            // or a larger file can change the original and the distances
+            let mut chunk_updates = Vec::new();
+            let mut info_updates = Vec::new();
+            let mut clusters: Vec<usize> = touched.into_iter().map(|c| clusterer.cluster_of(c)).collect();
+            clusters.sort_unstable();
+            clusters.dedup();
+            for cluster in clusters {
+                let Some(group) = clusterer.group(cluster, &mut keeper) else { continue };
+                let original_path = &all_files[group.original];
+                let entries = std::iter::once((group.original, DuplicateInfo {
+                    original_path: original_path.clone(),
+                    distance: 0,
+                    is_original: true,
+                    exact: false,
+                    transform: Orientation::NoTransforms,
+                }))
+                .chain(group.duplicates.iter().map(|d| (d.item, DuplicateInfo {
+                    original_path: original_path.clone(),
+                    distance: d.distance,
+                    is_original: false,
+                    exact: d.exact,
+                    transform: d.transform,
+                })));
+                for (item, info) in entries {
+                    if !published[item] {
+                        chunk_updates.push(all_files[item].clone());
+                        published[item] = true;
                     }
-                    
-                    // Show the current duplicate
-                    chunk_updates.push(path.clone());
-                    info_updates.push((path.clone(), DuplicateInfo {
-                        original_path: original.path.clone(),
-                        distance: dist,
-                        is_original: false,
-                    }));
-                    
-                } else {
-                    // New unique image
-                    seen.push(SeenImage {
-                        path: path.clone(),
-                        hash,
-                    });
-                    exposed_indices.push(false);
+                    info_updates.push((all_files[item].clone(), info));
                 }
             }
-            
-            // 3. Batch Update UI
-            if !chunk_updates.is_empty() {
+
+            if !info_updates.is_empty() {
                 // Update duplicate info map first
                 let mut info_guard = dupe_info_arc.write().unwrap();
                 for (p, info) in info_updates {
@@ -162,46 +456,49 @@ pub fn spawn_dedupe_scanner(
             }
         }
 
+        if let Some(cache) = cache {
+            cache.finish();
+        }
+
         log::info!(
             "Dedupe scan complete in {:.2}s. Found {} duplicates among {} files.",
             start_time.elapsed().as_secs_f64(),
             displayed_count,
-            seen.len()
+            all_files.len()
         );
     });
 }
 
-#[derive(Debug)]
-struct ScannedImage {
-    path: PathBuf,
-    hash: ImageHash,
-    width: u32,
-    height: u32,
-}
-
 pub fn run_headless_dedupe(
-    paths: Vec<PathBuf>,
-    recursive: bool,
-    follow_links: bool,
-    threshold: u32,
+    sources: ScanPaths,
+    options: DedupeOptions,
     output_path: PathBuf,
+    format: DumpFormat,
+    action: Option<ActionOptions>,
 ) {
-    let mut all_files = Vec::new();
-    for path in &paths {
-        if path.is_dir() {
-            collect_files(path, recursive, follow_links, &mut all_files);
-        } else if path.is_file() {
-            if is_image_file(path) {
-                all_files.push(path.clone());
-            }
-        }
-    }
-    
-    // Sort for deterministic behavior
-    all_files.sort();
+    let threshold = options.max_distance();
+    let started = chrono::Local::now();
+    let mut timings = Timings::default();
+    let mut stage = Instant::now();
+    let all_files = collect_images(&sources).unwrap_or_else(|e| {
+        eprintln!("Error: reading the file list: {}", e);
+        std::process::exit(1);
+    });
 
     let total_files = all_files.len();
-    eprintln!("Found {} candidates. Hashing...", total_files);
+    timings.collect = stage.elapsed();
+    stage = Instant::now();
+    eprintln!("Found {} candidates. Looking for byte-identical copies...", total_files);
+    // Actions leave files alone that changed after this
+    let stamps: Vec<Option<(u64, u128)>> = match action {
+        Some(_) => all_files.par_iter().map(|path| file_stamp(path)).collect(),
+        None => Vec::new(),
+    };
+    let copy_of = find_exact_copies(&all_files);
+    let exact_copies = copy_of.iter().flatten().count();
+    timings.exact = stage.elapsed();
+    stage = Instant::now();
+    eprintln!("Found {} byte-identical copies. Hashing...", exact_copies);
 
     let counter = Arc::new(AtomicUsize::new(0));
     let stop_signal = Arc::new(AtomicBool::new(false));
@@ -223,111 +520,130 @@ pub fn run_headless_dedupe(
         eprintln!("\rScanning: {} / {} - Done.", ticker_counter.load(Ordering::Relaxed), total_files);
     });
 
-    let hasher_config = HasherConfig::new();
-    let scanned: Vec<ScannedImage> = all_files.par_iter()
-        .filter_map(|path| {
-            let res = {
-                let hasher = hasher_config.to_hasher();
-                 match ImageReader::open(path) {
-                    Ok(reader) => match reader.decode() {
-                        Ok(img) => {
-                            let hash = hasher.hash_image(&img);
-                            Some(ScannedImage {
-                                path: path.clone(),
-                                hash,
-                                width: img.width(),
-                                height: img.height(),
-                            })
-                        },
-                        Err(_) => None, 
-                    },
-                    Err(_) => None,
-                }
+    let hasher_config = options.hasher_config();
+    let cache = options.open_cache(&sources.paths);
+    let mut hashed: Vec<Option<(Vec<ImageHash>, u32, u32)>> = all_files.par_iter()
+        .enumerate()
+        .map(|(index, path)| {
+            // Copies are settled by the first file with their contents
+            let res = if options.exact_only || copy_of[index].is_some() {
+                None
+            } else {
+                hash_file(path, options.all_pages, options.orientations, &hasher_config, cache.as_ref())
             };
             counter.fetch_add(1, Ordering::Relaxed);
             res
         })
         .collect();
+    timings.hash = stage.elapsed();
     
     // Stop ticker
     stop_signal.store(true, Ordering::Relaxed);
     let _ = ticker_handle.join();
+    if let Some(cache) = cache {
+        cache.finish();
+    }
         
-    eprintln!("Hashed {} images. Clustering...", scanned.len());
+    let failures: Vec<usize> = (0..total_files)
+        .filter(|&i| !options.exact_only && copy_of[i].is_none() && hashed[i].is_none())
+        .collect();
+    stage = Instant::now();
+    eprintln!("Hashed {} images ({} failed). Clustering...", hashed.iter().flatten().count(), failures.len());
 
-    // Clustering
-    let mut clusters: Vec<Vec<ScannedImage>> = Vec::new();
-    
-    for img in scanned {
-        let mut match_index = None;
-        for (i, cluster) in clusters.iter().enumerate() {
-            // Compare with the first one (representative)
-            if img.hash.dist(&cluster[0].hash) <= threshold {
-                match_index = Some(i);
-                break;
-            }
-        }
-        
-        if let Some(i) = match_index {
-            clusters[i].push(img);
-        } else {
-            clusters.push(vec![img]);
+    // Clustering, in file order like the interactive scan
+    let mut clusterer = Clusterer::new(options.cluster, options.hash_bits(), threshold, options.orientations);
+    for (index, result) in hashed.iter().enumerate() {
+        match (copy_of[index], result) {
+            (Some(source), _) => clusterer.add_copy(source),
+            (None, Some((hashes, width, height))) => clusterer.add(hashes.clone(), *width, *height),
+            (None, None) => clusterer.add(Vec::new(), 0, 0),
+        };
+    }
+    let groups = clusterer.groups(&mut Keeper::new(&options.keep, &all_files));
+    timings.cluster = stage.elapsed();
+
+    // Copies are reported with their source's hash and size
+    for (index, source) in copy_of.iter().enumerate() {
+        if let Some(source) = *source {
+            hashed[index] = hashed[source].clone();
         }
     }
     
-    eprintln!("Found {} clusters. Writing output to {}...", clusters.len(), output_path.display());
-
-    // Post-process and write to file
-    use std::io::Write;
-    let mut file = match fs::File::create(&output_path) {
-        Ok(f) => f,
-        Err(e) => {
-            eprintln!("Error creating output file: {}", e);
-            return;
-        }
+    eprintln!("Found {} clusters. Writing output to {}...", groups.len(), output_path.display());
+
+    let report = Report {
+        started,
+        roots: &sources.paths,
+        options: &options,
+        files: &all_files,
+        hashed: &hashed,
+        exact_copies,
+        failures: &failures,
+        groups: &groups,
+        timings,
     };
+    let result = write_report(&output_path, &report, format);
+    if let Err(e) = result {
+        eprintln!("Error: writing {}: {}", output_path.display(), e);
+        std::process::exit(1);
+    }
+    eprintln!("Done.");
 
-    // Header
-    let now = chrono::Local::now();
-    writeln!(file, "Duplicate Scan Report").unwrap();
-    writeln!(file, "Time: {}", now.format("%Y-%m-%d %H:%M:%S")).unwrap();
-    writeln!(file, "Scanned Directories:").unwrap();
-    for p in &paths {
-        writeln!(file, "  - {}", p.display()).unwrap();
+    if let Some(action) = action {
+        run_action(&action, &groups, &all_files, &stamps, &sources.paths);
     }
-    writeln!(file, "Total Files Scanned: {}", total_files).unwrap();
-    writeln!(file, "Threshold: {}", threshold).unwrap();
-    writeln!(file, "--------------------------------------------------").unwrap();
-
-    for mut cluster in clusters {
-        // Only interested in duplicates (cluster size > 1)
-        if cluster.len() > 1 {
-            // Find best original: max pixels, then alphabetical path
-            // We want to sort such that index 0 is the best.
-            cluster.sort_by(|a, b| {
-                let pixels_a = a.width as u64 * a.height as u64;
-                let pixels_b = b.width as u64 * b.height as u64;
-                
-                if pixels_a != pixels_b {
-                    return pixels_b.cmp(&pixels_a); // Descending resolution
-                }
-                a.path.cmp(&b.path) // Ascending path for deterministic tie-break
-            });
-            
-            let original = &cluster[0];
-            writeln!(file, "# {}", original.path.display()).unwrap();
-            
-            for i in 1..cluster.len() {
-                let dup = &cluster[i];
-                let dist = dup.hash.dist(&original.hash);
-                writeln!(file, "D {} {}", dist, dup.path.display()).unwrap();
-            }
+}
+
+/// What a scan looks at: the paths on the command line and `--file-list`.
+#[derive(Clone, Debug)]
+pub struct ScanPaths {
+    pub paths: Vec<PathBuf>,
+    pub file_list: Option<PathBuf>,
+    pub recursive: bool,
+    pub follow_links: bool,
+}
+
+/// Image files named in the file list and found under the paths of
+/// `sources`, sorted and without repeats: what headless scans and duplicate
+/// searches work on. Formats this build can't decode are left out, with a
+/// warning, rather than all turning up as failures.
+pub fn collect_images(sources: &ScanPaths) -> io::Result<Vec<PathBuf>> {
+    let ScanPaths { paths, file_list, recursive, follow_links } = sources;
+    let mut all_files = match file_list {
+        Some(list) => read_file_list(list, *follow_links)?,
+        None => Vec::new(),
+    };
+    for path in paths {
+        if path.is_dir() {
+            collect_files(path, *recursive, *follow_links, &mut all_files);
+        } else if path.is_file() && is_image_file(path) {
+            all_files.push(path.clone());
         }
     }
-    eprintln!("Done.");
+    // A file listed twice must not turn up as its own exact copy
+    all_files.sort();
+    all_files.dedup();
+
+    let mut unsupported: BTreeMap<&str, usize> = BTreeMap::new();
+    all_files.retain(|path| match missing_feature(path) {
+        Some(feature) => {
+            *unsupported.entry(feature).or_default() += 1;
+            false
+        }
+        None => true,
+    });
+    for (feature, count) in unsupported {
+        log::warn!(
+            "Skipping {} {} file(s): support not compiled in (build with --features {})",
+            count,
+            feature.to_uppercase(),
+            feature
+        );
+    }
+    Ok(all_files)
 }
 
-fn collect_files(
+pub fn collect_files(
     dir: &Path, 
     recursive: bool, 
     follow_links: bool,
@@ -13,13 +13,118 @@ const IMAGE_EXTENSIONS: &[&str] = &[
     "pgm", "ppm", "pam", "dds", "hdr", "exr", "ff", "qoi",
 ];
 
-pub fn is_image_file(path: &Path) -> bool {
+/// Camera RAW formats, shown via their embedded previews (or developed,
+/// with cargo feature "raw").
+const RAW_EXTENSIONS: &[&str] = &[
+    "cr2", "cr3", "nef", "nrw", "arw", "sr2", "dng", "raf", "orf", "rw2", "pef", "srw", "3fr", "iiq",
+];
+
+/// Formats that store pixels without loss (or are vector sources), preferred
+/// over lossy ones when picking which duplicate to keep.
+const LOSSLESS_EXTENSIONS: &[&str] = &[
+    "png", "tiff", "tif", "bmp", "tga", "pnm", "pbm", "pgm", "ppm", "pam", "exr", "hdr", "qoi",
+    "ff", "svg", "svgz",
+];
+
+/// Vector formats, rasterised on demand.
+const VECTOR_EXTENSIONS: &[&str] = &["svg", "svgz"];
+const HEIF_EXTENSIONS: &[&str] = &["heic", "heif"];
+
+/// Formats whose decoders are optional cargo features:
+/// (extensions, feature, compiled in). The viewer lists them either way, so
+/// a build without the decoder says so instead of silently skipping the
+/// files; scans that can't use them skip them with a warning.
+const OPTIONAL_FORMATS: &[(&[&str], &str, bool)] = &[
+    (VECTOR_EXTENSIONS, "svg", cfg!(feature = "svg")),
+    (&["avif"], "avif", cfg!(feature = "avif")),
+    (HEIF_EXTENSIONS, "heif", cfg!(feature = "heif")),
+];
+
+fn has_extension(path: &Path, list: &[&str]) -> bool {
     path.extension()
         .and_then(|e| e.to_str())
-        .map(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .map(|e| list.contains(&e.to_lowercase().as_str()))
         .unwrap_or(false)
 }
 
+pub fn is_image_file(path: &Path) -> bool {
+    has_extension(path, IMAGE_EXTENSIONS)
+        || has_extension(path, RAW_EXTENSIONS)
+        || OPTIONAL_FORMATS.iter().any(|(exts, _, _)| has_extension(path, exts))
+}
+
+/// The cargo feature needed to decode `path`, if it isn't compiled in.
+pub fn missing_feature(path: &Path) -> Option<&'static str> {
+    OPTIONAL_FORMATS
+        .iter()
+        .find(|(exts, _, enabled)| !enabled && has_extension(path, exts))
+        .map(|(_, feature, _)| *feature)
+}
+
+pub fn is_raw_file(path: &Path) -> bool {
+    has_extension(path, RAW_EXTENSIONS)
+}
+
+/// How much of the original picture a format keeps: 2 for camera RAW, 1 for
+/// lossless formats, 0 for lossy ones (JPEG, WebP, HEIF, ...).
+pub fn format_rank(path: &Path) -> u8 {
+    if has_extension(path, RAW_EXTENSIONS) {
+        2
+    } else if has_extension(path, LOSSLESS_EXTENSIONS) {
+        1
+    } else {
+        0
+    }
+}
+
+pub fn is_vector_file(path: &Path) -> bool {
+    has_extension(path, VECTOR_EXTENSIONS)
+}
+
+#[cfg(feature = "heif")]
+pub fn is_heif_file(path: &Path) -> bool {
+    has_extension(path, HEIF_EXTENSIONS)
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
+/// Image files named on one line of a `--file-list`: tab or double-space
+/// separated columns, each a path or, if it isn't a file, paths separated by
+/// whitespace. Symlinks only count with `follow_links`.
+pub fn list_line_images(line: &str, follow_links: bool) -> Vec<PathBuf> {
+    let mut images = Vec::new();
+    let mut consider = |p: PathBuf| {
+        if (follow_links || !is_symlink(&p)) && p.is_file() && is_image_file(&p) {
+            images.push(p);
+        }
+    };
+    for part in line.split('\t').flat_map(|column| column.split("  ")) {
+        let trimmed = part.trim();
+        let p = PathBuf::from(trimmed);
+        if trimmed.is_empty() || (!follow_links && is_symlink(&p)) {
+            continue;
+        }
+        if p.is_file() {
+            consider(p);
+        } else {
+            trimmed.split_whitespace().map(PathBuf::from).for_each(&mut consider);
+        }
+    }
+    images
+}
+
+/// Every image file a `--file-list` names, in order.
+pub fn read_file_list(list: &Path, follow_links: bool) -> io::Result<Vec<PathBuf>> {
+    let reader = io::BufReader::new(fs::File::open(list)?);
+    let mut images = Vec::new();
+    for line in reader.lines() {
+        images.extend(list_line_images(&line?, follow_links));
+    }
+    Ok(images)
+}
+
 pub fn spawn_file_scanner(
     paths: Vec<PathBuf>,
     file_list: Option<PathBuf>,
@@ -33,66 +138,24 @@ pub fn spawn_file_scanner(
         let start_time = Instant::now();
         let mut count = 0;
 
-        let should_process = |p: &PathBuf| -> bool {
-            if !follow_links {
-                if let Ok(meta) = fs::symlink_metadata(p) {
-                    if meta.file_type().is_symlink() {
-                        return false;
-                    }
-                }
-            }
-            true
-        };
+        let should_process = |p: &PathBuf| follow_links || !is_symlink(p);
 
         // 1. Read from file list if provided
-        if let Some(list_path) = file_list {
-            if let Ok(file) = fs::File::open(list_path) {
-                let reader = io::BufReader::new(file);
-                for line in reader.lines() {
-                    if let Ok(l) = line {
-                        // Split by tab first
-                        for tab_part in l.split('\t') {
-                            // Then by double-space (common column separator)
-                            for part in tab_part.split("  ") {
-                                let trimmed = part.trim();
-                                if trimmed.is_empty() { continue; }
-                                
-                                let p = PathBuf::from(trimmed);
-                                if !should_process(&p) { continue; }
-
-                                if p.is_file() {
-                                    if is_image_file(&p) {
-                                        {
-                                            let mut guard = files_arc.write().unwrap();
-                                            guard.push(p);
-                                        }
-                                        count += 1;
-                                        // Batch updates slightly? For now, every file is safe but maybe noisy.
-                                        // Let's send update every 100 items or so, or if it's the first item.
-                                        if count == 1 || count % 100 == 0 {
-                                             let _ = proxy.send_event(UserEvent::FileListUpdated);
-                                        }
-                                    }
-                                } else {
-                                    // Try split whitespace
-                                    for sub in trimmed.split_whitespace() {
-                                        let sub_p = PathBuf::from(sub);
-                                        if !should_process(&sub_p) { continue; }
-
-                                        if sub_p.is_file() && is_image_file(&sub_p) {
-                                            {
-                                                let mut guard = files_arc.write().unwrap();
-                                                guard.push(sub_p);
-                                            }
-                                            count += 1;
-                                            if count == 1 || count % 100 == 0 {
-                                                let _ = proxy.send_event(UserEvent::FileListUpdated);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
+        if let Some(list_path) = file_list
+            && let Ok(file) = fs::File::open(list_path)
+        {
+            let reader = io::BufReader::new(file);
+            for line in reader.lines().map_while(Result::ok) {
+                for p in list_line_images(&line, follow_links) {
+                    {
+                        let mut guard = files_arc.write().unwrap();
+                        guard.push(p);
+                    }
+                    count += 1;
+                    // Batch updates slightly? For now, every file is safe but maybe noisy.
+                    // Let's send update every 100 items or so, or if it's the first item.
+                    if count == 1 || count % 100 == 0 {
+                         let _ = proxy.send_event(UserEvent::FileListUpdated);
                     }
                 }
             }
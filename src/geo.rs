@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Instant;
+use winit::event_loop::EventLoopProxy;
+use rayon::prelude::*;
+
+use crate::dedupe::{collect_images, ScanPaths};
+use crate::loader::{load_metadata, UserEvent};
+use crate::metadata::{json_string, ImageMetadata};
+
+// ---------------------------------------------------------------------------
+// Geotagged images: GeoJSON export and grouping by location
+// ---------------------------------------------------------------------------
+
+/// Mean Earth radius, for great-circle distances.
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Great-circle distance in km between two (latitude, longitude) points.
+pub fn distance_km(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (a.0.to_radians(), b.0.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (b.1 - a.1).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+}
+
+fn position(meta: &ImageMetadata) -> Option<(f64, f64)> {
+    Some((meta.latitude?, meta.longitude?))
+}
+
+/// Metadata of every file, read in parallel.
+fn read_all(files: Vec<PathBuf>) -> Vec<(PathBuf, Option<ImageMetadata>)> {
+    files
+        .into_par_iter()
+        .map(|path| {
+            let metadata = load_metadata(&path).metadata;
+            (path, metadata)
+        })
+        .collect()
+}
+
+// -- GeoJSON export -------------------------------------------------------------
+
+/// Write the geotagged images among `sources` to `output` as a GeoJSON
+/// FeatureCollection of points, then exit.
+pub fn run_geojson_export(sources: &ScanPaths, output: &Path) {
+    let files = collect_images(sources).unwrap_or_else(|e| {
+        eprintln!("Error: reading the file list: {}", e);
+        std::process::exit(1);
+    });
+    eprintln!("Found {} candidates. Reading metadata...", files.len());
+    let images = read_all(files);
+
+    let result = fs::File::create(output)
+        .and_then(|file| write_geojson(BufWriter::new(file), &images));
+    match result {
+        Ok(count) => eprintln!("Wrote {} geotagged image(s) to {}.", count, output.display()),
+        Err(e) => {
+            eprintln!("Error: writing {}: {}", output.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `value` with at most `places` decimals and no trailing zeros. Seven
+/// places of a degree are about a centimetre.
+fn decimal(value: f64, places: usize) -> String {
+    let s = format!("{:.*}", places, value);
+    let s = if s.contains('.') { s.trim_end_matches('0').trim_end_matches('.') } else { &s };
+    if s == "-0" { "0".to_string() } else { s.to_string() }
+}
+
+/// One Feature per geotagged image, with its path, time and camera as
+/// properties. Returns how many were written.
+fn write_geojson(mut out: impl Write, images: &[(PathBuf, Option<ImageMetadata>)]) -> io::Result<usize> {
+    writeln!(out, "{{\"type\":\"FeatureCollection\",\"features\":[")?;
+    let mut count = 0;
+    for (path, meta) in images {
+        let Some(meta) = meta else { continue };
+        let Some((lat, lon)) = position(meta) else { continue };
+
+        // GeoJSON puts longitude first
+        let coordinates = match meta.altitude {
+            Some(alt) => format!("[{},{},{}]", decimal(lon, 7), decimal(lat, 7), decimal(alt, 2)),
+            None => format!("[{},{}]", decimal(lon, 7), decimal(lat, 7)),
+        };
+        let mut properties = format!("\"path\":{}", json_string(&path.to_string_lossy()));
+        for (key, value) in [("datetime", &meta.datetime), ("make", &meta.make), ("model", &meta.model)] {
+            if let Some(value) = value {
+                properties.push_str(&format!(",\"{}\":{}", key, json_string(value)));
+            }
+        }
+
+        if count > 0 {
+            writeln!(out, ",")?;
+        }
+        write!(
+            out,
+            "{{\"type\":\"Feature\",\"geometry\":{{\"type\":\"Point\",\"coordinates\":{}}},\"properties\":{{{}}}}}",
+            coordinates, properties
+        )?;
+        count += 1;
+    }
+    writeln!(out, "\n]}}")?;
+    out.flush()?;
+    Ok(count)
+}
+
+// -- Grouping by location ---------------------------------------------------------
+
+/// Union-find root of `i`, with path halving.
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Buckets of positions about `radius_km` across: bands of latitude, split
+/// into columns of longitude wide enough at that latitude that positions
+/// within the radius of each other are always in the same or neighbouring
+/// cells (across the antimeridian too).
+struct Grid {
+    radius_deg: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl Grid {
+    fn new(radius_km: f64) -> Self {
+        Self { radius_deg: (radius_km / EARTH_RADIUS_KM).to_degrees(), cells: HashMap::new() }
+    }
+
+    fn band(&self, lat: f64) -> i64 {
+        (lat / self.radius_deg).floor() as i64
+    }
+
+    /// Columns of `band`: as many as fit the widest longitude span the
+    /// radius covers from any latitude in it or its neighbours, or one if
+    /// that's near a pole.
+    fn columns(&self, band: i64) -> i64 {
+        let r = self.radius_deg;
+        let reach = band.abs().max((band + 1).abs()) as f64 * r + r;
+        if reach + r >= 90.0 {
+            return 1;
+        }
+        let span = (r.to_radians().sin() / reach.to_radians().cos()).asin().to_degrees();
+        let columns = (360.0 / span).floor() as i64;
+        if columns < 3 { 1 } else { columns }
+    }
+
+    fn column(lon: f64, columns: i64) -> i64 {
+        ((lon + 180.0) / (360.0 / columns as f64)).floor() as i64 % columns
+    }
+
+    fn insert(&mut self, pos: (f64, f64), i: usize) {
+        let band = self.band(pos.0);
+        let column = Self::column(pos.1, self.columns(band));
+        self.cells.entry((band, column)).or_default().push(i);
+    }
+
+    /// Everything inserted in the cells that could hold positions within
+    /// the radius of `pos`.
+    fn near(&self, pos: (f64, f64)) -> impl Iterator<Item = usize> + '_ {
+        let band = self.band(pos.0);
+        (band - 1..=band + 1)
+            .flat_map(move |b| {
+                let columns = self.columns(b);
+                let column = Self::column(pos.1, columns);
+                let spread = if columns == 1 { 0 } else { 1 };
+                (-spread..=spread).map(move |d| (b, (column + d).rem_euclid(columns)))
+            })
+            .filter_map(|key| self.cells.get(&key))
+            .flatten()
+            .copied()
+    }
+}
+
+/// Order images for browsing by where they were taken. Images within
+/// `radius_km` of each other, directly or through others, form a group.
+/// Groups come in order of their earliest image and images by time within
+/// a group; images without a position go last.
+pub fn group_by_location(images: Vec<(PathBuf, Option<ImageMetadata>)>, radius_km: f64) -> Vec<PathBuf> {
+    let (tagged, untagged): (Vec<_>, Vec<_>) = images
+        .into_iter()
+        .map(|(path, meta)| {
+            let pos = meta.as_ref().and_then(position);
+            let time = meta.and_then(|m| m.datetime);
+            (path, pos, time)
+        })
+        .partition(|(_, pos, _)| pos.is_some());
+    let positions: Vec<(f64, f64)> = tagged.iter().filter_map(|(_, pos, _)| *pos).collect();
+
+    // Only images in neighbouring grid cells can be within the radius
+    let mut grid = Grid::new(radius_km);
+    let mut parent: Vec<usize> = (0..positions.len()).collect();
+    for (a, &pos) in positions.iter().enumerate() {
+        let near: Vec<usize> = grid.near(pos).collect();
+        for b in near {
+            let (ra, rb) = (find_root(&mut parent, a), find_root(&mut parent, b));
+            if ra != rb && distance_km(pos, positions[b]) <= radius_km {
+                parent[ra] = rb;
+            }
+        }
+        grid.insert(pos, a);
+    }
+
+    // Undated images sort after dated ones
+    let sort_key = |i: usize| (tagged[i].2.is_none(), tagged[i].2.clone(), tagged[i].0.clone());
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..tagged.len() {
+        groups.entry(find_root(&mut parent, i)).or_default().push(i);
+    }
+    let mut groups: Vec<Vec<usize>> = groups.into_values().collect();
+    for group in &mut groups {
+        group.sort_by_key(|&i| sort_key(i));
+    }
+    groups.sort_by_key(|group| sort_key(group[0]));
+
+    log::info!(
+        "{} geotagged image(s) in {} location group(s), {} without a position",
+        tagged.len(),
+        groups.len(),
+        untagged.len()
+    );
+
+    let mut order: Vec<Option<PathBuf>> = tagged.into_iter().map(|(path, _, _)| Some(path)).collect();
+    groups
+        .into_iter()
+        .flatten()
+        .filter_map(|i| order[i].take())
+        .chain(untagged.into_iter().map(|(path, _, _)| path))
+        .collect()
+}
+
+/// Browse-list producer for `--group-by-location`: read the metadata of
+/// every file among `sources`, then publish them grouped by location.
+pub fn spawn_location_scanner(
+    sources: ScanPaths,
+    radius_km: f64,
+    files_arc: Arc<RwLock<Vec<PathBuf>>>,
+    proxy: EventLoopProxy<UserEvent>,
+) {
+    thread::spawn(move || {
+        let start_time = Instant::now();
+        let files = collect_images(&sources).unwrap_or_else(|e| {
+            log::error!("Reading the file list: {}", e);
+            Vec::new()
+        });
+        log::info!("Found {} images. Reading locations...", files.len());
+
+        let ordered = group_by_location(read_all(files), radius_km);
+        files_arc.write().unwrap().extend(ordered);
+        let _ = proxy.send_event(UserEvent::FileListUpdated);
+
+        log::info!("Location scan complete in {:.2}s.", start_time.elapsed().as_secs_f64());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Positions from a fixed xorshift sequence, some near the poles and
+    /// the antimeridian, and many along one parallel.
+    fn positions() -> Vec<(f64, f64)> {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let mut out = Vec::new();
+        for _ in 0..300 {
+            out.push((next() * 180.0 - 90.0, next() * 360.0 - 180.0));
+            out.push((89.0 + next(), next() * 360.0 - 180.0));
+            out.push((next() * 2.0 - 1.0, 179.5 + next() - (next() < 0.5) as u8 as f64 * 359.0));
+            out.push((48.85, next() * 10.0));
+        }
+        out
+    }
+
+    fn groups(positions: &[(f64, f64)], radius_km: f64) -> Vec<Vec<usize>> {
+        let images = positions
+            .iter()
+            .enumerate()
+            .map(|(i, &(lat, lon))| {
+                let meta = ImageMetadata { latitude: Some(lat), longitude: Some(lon), ..Default::default() };
+                (PathBuf::from(format!("{:04}", i)), Some(meta))
+            })
+            .collect();
+        let order: Vec<usize> = group_by_location(images, radius_km)
+            .iter()
+            .map(|p| p.to_str().unwrap().parse().unwrap())
+            .collect();
+        let mut parent: Vec<usize> = (0..positions.len()).collect();
+        for a in 0..positions.len() {
+            for b in 0..a {
+                if distance_km(positions[a], positions[b]) <= radius_km {
+                    let (ra, rb) = (find_root(&mut parent, a), find_root(&mut parent, b));
+                    parent[ra] = rb;
+                }
+            }
+        }
+        let mut expected: HashMap<usize, Vec<usize>> = HashMap::new();
+        for i in 0..positions.len() {
+            expected.entry(find_root(&mut parent, i)).or_default().push(i);
+        }
+        let mut expected: Vec<Vec<usize>> = expected.into_values().collect();
+        expected.sort();
+        // Undated groups come in path order, each starting with its first
+        // path: cut the browse order by the expected sizes and compare members
+        let mut got = Vec::new();
+        let mut rest = &order[..];
+        while let Some(&first) = rest.first() {
+            let len = expected.iter().find(|g| g[0] == first).map_or(1, |g| g.len());
+            let mut group = rest[..len].to_vec();
+            group.sort();
+            got.push(group);
+            rest = &rest[len..];
+        }
+        got.sort();
+        assert_eq!(got, expected, "radius {} km", radius_km);
+        got
+    }
+
+    #[test]
+    fn grid_finds_the_same_groups_as_comparing_every_pair() {
+        let positions = positions();
+        for radius_km in [0.5, 30.0, 200.0, 1500.0] {
+            groups(&positions, radius_km);
+        }
+    }
+
+    #[test]
+    fn groups_reach_across_the_antimeridian_and_poles() {
+        let positions = [(0.0, 179.99), (0.0, -179.99), (89.999, 0.0), (89.999, 180.0), (0.0, 0.0)];
+        assert_eq!(groups(&positions, 5.0), vec![vec![0, 1], vec![2, 3], vec![4]]);
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, TryLockError};
+use std::io::{self, BufRead, BufReader, BufWriter, LineWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use image_hasher::ImageHash;
+
+// ---------------------------------------------------------------------------
+// On-disk cache of perceptual hashes for duplicate scans
+// ---------------------------------------------------------------------------
+//
+// One line per file, tab separated, path last so it may contain anything but
+// a newline:
+//
+//     config  size  mtime_ns  width  height  hash,hash,...  path
+//
+// Paths are stored absolute, so scans started from different directories
+// share entries instead of mixing up each other's relative paths.
+//
+// New hashes are appended as soon as they're computed, so an interrupted scan
+// picks up where it stopped; later lines win over earlier ones. `finish`
+// rewrites the file without stale lines and files deleted from the scanned
+// directories. Scans hold a shared lock on `<file>.lock` while they append,
+// and the rewrite only happens under an exclusive one, so it never drops
+// lines another iv process is still writing.
+
+const HEADER: &str = "# iv hash cache v1";
+
+/// Default cache file: `$XDG_CACHE_HOME/iv/hashes.tsv`, falling back to
+/// `~/.cache` (or `%LOCALAPPDATA%` on Windows).
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(base.join("iv").join("hashes.tsv"))
+}
+
+/// Size and modification time in ns, which must both match for a cached
+/// hash to be used.
+pub fn file_stamp(path: &Path) -> Option<(u64, u128)> {
+    let meta = fs::metadata(path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    Some((meta.len(), mtime))
+}
+
+/// `path` made absolute against the working directory.
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn ends_with_newline(path: &Path, len: u64) -> io::Result<bool> {
+    let mut file = fs::File::open(path)?;
+    file.seek(SeekFrom::Start(len - 1))?;
+    let mut last = [0u8];
+    file.read_exact(&mut last)?;
+    Ok(last[0] == b'\n')
+}
+
+struct Entry {
+    size: u64,
+    mtime: u128,
+    width: u32,
+    height: u32,
+    /// Base64 hashes, comma separated; only decoded on a hit.
+    hashes: String,
+}
+
+impl Entry {
+    fn parse(line: &str) -> Option<(String, PathBuf, Entry)> {
+        let mut parts = line.splitn(7, '\t');
+        let config = parts.next()?.to_string();
+        let entry = Entry {
+            size: parts.next()?.parse().ok()?,
+            mtime: parts.next()?.parse().ok()?,
+            width: parts.next()?.parse().ok()?,
+            height: parts.next()?.parse().ok()?,
+            hashes: parts.next()?.to_string(),
+        };
+        let path = PathBuf::from(parts.next().filter(|p| !p.is_empty())?);
+        Some((config, path, entry))
+    }
+
+    fn write(&self, out: &mut impl Write, config: &str, path: &str) -> io::Result<()> {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            config, self.size, self.mtime, self.width, self.height, self.hashes, path
+        )
+    }
+}
+
+pub struct HashCache {
+    path: PathBuf,
+    /// What is being scanned, absolute; only files under these are pruned.
+    roots: Vec<PathBuf>,
+    /// Shared lock held until `finish`, if locking works here.
+    lock: Option<fs::File>,
+    /// Hasher settings the hashes were made with; entries made with other
+    /// settings are kept but never returned.
+    config: String,
+    entries: HashMap<(String, PathBuf), Entry>,
+    appender: Mutex<Option<LineWriter<fs::File>>>,
+    hits: AtomicUsize,
+}
+
+impl HashCache {
+    /// Load the cache at `path` for hashes made with `config`, for a scan of
+    /// `roots`. A missing or unreadable cache just starts empty.
+    pub fn open(path: PathBuf, config: String, roots: &[PathBuf]) -> Self {
+        // Waits out another process's `finish`
+        let lock = Self::lock_shared(&path)
+            .map_err(|e| log::debug!("Hash cache {} not locked: {}", path.display(), e))
+            .ok();
+        let entries = Self::load(&path);
+        log::info!("Hash cache {}: {} entries", path.display(), entries.len());
+
+        let appender = Self::open_appender(&path)
+            .map_err(|e| log::warn!("Hash cache {} is read-only: {}", path.display(), e))
+            .ok();
+        Self {
+            path,
+            roots: roots.iter().map(|root| absolute(root)).collect(),
+            lock,
+            config,
+            entries,
+            appender: Mutex::new(appender),
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    fn lock_shared(path: &Path) -> io::Result<fs::File> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let file = fs::OpenOptions::new().create(true).truncate(false).write(true).open(lock_path)?;
+        file.lock_shared()?;
+        Ok(file)
+    }
+
+    fn load(path: &Path) -> HashMap<(String, PathBuf), Entry> {
+        let mut entries = HashMap::new();
+        let Ok(file) = fs::File::open(path) else { return entries };
+        for line in BufReader::new(file).lines() {
+            // A line cut short by an interrupted scan fails to parse
+            let Ok(line) = line else { break };
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some((config, file_path, entry)) = Entry::parse(&line) {
+                entries.insert((config, file_path), entry);
+            }
+        }
+        entries
+    }
+
+    fn open_appender(path: &Path) -> io::Result<LineWriter<fs::File>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        let mut writer = LineWriter::new(file);
+        if len == 0 {
+            writeln!(writer, "{}", HEADER)?;
+        } else if !ends_with_newline(path, len)? {
+            // Don't glue the first new line onto one an interrupted scan cut short
+            writeln!(writer)?;
+        }
+        Ok(writer)
+    }
+
+    /// Cached hashes and first-page size of `path`, if it hasn't changed
+    /// since they were stored; `stamp` is its current `file_stamp`.
+    pub fn get(&self, path: &Path, stamp: (u64, u128)) -> Option<(Vec<ImageHash>, u32, u32)> {
+        let entry = self.entries.get(&(self.config.clone(), absolute(path)))?;
+        if (entry.size, entry.mtime) != stamp {
+            return None;
+        }
+        let hashes = entry
+            .hashes
+            .split(',')
+            .map(|h| ImageHash::from_base64(h).ok())
+            .collect::<Option<Vec<ImageHash>>>()?;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some((hashes, entry.width, entry.height))
+    }
+
+    /// Append freshly computed hashes for `path`, with the `file_stamp` it
+    /// had before hashing started: a file changed meanwhile then just misses
+    /// next time. Files that can't be decoded aren't stored, so they're
+    /// retried next time.
+    pub fn put(&self, path: &Path, (size, mtime): (u64, u128), hashes: &[ImageHash], width: u32, height: u32) {
+        // Lines are tab and newline separated
+        let path = absolute(path);
+        let Some(name) = path.to_str().filter(|p| !p.contains(['\n', '\r'])) else { return };
+        let entry = Entry {
+            size,
+            mtime,
+            width,
+            height,
+            hashes: hashes.iter().map(|h| h.to_base64()).collect::<Vec<_>>().join(","),
+        };
+        let mut appender = self.appender.lock().unwrap();
+        if let Some(writer) = appender.as_mut()
+            && let Err(e) = entry.write(writer, &self.config, name)
+        {
+            log::warn!("Hash cache {}: {}", self.path.display(), e);
+            *appender = None;
+        }
+    }
+
+    /// Whether a cached `file` that is gone was deleted, rather than on a
+    /// drive that isn't mounted or outside this scan. Relative paths, from
+    /// caches written before paths were stored absolute, can't be looked up
+    /// any more and count as deleted.
+    fn deleted(&self, file: &Path) -> bool {
+        let Some(dir) = file.parent().filter(|_| file.is_absolute()) else { return true };
+        self.roots.iter().any(|root| file.starts_with(root)) && dir.is_dir() && !file.is_file()
+    }
+
+    /// Rewrite the cache compacted: the latest line per file and settings,
+    /// minus files deleted under the scanned roots. Skipped while another
+    /// scan is appending.
+    pub fn finish(self) {
+        if self.appender.lock().unwrap().is_none() {
+            return;
+        }
+        let Some(lock) = &self.lock else { return };
+        // Only one holder of the shared lock may upgrade, so let go first
+        let _ = lock.unlock();
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                log::info!("Hash cache {} is in use by another scan, not compacting", self.path.display());
+                return;
+            }
+            Err(TryLockError::Error(e)) => {
+                log::warn!("Hash cache {}: {}", self.path.display(), e);
+                return;
+            }
+        }
+        // Re-read so the appended lines are included
+        let mut entries = Self::load(&self.path);
+
+        let before = entries.len();
+        entries.retain(|(_, file), _| !self.deleted(file));
+        let pruned = before - entries.len();
+
+        let path = &self.path;
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let result = fs::File::create(&tmp).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            writeln!(out, "{}", HEADER)?;
+            let mut sorted: Vec<_> = entries.iter().collect();
+            sorted.sort_by(|a, b| a.0.cmp(b.0));
+            for ((config, file), entry) in sorted {
+                if let Some(name) = file.to_str() {
+                    entry.write(&mut out, config, name)?;
+                }
+            }
+            out.flush()?;
+            fs::rename(&tmp, path)
+        });
+        match result {
+            Ok(()) => log::info!(
+                "Hash cache: {} reused, {} entries kept, {} pruned",
+                self.hits.load(Ordering::Relaxed),
+                entries.len(),
+                pruned
+            ),
+            Err(e) => {
+                log::warn!("Hash cache {}: {}", path.display(), e);
+                let _ = fs::remove_file(&tmp);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scans_from_different_directories_keep_each_others_entries() {
+        let base = env::temp_dir().join(format!("iv-hash-cache-{}", std::process::id()));
+        let (a, b) = (base.join("a"), base.join("b"));
+        let cache_path = base.join("hashes.tsv");
+        let hash = ImageHash::from_bytes(&[0x5a; 8]).unwrap();
+        let cwd = env::current_dir().unwrap();
+
+        for dir in [&a, &b] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("x.png"), dir.to_str().unwrap()).unwrap();
+            env::set_current_dir(dir).unwrap();
+            let file = Path::new("./x.png");
+            let roots = [PathBuf::from(".")];
+            let cache = HashCache::open(cache_path.clone(), "test".to_string(), &roots);
+            // Same relative path, other directory: no hit
+            let stamp = file_stamp(file).unwrap();
+            assert!(cache.get(file, stamp).is_none());
+            cache.put(file, stamp, std::slice::from_ref(&hash), 1, 1);
+            cache.finish();
+        }
+
+        // Scanning b again doesn't prune or shadow a's entry
+        let cache = HashCache::open(cache_path.clone(), "test".to_string(), &[PathBuf::from(".")]);
+        for dir in [&a, &b] {
+            let file = dir.join("x.png");
+            let cached = cache.get(&file, file_stamp(&file).unwrap());
+            assert_eq!(cached.map(|(hashes, ..)| hashes), Some(vec![hash.clone()]));
+        }
+        cache.finish();
+
+        env::set_current_dir(cwd).unwrap();
+        let _ = fs::remove_dir_all(&base);
+    }
+}
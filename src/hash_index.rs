@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use image_hasher::ImageHash;
+
+// ---------------------------------------------------------------------------
+// Similarity indexes over perceptual hashes, for duplicate lookups
+// ---------------------------------------------------------------------------
+//
+// Both answer "the first item with a hash within the threshold" without
+// comparing against every stored hash:
+//
+// - Multi-index hashing splits hashes into threshold + 1 bit ranges. Two
+//   hashes within the threshold differ in at most that many bits, so they
+//   agree exactly on at least one range; only hashes sharing a range with
+//   the query are compared. Very fast while the ranges are wide enough to
+//   spread hashes over many buckets, i.e. for small thresholds.
+// - A BK-tree uses the triangle inequality to skip subtrees that can't hold
+//   a match, for the large thresholds where the ranges get too narrow.
+
+/// Narrowest range multi-index hashing is used with (a threshold of up to
+/// 11 on 64-bit hashes); below this the buckets get too full to beat the
+/// BK-tree. See `benches/similarity.rs`.
+const MIN_RANGE_BITS: u32 = 5;
+
+/// The index for hashes of `hash_bits` bits searched within `radius`.
+pub enum HashIndex {
+    Multi(MultiIndex),
+    Tree(BkTree),
+}
+
+impl HashIndex {
+    pub fn new(hash_bits: u32, radius: u32) -> Self {
+        if hash_bits / (radius + 1) >= MIN_RANGE_BITS {
+            HashIndex::Multi(MultiIndex::new(hash_bits, radius))
+        } else {
+            HashIndex::Tree(BkTree::new(radius))
+        }
+    }
+
+    /// Add one hash of `item`. Files with several pages add one per page.
+    pub fn insert(&mut self, hash: ImageHash, item: usize) {
+        match self {
+            HashIndex::Multi(index) => index.insert(hash, item),
+            HashIndex::Tree(tree) => tree.insert(hash, item),
+        }
+    }
+
+    /// Call `f(item, distance)` for every stored hash within the radius of
+    /// any of `hashes`, in no particular order. An item may come up more
+    /// than once.
+    pub fn for_each_match(&self, hashes: &[ImageHash], mut f: impl FnMut(usize, u32)) {
+        for hash in hashes {
+            match self {
+                HashIndex::Multi(index) => index.find(hash, &mut f),
+                HashIndex::Tree(tree) => tree.find(hash, &mut f),
+            }
+        }
+    }
+
+    /// The first item (lowest id) with any hash within the radius of any of
+    /// `hashes`, and its distance: the closest pair. The same answer as
+    /// trying every item in insertion order.
+    pub fn first_match(&self, hashes: &[ImageHash]) -> Option<(usize, u32)> {
+        let mut best = None;
+        self.for_each_match(hashes, |item, d| keep_first(&mut best, item, d));
+        best
+    }
+}
+
+fn keep_first(best: &mut Option<(usize, u32)>, item: usize, d: u32) {
+    match *best {
+        Some((b, bd)) if b < item || (b == item && bd <= d) => {}
+        _ => *best = Some((item, d)),
+    }
+}
+
+// -- Multi-index hashing ----------------------------------------------------------
+
+pub struct MultiIndex {
+    radius: u32,
+    /// Bit ranges [start, end) the hashes are split into.
+    ranges: Vec<(u32, u32)>,
+    /// Per range: the bits in it -> entries with those bits.
+    tables: Vec<HashMap<u64, Vec<usize>>>,
+    entries: Vec<(ImageHash, usize)>,
+}
+
+impl MultiIndex {
+    pub fn new(hash_bits: u32, radius: u32) -> Self {
+        let count = (radius + 1).min(hash_bits.max(1));
+        let ranges = (0..count)
+            .map(|i| (i * hash_bits / count, (i + 1) * hash_bits / count))
+            .collect();
+        Self {
+            radius,
+            ranges,
+            tables: (0..count).map(|_| HashMap::new()).collect(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// The bits of `hash` in `start..end`, as a table key. Ranges wider than
+    /// 64 bits are folded, which only adds candidates to check.
+    fn key(hash: &ImageHash, (start, end): (u32, u32)) -> u64 {
+        let bytes = hash.as_bytes();
+        (start..end).fold(0u64, |key, bit| {
+            let set = bytes.get(bit as usize / 8).is_some_and(|b| b >> (bit % 8) & 1 == 1);
+            key.rotate_left(1) ^ set as u64
+        })
+    }
+
+    fn insert(&mut self, hash: ImageHash, item: usize) {
+        let entry = self.entries.len();
+        for (table, &range) in self.tables.iter_mut().zip(&self.ranges) {
+            table.entry(Self::key(&hash, range)).or_default().push(entry);
+        }
+        self.entries.push((hash, item));
+    }
+
+    fn find(&self, hash: &ImageHash, mut f: impl FnMut(usize, u32)) {
+        for (table, &range) in self.tables.iter().zip(&self.ranges) {
+            let Some(bucket) = table.get(&Self::key(hash, range)) else { continue };
+            for &entry in bucket {
+                let (stored, item) = &self.entries[entry];
+                let d = stored.dist(hash);
+                if d <= self.radius {
+                    f(*item, d);
+                }
+            }
+        }
+    }
+}
+
+// -- BK-tree ----------------------------------------------------------------------
+
+struct Node {
+    hash: ImageHash,
+    /// Caller's id for the file the hash belongs to.
+    item: usize,
+    /// (distance to this node, child node index)
+    children: Vec<(u32, usize)>,
+}
+
+pub struct BkTree {
+    radius: u32,
+    nodes: Vec<Node>,
+}
+
+impl BkTree {
+    pub fn new(radius: u32) -> Self {
+        Self { radius, nodes: Vec::new() }
+    }
+
+    fn insert(&mut self, hash: ImageHash, item: usize) {
+        let new = self.nodes.len();
+        if new > 0 {
+            let mut at = 0;
+            loop {
+                let d = self.nodes[at].hash.dist(&hash);
+                match self.nodes[at].children.iter().find(|&&(cd, _)| cd == d) {
+                    Some(&(_, child)) => at = child,
+                    None => {
+                        self.nodes[at].children.push((d, new));
+                        break;
+                    }
+                }
+            }
+        }
+        self.nodes.push(Node { hash, item, children: Vec::new() });
+    }
+
+    /// A node at distance d from the query can only have matches among
+    /// children at distance d - radius ..= d + radius from it.
+    fn find(&self, hash: &ImageHash, mut f: impl FnMut(usize, u32)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(at) = stack.pop() {
+            let node = &self.nodes[at];
+            let d = node.hash.dist(hash);
+            if d <= self.radius {
+                f(node.item, d);
+            }
+            let (lo, hi) = (d.saturating_sub(self.radius), d.saturating_add(self.radius));
+            stack.extend(node.children.iter().filter(|&&(cd, _)| cd >= lo && cd <= hi).map(|&(_, child)| child));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(bits: u64) -> ImageHash {
+        ImageHash::from_bytes(&bits.to_le_bytes()).unwrap()
+    }
+
+    /// Hashes with some close neighbours, from a fixed xorshift sequence.
+    fn hashes() -> Vec<u64> {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let mut out = Vec::new();
+        for _ in 0..200 {
+            let base = next();
+            out.push(base);
+            out.push(base ^ (1 << (next() % 64)) ^ (1 << (next() % 64)));
+        }
+        out
+    }
+
+    fn linear_first(stored: &[u64], query: u64, radius: u32) -> Option<(usize, u32)> {
+        stored
+            .iter()
+            .enumerate()
+            .map(|(i, &h)| (i, (h ^ query).count_ones()))
+            .find(|&(_, d)| d <= radius)
+    }
+
+    fn check(mut index: HashIndex, radius: u32) {
+        let all = hashes();
+        let (stored, queries) = all.split_at(all.len() / 2);
+        for (i, &h) in stored.iter().enumerate() {
+            index.insert(hash(h), i);
+        }
+        for &query in queries.iter().chain(stored) {
+            assert_eq!(index.first_match(&[hash(query)]), linear_first(stored, query, radius), "radius {}", radius);
+        }
+    }
+
+    #[test]
+    fn indexes_agree_with_a_linear_scan() {
+        for radius in [0, 2, 5, 12] {
+            check(HashIndex::Multi(MultiIndex::new(64, radius)), radius);
+            check(HashIndex::Tree(BkTree::new(radius)), radius);
+        }
+    }
+
+    #[test]
+    fn picks_multi_index_for_small_radii_only() {
+        assert!(matches!(HashIndex::new(64, 2), HashIndex::Multi(_)));
+        assert!(matches!(HashIndex::new(64, 16), HashIndex::Tree(_)));
+    }
+
+    #[test]
+    fn first_match_is_the_lowest_item_over_all_pages() {
+        let mut index = HashIndex::new(64, 1);
+        index.insert(hash(0b100), 3);
+        index.insert(hash(0b1), 1);
+        index.insert(hash(0), 2);
+        // Item 2 equals the first page, but item 1 is within reach of both
+        assert_eq!(index.first_match(&[hash(0), hash(0b11)]), Some((1, 1)));
+        assert_eq!(index.first_match(&[hash(u64::MAX)]), None);
+    }
+}
@@ -0,0 +1,45 @@
+use image::{DynamicImage, RgbImage, RgbaImage};
+use libheif_rs::{ColorSpace, HeifContext, HeifError, LibHeif, RgbChroma};
+
+use crate::loader::DecodeError;
+
+// ---------------------------------------------------------------------------
+// HEIF/HEIC decoding via libheif (cargo feature "heif")
+// ---------------------------------------------------------------------------
+
+impl From<HeifError> for DecodeError {
+    fn from(e: HeifError) -> Self {
+        DecodeError::Corrupt(e.to_string())
+    }
+}
+
+/// Decode the primary image. libheif applies the container's rotation and
+/// mirroring itself.
+pub fn decode(bytes: &[u8]) -> Result<DynamicImage, DecodeError> {
+    let ctx = HeifContext::read_from_bytes(bytes)?;
+    let handle = ctx.primary_image_handle()?;
+    let alpha = handle.has_alpha_channel();
+    let chroma = if alpha { RgbChroma::Rgba } else { RgbChroma::Rgb };
+    let image = LibHeif::new().decode(&handle, ColorSpace::Rgb(chroma), None)?;
+
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or_else(|| DecodeError::Corrupt("HEIF image has no interleaved plane".to_string()))?;
+    let (width, height) = (plane.width, plane.height);
+
+    let short = || DecodeError::Corrupt("HEIF plane smaller than its size".to_string());
+
+    // Drop the stride padding at the end of each row
+    let row_len = width as usize * if alpha { 4 } else { 3 };
+    let mut data = Vec::with_capacity(row_len * height as usize);
+    for row in plane.data.chunks(plane.stride).take(height as usize) {
+        data.extend_from_slice(row.get(..row_len).ok_or_else(short)?);
+    }
+
+    if alpha {
+        RgbaImage::from_raw(width, height, data).map(DynamicImage::ImageRgba8).ok_or_else(short)
+    } else {
+        RgbImage::from_raw(width, height, data).map(DynamicImage::ImageRgb8).ok_or_else(short)
+    }
+}
@@ -0,0 +1,78 @@
+// ---------------------------------------------------------------------------
+// Per-channel histograms of decoded images
+// ---------------------------------------------------------------------------
+
+/// Channel order in `Histogram::bins`.
+pub const RED: usize = 0;
+pub const GREEN: usize = 1;
+pub const BLUE: usize = 2;
+pub const LUMA: usize = 3;
+
+pub struct Histogram {
+    /// Pixel counts per value for red, green, blue and luma (Rec. 709).
+    pub bins: [[u32; 256]; 4],
+    /// Pixels counted; fully transparent ones are left out.
+    pub total: u64,
+    /// Pixels with a channel at 255, and pixels that are pure black: what
+    /// the clipping warning marks on the image.
+    pub clipped_highlights: u64,
+    pub clipped_shadows: u64,
+}
+
+/// True if a framebuffer pixel `0x00RRGGBB` has a blown-out channel.
+#[inline]
+pub fn is_clipped_highlight(rgb: u32) -> bool {
+    rgb & 0xFF0000 == 0xFF0000 || rgb & 0xFF00 == 0xFF00 || rgb & 0xFF == 0xFF
+}
+
+/// True if a framebuffer pixel `0x00RRGGBB` is pure black.
+#[inline]
+pub fn is_clipped_shadow(rgb: u32) -> bool {
+    rgb & 0xFF_FFFF == 0
+}
+
+impl Histogram {
+    /// Count display pixels (see `DecodedImage::pixels`). Premultiplied
+    /// pixels are counted at their straight colour.
+    pub fn new(pixels: &[u32], opaque: bool) -> Self {
+        let mut hist = Histogram {
+            bins: [[0; 256]; 4],
+            total: 0,
+            clipped_highlights: 0,
+            clipped_shadows: 0,
+        };
+        for &p in pixels {
+            let a = if opaque { 255 } else { p >> 24 };
+            if a == 0 {
+                continue;
+            }
+            let unpremultiply = |c: u32| if a == 255 { c } else { (c * 255 / a).min(255) };
+            let r = unpremultiply((p >> 16) & 0xFF);
+            let g = unpremultiply((p >> 8) & 0xFF);
+            let b = unpremultiply(p & 0xFF);
+            let luma = (54 * r + 183 * g + 19 * b) >> 8;
+
+            hist.bins[RED][r as usize] += 1;
+            hist.bins[GREEN][g as usize] += 1;
+            hist.bins[BLUE][b as usize] += 1;
+            hist.bins[LUMA][luma as usize] += 1;
+            hist.total += 1;
+
+            let rgb = (r << 16) | (g << 8) | b;
+            hist.clipped_highlights += is_clipped_highlight(rgb) as u64;
+            hist.clipped_shadows += is_clipped_shadow(rgb) as u64;
+        }
+        hist
+    }
+
+    /// Tallest bin of any channel, leaving out the end bins so a large
+    /// clipped area doesn't flatten the rest of the graph.
+    pub fn peak(&self) -> u32 {
+        self.bins.iter().flat_map(|c| c[1..255].iter().copied()).max().unwrap_or(0).max(1)
+    }
+
+    /// Share of counted pixels, in percent.
+    pub fn percent(&self, count: u64) -> f64 {
+        if self.total == 0 { 0.0 } else { count as f64 * 100.0 / self.total as f64 }
+    }
+}
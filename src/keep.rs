@@ -0,0 +1,246 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::files::format_rank;
+use crate::loader::has_exif;
+
+// ---------------------------------------------------------------------------
+// Which file of a duplicate group to keep as the original
+// ---------------------------------------------------------------------------
+
+/// One criterion of `--keep`; each only breaks the ties of the ones before.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeepRule {
+    /// Most pixels
+    Resolution,
+    /// Largest file
+    Size,
+    /// RAW over lossless over lossy formats
+    Format,
+    /// Files with EXIF
+    Exif,
+    /// Oldest modification time
+    Oldest,
+    /// Newest modification time
+    Newest,
+    /// Files under this directory
+    Under(PathBuf),
+    /// Alphabetically first path
+    Name,
+}
+
+/// Ordered `--keep` criteria. Whatever they leave tied goes to the file
+/// first in scan order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeepPolicy {
+    rules: Vec<KeepRule>,
+}
+
+impl Default for KeepPolicy {
+    fn default() -> Self {
+        Self { rules: vec![KeepRule::Resolution] }
+    }
+}
+
+impl KeepPolicy {
+    /// Parse a comma separated list such as
+    /// "under:/archive/master,format,resolution,oldest".
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let rule = match part.to_lowercase().as_str() {
+                "resolution" => KeepRule::Resolution,
+                "size" => KeepRule::Size,
+                "format" => KeepRule::Format,
+                "exif" => KeepRule::Exif,
+                "oldest" => KeepRule::Oldest,
+                "newest" => KeepRule::Newest,
+                "name" => KeepRule::Name,
+                _ => match part.split_once(':') {
+                    Some((key, dir)) if key.eq_ignore_ascii_case("under") && !dir.is_empty() => {
+                        KeepRule::Under(PathBuf::from(dir))
+                    }
+                    _ => {
+                        return Err(format!(
+                            "unknown rule '{}' (expected resolution, size, format, exif, oldest, newest, name or under:DIR)",
+                            part
+                        ));
+                    }
+                },
+            };
+            rules.push(rule);
+        }
+        if rules.is_empty() {
+            return Err("no rules given".to_string());
+        }
+        Ok(Self { rules })
+    }
+
+    /// The rules as given, for logs and the dump header.
+    pub fn describe(&self) -> String {
+        let names: Vec<String> = self
+            .rules
+            .iter()
+            .map(|rule| match rule {
+                KeepRule::Resolution => "resolution".to_string(),
+                KeepRule::Size => "size".to_string(),
+                KeepRule::Format => "format".to_string(),
+                KeepRule::Exif => "exif".to_string(),
+                KeepRule::Oldest => "oldest".to_string(),
+                KeepRule::Newest => "newest".to_string(),
+                KeepRule::Under(dir) => format!("under:{}", dir.display()),
+                KeepRule::Name => "name".to_string(),
+            })
+            .collect();
+        names.join(",")
+    }
+
+    fn needs(&self, f: impl Fn(&KeepRule) -> bool) -> bool {
+        self.rules.iter().any(f)
+    }
+}
+
+/// What the rules look at, read once per file and only if a rule needs it.
+#[derive(Default)]
+struct Facts {
+    size: u64,
+    mtime: Option<SystemTime>,
+    exif: bool,
+    canonical: Option<PathBuf>,
+}
+
+/// Applies a `KeepPolicy` to groups of scanned files, identified by their
+/// position in `paths`.
+pub struct Keeper<'a> {
+    policy: &'a KeepPolicy,
+    paths: &'a [PathBuf],
+    /// `under:` directories resolved, to match canonical file paths too.
+    canonical_dirs: Vec<Option<PathBuf>>,
+    facts: HashMap<usize, Facts>,
+}
+
+impl<'a> Keeper<'a> {
+    pub fn new(policy: &'a KeepPolicy, paths: &'a [PathBuf]) -> Self {
+        let canonical_dirs = policy
+            .rules
+            .iter()
+            .map(|rule| match rule {
+                KeepRule::Under(dir) => fs::canonicalize(dir).ok(),
+                _ => None,
+            })
+            .collect();
+        Self { policy, paths, canonical_dirs, facts: HashMap::new() }
+    }
+
+    fn read_facts(&self, path: &Path) -> Facts {
+        let policy = self.policy;
+        let meta = if policy.needs(|r| matches!(r, KeepRule::Size | KeepRule::Oldest | KeepRule::Newest)) {
+            fs::metadata(path).ok()
+        } else {
+            None
+        };
+        Facts {
+            size: meta.as_ref().map_or(0, |m| m.len()),
+            mtime: meta.and_then(|m| m.modified().ok()),
+            exif: policy.needs(|r| *r == KeepRule::Exif) && has_exif(path),
+            canonical: if policy.needs(|r| matches!(r, KeepRule::Under(_))) {
+                fs::canonicalize(path).ok()
+            } else {
+                None
+            },
+        }
+    }
+
+    fn is_under(&self, item: usize, rule: usize, dir: &Path) -> bool {
+        self.paths[item].starts_with(dir)
+            || matches!(
+                (&self.facts[&item].canonical, &self.canonical_dirs[rule]),
+                (Some(path), Some(dir)) if path.starts_with(dir)
+            )
+    }
+
+    /// Less if `a` is the better file to keep.
+    fn compare(&self, a: usize, b: usize, pixels: &impl Fn(usize) -> u64) -> Ordering {
+        let (fa, fb) = (&self.facts[&a], &self.facts[&b]);
+        for (i, rule) in self.policy.rules.iter().enumerate() {
+            let order = match rule {
+                KeepRule::Resolution => pixels(b).cmp(&pixels(a)),
+                KeepRule::Size => fb.size.cmp(&fa.size),
+                KeepRule::Format => format_rank(&self.paths[b]).cmp(&format_rank(&self.paths[a])),
+                KeepRule::Exif => fb.exif.cmp(&fa.exif),
+                // Unknown times go last
+                KeepRule::Oldest => fa.mtime.is_none().cmp(&fb.mtime.is_none()).then(fa.mtime.cmp(&fb.mtime)),
+                KeepRule::Newest => fa.mtime.is_none().cmp(&fb.mtime.is_none()).then(fb.mtime.cmp(&fa.mtime)),
+                KeepRule::Under(dir) => self.is_under(b, i, dir).cmp(&self.is_under(a, i, dir)),
+                KeepRule::Name => self.paths[a].cmp(&self.paths[b]),
+            };
+            if order != Ordering::Equal {
+                return order;
+            }
+        }
+        a.cmp(&b)
+    }
+
+    /// The member of a group to keep, given each member's pixel count.
+    pub fn pick(&mut self, members: &[usize], pixels: impl Fn(usize) -> u64) -> usize {
+        for &m in members {
+            if !self.facts.contains_key(&m) {
+                let facts = self.read_facts(&self.paths[m]);
+                self.facts.insert(m, facts);
+            }
+        }
+        members
+            .iter()
+            .copied()
+            .min_by(|&a, &b| self.compare(a, b, &pixels))
+            .unwrap_or(members[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pick(rules: &str, paths: &[&str], pixels: &[u64]) -> usize {
+        let policy = KeepPolicy::parse(rules).unwrap();
+        let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+        let members: Vec<usize> = (0..paths.len()).collect();
+        Keeper::new(&policy, &paths).pick(&members, |m| pixels[m])
+    }
+
+    #[test]
+    fn parses_rules_in_order() {
+        let policy = KeepPolicy::parse(" Format, under:/archive/master ,resolution").unwrap();
+        assert_eq!(
+            policy.rules,
+            vec![KeepRule::Format, KeepRule::Under(PathBuf::from("/archive/master")), KeepRule::Resolution]
+        );
+        assert_eq!(policy.describe(), "format,under:/archive/master,resolution");
+        assert_eq!(KeepPolicy::default().describe(), "resolution");
+    }
+
+    #[test]
+    fn rejects_unknown_and_empty_rules() {
+        assert!(KeepPolicy::parse("biggest").is_err());
+        assert!(KeepPolicy::parse("under:").is_err());
+        assert!(KeepPolicy::parse(" , ").is_err());
+    }
+
+    #[test]
+    fn later_rules_only_break_ties() {
+        let paths = ["b/x.jpg", "a/x.png", "a/y.cr2"];
+        assert_eq!(pick("resolution", &paths, &[10, 20, 5]), 1);
+        assert_eq!(pick("format", &paths, &[10, 20, 5]), 2);
+        assert_eq!(pick("under:b,format", &paths, &[10, 20, 5]), 0);
+        assert_eq!(pick("under:a,resolution", &paths, &[10, 20, 30]), 2);
+        assert_eq!(pick("name", &paths, &[0, 0, 0]), 1);
+    }
+
+    #[test]
+    fn full_ties_go_to_the_first_in_scan_order() {
+        assert_eq!(pick("resolution", &["z.jpg", "a.jpg"], &[10, 10]), 0);
+    }
+}
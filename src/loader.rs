@@ -1,126 +1,724 @@
-use image::GenericImageView;
-use std::collections::{HashMap, HashSet};
+use image::{DynamicImage, GenericImageView};
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use std::{fmt, io};
 use winit::event_loop::EventLoopProxy;
 
+use crate::files::{is_raw_file, is_vector_file, missing_feature};
+use crate::histogram::Histogram;
+use crate::metadata::{read_metadata, ImageMetadata};
+use crate::tiles::{Tile, TiledImage, TILED_MIN_PIXELS};
+
 // ---------------------------------------------------------------------------
 // Decoded image data (CPU side, before GPU upload)
 // ---------------------------------------------------------------------------
 
-#[derive(Clone, Debug)]
-pub struct ImageMetadata {
-    pub make: Option<String>,
-    pub model: Option<String>,
-    pub datetime: Option<String>,
-    pub exposure_time: Option<String>,
-    pub f_number: Option<String>,
-    pub iso: Option<String>,
-    pub focal_length: Option<String>,
-    pub gps: Option<String>,
-}
-
 pub struct DecodedImage {
-    pub rgba_bytes: Vec<u8>,
+    /// Display-ready pixels, row-major. See `to_display_pixels`.
+    pub pixels: Vec<u32>,
+    /// No transparent pixels: `pixels` are plain `0x00RRGGBB` and can be
+    /// copied straight into the framebuffer.
+    pub opaque: bool,
     pub width: u32,
     pub height: u32,
     pub file_size: u64,
+    /// Modification time of the file when it was decoded, for detecting
+    /// edits made by other programs.
+    pub mtime: Option<SystemTime>,
     pub format_name: String,
-    pub metadata: Option<ImageMetadata>,
+    /// For vector images: raster pixels per intrinsic unit. The UI asks for
+    /// a re-render when the display scale drifts too far from it.
+    pub raster_scale: Option<f32>,
+    /// Which image of a multi-page/multi-frame file this is (0-based).
+    pub page: usize,
+    pub page_count: usize,
+    /// Gigapixel images: `pixels` is only an overview, and regions at full
+    /// resolution are read as tiles on demand.
+    pub tiled: Option<Arc<TiledImage>>,
+    /// Of `pixels`, for full decodes only (not thumbnails or previews).
+    pub histogram: Option<Box<Histogram>>,
 }
 
 impl DecodedImage {
     pub fn mem_size(&self) -> u64 {
-        self.rgba_bytes.len() as u64
+        self.pixels.len() as u64 * 4 + self.tiled.as_ref().map_or(0, |t| t.mem_size())
+    }
+
+    /// Size of the image itself; larger than `width` x `height` when tiled.
+    pub fn full_size(&self) -> (u32, u32) {
+        match &self.tiled {
+            Some(tiled) => (tiled.width, tiled.height),
+            None => (self.width, self.height),
+        }
+    }
+
+    /// True if `path` no longer matches the size/mtime recorded at decode
+    /// time (or can't be stat'ed at all).
+    pub fn is_stale(&self, path: &Path) -> bool {
+        file_changed(path, self.file_size, self.mtime)
+    }
+}
+
+/// Metadata of one file, read without decoding any pixels.
+pub struct FileMetadata {
+    /// None if the file has none, or couldn't be read.
+    pub metadata: Option<ImageMetadata>,
+    pub file_size: u64,
+    pub mtime: Option<SystemTime>,
+}
+
+impl FileMetadata {
+    /// See `DecodedImage::is_stale`.
+    pub fn is_stale(&self, path: &Path) -> bool {
+        file_changed(path, self.file_size, self.mtime)
+    }
+}
+
+fn file_changed(path: &Path, file_size: u64, mtime: Option<SystemTime>) -> bool {
+    match fs::metadata(path) {
+        Ok(m) => m.len() != file_size || m.modified().ok() != mtime,
+        Err(_) => true,
+    }
+}
+
+/// Convert to softbuffer's pixel layout. Opaque images become `0x00RRGGBB`;
+/// images with transparency carry alpha in the top byte and premultiplied
+/// RGB (`0xAARRGGBB`), so blending is a single multiply-add per channel.
+fn to_display_pixels(img: &DynamicImage) -> (Vec<u32>, bool) {
+    let pack = |r: u8, g: u8, b: u8| (r as u32) << 16 | (g as u32) << 8 | b as u32;
+
+    if !img.color().has_alpha() {
+        let rgb = img.to_rgb8();
+        let pixels = rgb.pixels().map(|p| pack(p[0], p[1], p[2])).collect();
+        return (pixels, true);
+    }
+
+    let rgba = img.to_rgba8();
+    let opaque = rgba.pixels().all(|p| p[3] == 255);
+    let pixels = rgba.pixels()
+        .map(|p| {
+            let a = p[3] as u32;
+            if opaque || a == 255 {
+                pack(p[0], p[1], p[2]) | if opaque { 0 } else { 0xFF00_0000 }
+            } else {
+                let premul = |c: u8| ((c as u32 * a + 127) / 255) as u8;
+                a << 24 | pack(premul(p[0]), premul(p[1]), premul(p[2]))
+            }
+        })
+        .collect();
+    (pixels, opaque)
+}
+
+// ---------------------------------------------------------------------------
+// Decode errors
+// ---------------------------------------------------------------------------
+
+/// Why a file could not be decoded. Only `Io` is considered transient and
+/// retried automatically; the rest need the file itself to change.
+#[derive(Clone, Debug)]
+pub enum DecodeError {
+    Io(String),
+    /// The file is gone or can't be opened; retrying won't help.
+    Access(String),
+    Unsupported(String),
+    Corrupt(String),
+    Limits(String),
+}
+
+impl DecodeError {
+    /// Short class name shown in the error overlay.
+    pub fn label(&self) -> &'static str {
+        match self {
+            DecodeError::Io(_) => "I/O error",
+            DecodeError::Access(_) => "not accessible",
+            DecodeError::Unsupported(_) => "unsupported format",
+            DecodeError::Corrupt(_) => "corrupt data",
+            DecodeError::Limits(_) => "resource limit",
+        }
+    }
+
+    pub fn is_transient(&self) -> bool {
+        matches!(self, DecodeError::Io(_))
     }
 }
 
-fn extract_metadata(path: &Path) -> Option<ImageMetadata> {
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Io(m)
+            | DecodeError::Access(m)
+            | DecodeError::Unsupported(m)
+            | DecodeError::Corrupt(m)
+            | DecodeError::Limits(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied => DecodeError::Access(e.to_string()),
+            _ => DecodeError::Io(e.to_string()),
+        }
+    }
+}
+
+impl From<image::ImageError> for DecodeError {
+    fn from(e: image::ImageError) -> Self {
+        use image::ImageError;
+        match e {
+            ImageError::IoError(e) => e.into(),
+            ImageError::Unsupported(e) => DecodeError::Unsupported(e.to_string()),
+            ImageError::Limits(e) => DecodeError::Limits(e.to_string()),
+            ImageError::Decoding(e) => DecodeError::Corrupt(e.to_string()),
+            ImageError::Parameter(e) => DecodeError::Corrupt(e.to_string()),
+            ImageError::Encoding(e) => DecodeError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+/// Automatic retries for transient errors, after the first failure.
+const MAX_AUTO_RETRIES: u32 = 4;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// A failed load plus its retry bookkeeping.
+#[derive(Clone, Debug)]
+pub struct FailedLoad {
+    pub path: PathBuf,
+    pub error: DecodeError,
+    pub attempts: u32,
+    /// When the next automatic retry may start. `None` = give up until the
+    /// user asks for a retry.
+    pub retry_at: Option<Instant>,
+}
+
+impl FailedLoad {
+    fn new(path: PathBuf, error: DecodeError, previous_attempts: u32) -> Self {
+        let attempts = previous_attempts + 1;
+        let retry_at = if error.is_transient() && attempts <= MAX_AUTO_RETRIES {
+            // 1s, 2s, 4s, ... capped
+            let delay = RETRY_BASE_DELAY
+                .saturating_mul(1 << (attempts - 1).min(16))
+                .min(RETRY_MAX_DELAY);
+            Some(Instant::now() + delay)
+        } else {
+            None
+        };
+        Self { path, error, attempts, retry_at }
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        self.retry_at.is_some_and(|t| t <= now)
+    }
+
+    /// Message for the error overlay.
+    pub fn describe(&self) -> String {
+        let mut msg = format!(
+            "Could not load [{}]: {}: {}",
+            self.error.label(),
+            self.path.display(),
+            self.error
+        );
+        if self.retry_at.is_some() {
+            msg.push_str(&format!(" (retrying, attempt {}/{})", self.attempts, MAX_AUTO_RETRIES + 1));
+        }
+        msg
+    }
+}
+
+fn read_exif(path: &Path) -> Option<exif::Exif> {
     let file = fs::File::open(path).ok()?;
     let mut bufreader = BufReader::new(file);
     let exifreader = exif::Reader::new();
-    let exif = exifreader.read_from_container(&mut bufreader).ok()?;
+    exifreader.read_from_container(&mut bufreader).ok()
+}
+
+/// Whether `path` carries EXIF. RAW files always do, though it may take
+/// reading the whole file to find it.
+pub fn has_exif(path: &Path) -> bool {
+    is_raw_file(path) || read_exif(path).is_some()
+}
+
+/// Decode the JPEG thumbnail embedded in the EXIF block (IFD1), if present.
+/// Much cheaper than a full decode, so it is used as a low-res stand-in
+/// while the real image loads.
+fn extract_embedded_preview(path: &Path) -> Option<DecodedImage> {
+    let exif = read_exif(path)?;
+    let offset = exif.get_field(exif::Tag::JPEGInterchangeFormat, exif::In::THUMBNAIL)?
+        .value.get_uint(0)? as usize;
+    let len = exif.get_field(exif::Tag::JPEGInterchangeFormatLength, exif::In::THUMBNAIL)?
+        .value.get_uint(0)? as usize;
+    let jpeg = exif.buf().get(offset..offset.checked_add(len)?)?;
+
+    let img = image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg).ok()?;
+    let (width, height) = img.dimensions();
+    let (pixels, opaque) = to_display_pixels(&img);
+    let file_meta = fs::metadata(path).ok()?;
+    Some(DecodedImage {
+        pixels,
+        opaque,
+        width,
+        height,
+        file_size: file_meta.len(),
+        mtime: file_meta.modified().ok(),
+        format_name: "JPEG (embedded)".to_string(),
+        raster_scale: None,
+        page: 0,
+        page_count: 1,
+        tiled: None,
+        histogram: None,
+    })
+}
+
+/// How much of a non-RAW file is searched for XMP and IPTC packets. They
+/// sit in the header of the formats that keep them outside EXIF (JPEG APP
+/// segments, PNG chunks ahead of the pixel data, ...).
+const METADATA_SCAN_BYTES: u64 = 1024 * 1024;
+
+/// How much of a RAW file metadata is read from. The IFDs, the CR3 "CMT1"
+/// box and the RAF preview (which carries the EXIF) all come before the
+/// sensor data; fields that point further in are left out.
+const RAW_METADATA_BYTES: u64 = 8 * 1024 * 1024;
+
+/// The first `limit` bytes of `path`, or all of it if shorter.
+fn read_head(path: &Path, limit: u64) -> Option<Vec<u8>> {
+    let mut head = Vec::new();
+    fs::File::open(path).and_then(|f| f.take(limit).read_to_end(&mut head)).ok()?;
+    Some(head)
+}
 
-    let get_field = |tag| {
-        exif.get_field(tag, exif::In::PRIMARY)
-            .map(|f| f.display_value().with_unit(&exif).to_string())
+/// Files whose metadata is kept at most, a few KB each. Past it the
+/// earliest read go first, and prefetching stops.
+const MAX_CACHED_METADATA: usize = 20_000;
+
+/// Read the metadata of `path` on its own, for the metadata cache. Only the
+/// head of the file is read, so this is cheap next to a decode.
+pub fn load_metadata(path: &Path) -> FileMetadata {
+    let file_meta = fs::metadata(path).ok();
+    let metadata = if is_raw_file(path) {
+        read_head(path, RAW_METADATA_BYTES)
+            .and_then(|head| read_metadata(crate::raw::read_exif(&head).as_ref(), Some(&head)))
+    } else {
+        let head = read_head(path, METADATA_SCAN_BYTES);
+        read_metadata(read_exif(path).as_ref(), head.as_deref())
     };
+    FileMetadata {
+        metadata,
+        file_size: file_meta.as_ref().map_or(0, |m| m.len()),
+        mtime: file_meta.and_then(|m| m.modified().ok()),
+    }
+}
 
-    let make = get_field(exif::Tag::Make);
-    let model = get_field(exif::Tag::Model);
-    let datetime = get_field(exif::Tag::DateTimeOriginal)
-        .or_else(|| get_field(exif::Tag::DateTime));
-    let exposure_time = get_field(exif::Tag::ExposureTime);
-    let f_number = get_field(exif::Tag::FNumber);
-    let iso = get_field(exif::Tag::PhotographicSensitivity);
-    let focal_length = get_field(exif::Tag::FocalLength);
-
-    // GPS logic
-    let lat_ref = get_field(exif::Tag::GPSLatitudeRef);
-    let lat = get_field(exif::Tag::GPSLatitude);
-    let lon_ref = get_field(exif::Tag::GPSLongitudeRef);
-    let lon = get_field(exif::Tag::GPSLongitude);
-    
-    let gps = match (lat, lat_ref, lon, lon_ref) {
-        (Some(lat), Some(lat_ref), Some(lon), Some(lon_ref)) => {
-            Some(format!("{} {}  {} {}", lat, lat_ref, lon, lon_ref))
-        },
-        _ => None,
+/// Wall time spent reading a file vs. decoding it, for adaptive pool sizing.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DecodeTiming {
+    pub io: Duration,
+    pub cpu: Duration,
+}
+
+/// Per-file choices the UI makes about what to decode. Thumbnails always
+/// use the defaults.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DecodeOptions {
+    /// Page/frame of a multi-image file.
+    pub page: usize,
+    /// Raster scale for vector images (None = pick a default).
+    pub raster_scale: Option<f32>,
+}
+
+/// Pixels from one of the decoders, plus how they were produced.
+struct Decoded {
+    img: DynamicImage,
+    page: usize,
+    page_count: usize,
+    raster_scale: Option<f32>,
+    /// RAW file shown via its embedded JPEG.
+    from_preview: bool,
+}
+
+impl Decoded {
+    fn single(img: DynamicImage) -> Self {
+        Self { img, page: 0, page_count: 1, raster_scale: None, from_preview: false }
+    }
+}
+
+/// Refuse files whose decoder is an optional feature that isn't compiled in,
+/// before reading them, with a message saying how to get it.
+fn check_compiled_in(path: &Path) -> Result<(), DecodeError> {
+    match missing_feature(path) {
+        Some(feature) => Err(DecodeError::Unsupported(format!(
+            "{} support not compiled in (build with --features {})",
+            feature.to_uppercase(),
+            feature
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Whether `decode_image` can decode `path` straight from the file. RAW,
+/// vector and HEIF decoders and page selection in multi-image containers
+/// (see `pages::select`) want it in memory.
+fn streams(path: &Path) -> bool {
+    #[cfg(feature = "heif")]
+    if crate::files::is_heif_file(path) {
+        return false;
+    }
+    use image::ImageFormat::{Dds, Ico, Tiff};
+    !is_raw_file(path)
+        && !is_vector_file(path)
+        && image::ImageFormat::from_path(path).is_ok_and(|format| !matches!(format, Tiff | Ico | Dds))
+}
+
+/// Reader that adds up the time spent waiting on `read`, to tell I/O from
+/// decoding in a streamed decode.
+struct TimedReader<R> {
+    inner: R,
+    elapsed: Duration,
+}
+
+impl<R> TimedReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, elapsed: Duration::ZERO }
+    }
+}
+
+impl<R: Read> Read for TimedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = Instant::now();
+        let result = self.inner.read(buf);
+        self.elapsed += start.elapsed();
+        result
+    }
+}
+
+impl<R: Seek> Seek for TimedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Decode a single-image raster file from `reader`, which `streams` allows.
+fn decode_stream(
+    path: &Path,
+    reader: impl BufRead + Seek,
+    max_alloc: Option<u64>,
+) -> Result<DynamicImage, DecodeError> {
+    let mut reader = image::ImageReader::new(reader);
+    reader.set_format(image::ImageFormat::from_path(path)?);
+    if let Some(max_alloc) = max_alloc {
+        let mut limits = image::Limits::default();
+        limits.max_alloc = Some(max_alloc);
+        reader.limits(limits);
+    }
+    Ok(reader.decode()?)
+}
+
+/// Decode a raster image from memory, picking the format from the extension
+/// like `image::open` does, else sniffing the content. For multi-image
+/// containers, `page` selects the image (clamped to the last one).
+/// `max_alloc` raises the decoder's allocation limit.
+fn decode_raster(path: &Path, bytes: &[u8], page: usize, max_alloc: Option<u64>) -> Result<Decoded, DecodeError> {
+    #[cfg(feature = "heif")]
+    if crate::files::is_heif_file(path) {
+        return crate::heif::decode(bytes).map(Decoded::single);
+    }
+    let format = match image::ImageFormat::from_path(path) {
+        Ok(format) => format,
+        Err(_) => image::ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()?
+            .format()
+            .ok_or_else(|| DecodeError::Unsupported("unrecognised image format".to_string()))?,
     };
 
-    Some(ImageMetadata {
-        make,
-        model,
-        datetime,
-        exposure_time,
-        f_number,
-        iso,
-        focal_length,
-        gps,
-    })
+    let (data, page, page_count) = match crate::pages::select(format, bytes, page) {
+        Some(selected) => (selected.data, selected.page, selected.count),
+        None => (Cow::Borrowed(bytes), 0, 1),
+    };
+    let mut reader = image::ImageReader::with_format(Cursor::new(&*data), format);
+    if let Some(max_alloc) = max_alloc {
+        let mut limits = image::Limits::default();
+        limits.max_alloc = Some(max_alloc);
+        reader.limits(limits);
+    }
+    let img = reader.decode()?;
+    Ok(Decoded { img, page, page_count, raster_scale: None, from_preview: false })
 }
 
-fn decode_image(path: &Path, target_size: Option<(u32, u32)>) -> Result<DecodedImage, String> {
-    let file_size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
-    
-    // Extract metadata before decoding (fail-soft)
-    let metadata = extract_metadata(path);
+/// Rasterise a vector image: to fit `target_size` for thumbnails, else at
+/// `raster_scale` (or a default). Returns the scale used alongside.
+#[cfg(feature = "svg")]
+fn render_vector(
+    bytes: &[u8],
+    target_size: Option<(u32, u32)>,
+    raster_scale: Option<f32>,
+) -> Result<(DynamicImage, f32), DecodeError> {
+    let tree = crate::svg::parse(bytes)?;
+    let scale = match (target_size, raster_scale) {
+        (Some(fit), _) => crate::svg::fit_scale(&tree, fit),
+        (None, Some(scale)) => scale,
+        (None, None) => crate::svg::default_scale(&tree),
+    };
+    crate::svg::render(&tree, scale)
+}
 
-    let img_result = image::open(path);
-    
-    match img_result {
-        Ok(img) => {
-            let format_name = path
-                .extension()
-                .and_then(|e| e.to_str())
-                .unwrap_or("unknown")
-                .to_uppercase();
-
-            let final_img = if let Some((w, h)) = target_size {
-                img.thumbnail(w, h)
-            } else {
-                img
-            };
-            
-            let (f_width, f_height) = final_img.dimensions();
-            let rgba = final_img.to_rgba8();
-            
-            Ok(DecodedImage {
-                rgba_bytes: rgba.into_raw(),
-                width: f_width,
-                height: f_height,
-                file_size,
-                format_name,
-                metadata,
-            })
+#[cfg(not(feature = "svg"))]
+fn render_vector(
+    _bytes: &[u8],
+    _target_size: Option<(u32, u32)>,
+    _raster_scale: Option<f32>,
+) -> Result<(DynamicImage, f32), DecodeError> {
+    Err(DecodeError::Unsupported("SVG support not compiled in (build with --features svg)".to_string()))
+}
+
+/// Decode a camera RAW file: developed from the sensor data for full views
+/// when the "raw" feature is on, else (and for thumbnails) the largest
+/// embedded preview. The flag tells whether the image was developed.
+#[cfg(feature = "raw")]
+fn decode_raw(
+    path: &Path,
+    bytes: &[u8],
+    exif: Option<&exif::Exif>,
+    full: bool,
+) -> Result<(DynamicImage, bool), DecodeError> {
+    if full {
+        match crate::raw::develop(path) {
+            Ok(img) => return Ok((img, true)),
+            Err(e) => log::warn!("Falling back to embedded preview for {}: {}", path.display(), e),
+        }
+    }
+    crate::raw::decode_preview(bytes, exif.and_then(crate::raw::orientation)).map(|img| (img, false))
+}
+
+#[cfg(not(feature = "raw"))]
+fn decode_raw(
+    _path: &Path,
+    bytes: &[u8],
+    exif: Option<&exif::Exif>,
+    _full: bool,
+) -> Result<(DynamicImage, bool), DecodeError> {
+    crate::raw::decode_preview(bytes, exif.and_then(crate::raw::orientation)).map(|img| (img, false))
+}
+
+/// Route `bytes` to the right decoder.
+fn decode_file(
+    path: &Path,
+    bytes: &[u8],
+    exif: Option<&exif::Exif>,
+    target_size: Option<(u32, u32)>,
+    options: DecodeOptions,
+) -> Result<Decoded, DecodeError> {
+    if is_vector_file(path) {
+        let (img, scale) = render_vector(bytes, target_size, options.raster_scale)?;
+        Ok(Decoded { raster_scale: Some(scale), ..Decoded::single(img) })
+    } else if is_raw_file(path) {
+        let (img, developed) = decode_raw(path, bytes, exif, target_size.is_none())?;
+        Ok(Decoded { from_preview: !developed, ..Decoded::single(img) })
+    } else {
+        decode_raster(path, bytes, options.page, None)
+    }
+}
+
+/// Load the first page of a file (or every page, if `all_pages`) and pass
+/// each to `visit`, for callers that want plain pixels (e.g. perceptual
+/// hashing). Pages are decoded one at a time to bound memory.
+pub fn for_each_page(
+    path: &Path,
+    all_pages: bool,
+    mut visit: impl FnMut(DynamicImage),
+) -> Result<(), DecodeError> {
+    check_compiled_in(path)?;
+    let bytes = fs::read(path)?;
+    let exif = if is_raw_file(path) { crate::raw::read_exif(&bytes) } else { None };
+    let first = decode_file(path, &bytes, exif.as_ref(), None, DecodeOptions::default())?;
+    let page_count = first.page_count;
+    visit(first.img);
+    if all_pages {
+        for page in 1..page_count {
+            visit(decode_raster(path, &bytes, page, None)?.img);
         }
-        Err(e) => Err(format!("{}", e)),
+    }
+    Ok(())
+}
+
+/// The image crate's allocation limit, which large images may exceed.
+const DEFAULT_MAX_ALLOC: u64 = 512 * 1024 * 1024;
+
+/// Size from the image header, without decoding or reading the rest of the
+/// file.
+fn header_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let reader = image::ImageReader::open(path).ok()?.with_guessed_format().ok()?;
+    reader.into_dimensions().ok()
+}
+
+/// What a tiled image shows first, and the image for full views.
+type TiledView = (Tile, Option<Arc<TiledImage>>);
+
+/// Display pixels for a tiled image: its overview for full views (keeping
+/// the tiles' source), or a small pyramid level for thumbnails.
+fn tiled_view(image: TiledImage, target_size: Option<(u32, u32)>) -> Result<TiledView, DecodeError> {
+    match target_size {
+        Some((w, h)) => Ok((image.thumbnail(w.max(h))?, None)),
+        None => Ok((image.overview()?, Some(Arc::new(image)))),
+    }
+}
+
+/// Display pixels for a large PNG or baseline JPEG read top to bottom (see
+/// `rows`). None if `path` can't be read that way, or if a full view is
+/// better decoded whole because it `fits_whole`: JPEG rows are 1/8 scale.
+fn decode_rows(
+    path: &Path,
+    target_size: Option<(u32, u32)>,
+    fits_whole: bool,
+    max_alloc: u64,
+) -> Result<Option<TiledView>, DecodeError> {
+    let Some(rows) = crate::rows::open(path)? else { return Ok(None) };
+    match target_size {
+        Some((w, h)) => Ok(Some((crate::tiles::thumbnail_from_rows(rows, w.max(h))?, None))),
+        None if fits_whole && rows.level() > 0 => Ok(None),
+        None => tiled_view(TiledImage::from_rows(rows, max_alloc)?, None).map(Some),
     }
 }
 
+/// Decode `path` for display. Images above `TILED_MIN_PIXELS` get an
+/// overview plus tiles: TIFFs are read piecemeal from disk, PNGs and JPEGs
+/// top to bottom, and other formats decoded whole if they fit in
+/// `max_alloc`, else refused before any pixels are allocated.
+fn decode_image(
+    path: &Path,
+    target_size: Option<(u32, u32)>,
+    options: DecodeOptions,
+    max_alloc: u64,
+) -> Result<(DecodedImage, DecodeTiming), DecodeError> {
+    check_compiled_in(path)?;
+
+    let io_start = Instant::now();
+    let file_meta = fs::metadata(path)?;
+    let file_size = file_meta.len();
+    let mtime = file_meta.modified().ok();
+    let mut format_name = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("unknown")
+        .to_uppercase();
+
+    // Very large TIFFs are read from disk chunk by chunk, never whole
+    let is_tiff = matches!(image::ImageFormat::from_path(path), Ok(image::ImageFormat::Tiff));
+    let tiled_tiff = if is_tiff && !is_raw_file(path) { crate::tiles::open_tiff(path, options.page) } else { None };
+
+    // Which other images are large, their header says
+    let header = if is_raw_file(path) || is_vector_file(path) { None } else { header_dimensions(path) };
+    let large = header.is_some_and(|(w, h)| w as u64 * h as u64 > TILED_MIN_PIXELS);
+    let max_alloc = max_alloc.max(DEFAULT_MAX_ALLOC);
+    let fits_whole = header.is_none_or(|(w, h)| w as u64 * h as u64 * 4 <= max_alloc);
+
+    let headers_read = io_start.elapsed();
+    let streamed = match tiled_tiff {
+        Some((tiled, page, page_count)) => Some((tiled_view(tiled, target_size)?, page, page_count)),
+        None if large => decode_rows(path, target_size, fits_whole, max_alloc)?.map(|view| (view, 0, 1)),
+        None => None,
+    };
+    if let Some(((view, tiled), page, page_count)) = streamed {
+        let histogram = target_size.is_none().then(|| Box::new(Histogram::new(&view.pixels, view.opaque)));
+        let image = DecodedImage {
+            pixels: view.pixels,
+            opaque: view.opaque,
+            width: view.width,
+            height: view.height,
+            file_size,
+            mtime,
+            format_name,
+            raster_scale: None,
+            page,
+            page_count,
+            tiled,
+            histogram,
+        };
+        // Reading and decoding interleave from here on; only the headers
+        // count as I/O
+        let cpu = io_start.elapsed().saturating_sub(headers_read);
+        return Ok((image, DecodeTiming { io: headers_read, cpu }));
+    }
+
+    // The rest of the large images are decoded whole, into the tiled view
+    if let Some((w, h)) = header.filter(|_| large && !fits_whole) {
+        return Err(DecodeError::Limits(format!(
+            "{}x{} {} image needs {} MB to decode, more than the cache budget; only TIFF, PNG and baseline JPEG files are read piecemeal",
+            w,
+            h,
+            format_name,
+            w as u64 * h as u64 * 4 / (1024 * 1024)
+        )));
+    }
+    let max_alloc = large.then_some(max_alloc);
+    let (decoded, io) = if streams(path) {
+        // Timed as it is read, so the file never sits in memory next to
+        // its pixels
+        let stat = io_start.elapsed();
+        let mut reader = BufReader::new(TimedReader::new(fs::File::open(path)?));
+        let img = decode_stream(path, &mut reader, max_alloc)?;
+        (Decoded::single(img), stat + reader.get_ref().elapsed)
+    } else {
+        // Read the whole file up front so I/O and decode time can be told apart
+        let bytes = fs::read(path)?;
+        let io = io_start.elapsed();
+
+        // Only RAW decoding needs EXIF (orientation, preview offsets); metadata
+        // is read by its own job
+        let exif = if is_raw_file(path) { crate::raw::read_exif(&bytes) } else { None };
+
+        let decoded = if large {
+            decode_raster(path, &bytes, options.page, max_alloc)?
+        } else {
+            decode_file(path, &bytes, exif.as_ref(), target_size, options)?
+        };
+        (decoded, io)
+    };
+
+    let (view, tiled, page, page_count, raster_scale) = if large {
+        let (view, tiled) = tiled_view(TiledImage::from_memory(decoded.img), target_size)?;
+        (view, tiled, decoded.page, decoded.page_count, None)
+    } else {
+        if decoded.from_preview {
+            format_name.push_str(" (embedded JPEG)");
+        }
+
+        let final_img = if let Some((w, h)) = target_size {
+            decoded.img.thumbnail(w, h)
+        } else {
+            decoded.img
+        };
+        let (width, height) = final_img.dimensions();
+        let (pixels, opaque) = to_display_pixels(&final_img);
+        let view = Tile { pixels, opaque, width, height };
+        (view, None, decoded.page, decoded.page_count, decoded.raster_scale)
+    };
+
+    let histogram = target_size.is_none().then(|| Box::new(Histogram::new(&view.pixels, view.opaque)));
+    let image = DecodedImage {
+        pixels: view.pixels,
+        opaque: view.opaque,
+        width: view.width,
+        height: view.height,
+        file_size,
+        mtime,
+        format_name,
+        raster_scale,
+        page,
+        page_count,
+        tiled,
+        histogram,
+    };
+    Ok((image, DecodeTiming { io, cpu: io_start.elapsed().saturating_sub(io) }))
+}
+
 // ---------------------------------------------------------------------------
 // Cache state (shared between UI and worker threads via Mutex + Condvar)
 // ---------------------------------------------------------------------------
@@ -135,20 +733,54 @@ pub enum ViewMode {
 pub enum WorkType {
     Full,
     Thumbnail,
+    /// Metadata only, no pixels.
+    Metadata,
+}
+
+/// A tile of the tiled image at `idx`: pyramid level and tile column/row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    pub idx: usize,
+    pub level: u32,
+    pub x: u32,
+    pub y: u32,
 }
 
 pub struct CacheState {
     pub current_idx: usize,
     pub mode: ViewMode,
+
+    // Recent browsing motion, reported by the UI with each move
+    /// +1 = forward, -1 = backward
+    pub nav_direction: i32,
+    /// Smoothed navigation speed in images per second
+    pub nav_speed: f32,
     
     // Caches
     pub images: HashMap<usize, Arc<DecodedImage>>,
     pub thumbnails: HashMap<usize, Arc<DecodedImage>>,
+    /// Thumbnails that are only a file's embedded preview, shown until the
+    /// real one is decoded.
+    provisional_thumbnails: HashSet<usize>,
+    /// Metadata by file, so a path listed twice is read once
+    metadata: HashMap<PathBuf, Arc<FileMetadata>>,
+    /// Cached metadata paths, earliest read first, for eviction.
+    metadata_order: VecDeque<PathBuf>,
+    /// Path of `current_idx`, whose metadata the overlays want first.
+    current_path: Option<PathBuf>,
+    /// Read metadata for the whole list once pixel work runs out, not just
+    /// for the current file.
+    pub prefetch_metadata: bool,
+    /// Next index to prefetch metadata for; the list is walked once, in order.
+    metadata_cursor: usize,
+    /// File to check against the disk next, and whether a check is running.
+    revalidate_next: Option<(usize, PathBuf)>,
+    revalidating: bool,
     
     // Work tracking
     pub in_progress: HashSet<(usize, WorkType)>,
-    pub errors: HashMap<usize, String>, // Full load errors
-    pub thumbnail_errors: HashSet<usize>, // Thumbnail load errors (simple set)
+    pub errors: HashMap<usize, FailedLoad>, // Full load errors
+    pub thumbnail_errors: HashMap<usize, FailedLoad>, // Thumbnail load errors
     
     // Budget
     pub used_bytes: u64,
@@ -157,6 +789,24 @@ pub struct CacheState {
     
     /// Indices that were decoded but couldn't be kept (cache full, too far).
     pub saturated: HashSet<usize>,
+
+    /// Non-default decode choices (page, vector raster scale), by index.
+    pub decode_options: HashMap<usize, DecodeOptions>,
+
+    // Tiles of gigapixel images, counted in `used_bytes`
+    pub tiles: HashMap<TileKey, Arc<Tile>>,
+    /// Tiles on screen in single view, most central first.
+    pub wanted_tiles: Vec<TileKey>,
+    tiles_in_progress: HashSet<TileKey>,
+    failed_tiles: HashSet<TileKey>,
+
+    // Worker pool sizing
+    /// Workers with an id at or above this stay idle.
+    pub active_workers: usize,
+    /// Pool size when the active count adapts to decode timings.
+    pub adaptive_workers: Option<usize>,
+    io_time_avg: f64,
+    cpu_time_avg: f64,
 }
 
 pub type SharedState = Arc<(Mutex<CacheState>, Condvar)>;
@@ -166,24 +816,64 @@ impl CacheState {
         Self {
             current_idx: 0,
             mode: ViewMode::Single,
+            nav_direction: 1,
+            nav_speed: 0.0,
             images: HashMap::new(),
             thumbnails: HashMap::new(),
+            provisional_thumbnails: HashSet::new(),
+            metadata: HashMap::new(),
+            metadata_order: VecDeque::new(),
+            current_path: None,
+            prefetch_metadata: false,
+            metadata_cursor: 0,
+            revalidate_next: None,
+            revalidating: false,
             in_progress: HashSet::new(),
             errors: HashMap::new(),
-            thumbnail_errors: HashSet::new(),
+            thumbnail_errors: HashMap::new(),
             used_bytes: 0,
             budget,
             file_count,
             saturated: HashSet::new(),
+            decode_options: HashMap::new(),
+            tiles: HashMap::new(),
+            wanted_tiles: Vec::new(),
+            tiles_in_progress: HashSet::new(),
+            failed_tiles: HashSet::new(),
+            active_workers: usize::MAX,
+            adaptive_workers: None,
+            io_time_avg: 0.0,
+            cpu_time_avg: 0.0,
         }
     }
 
-    pub fn set_current_idx(&mut self, idx: usize) {
+    /// Move to `idx`, the file at `path` once the list has reached it.
+    pub fn set_current_idx(&mut self, idx: usize, path: Option<PathBuf>, direction: i32, speed: f32) {
+        self.current_path = path;
+        if direction != 0 {
+            self.nav_direction = direction.signum();
+        }
+        self.nav_speed = speed;
         if idx != self.current_idx {
             self.current_idx = idx;
             self.saturated.clear();
         }
     }
+
+    /// Images prefetched in the direction of travel for each one behind.
+    /// 2:1 at rest, up to 8:1 while a navigation key is held at speed.
+    fn ahead_ratio(&self) -> usize {
+        (2.0 + self.nav_speed / 4.0).clamp(2.0, 8.0) as usize
+    }
+
+    /// Distance from `current_idx`, stretched by `ahead_ratio` for indices
+    /// behind the direction of travel so they're fetched later and evicted
+    /// sooner.
+    fn travel_dist(&self, idx: usize) -> usize {
+        let dist = idx.abs_diff(self.current_idx);
+        let ahead = if self.nav_direction >= 0 { idx >= self.current_idx } else { idx <= self.current_idx };
+        if ahead { dist } else { dist.saturating_mul(self.ahead_ratio()) }
+    }
     
     pub fn set_mode(&mut self, mode: ViewMode) {
         self.mode = mode;
@@ -199,6 +889,10 @@ impl CacheState {
         self.thumbnails.get(&idx).cloned()
     }
 
+    pub fn get_metadata(&self, path: &Path) -> Option<Arc<FileMetadata>> {
+        self.metadata.get(path).cloned()
+    }
+
     /// Average decoded image size in bytes (fallback: ~8 MB).
     fn avg_image_size(&self) -> u64 {
         if self.images.is_empty() {
@@ -212,34 +906,180 @@ impl CacheState {
         if idx >= self.file_count { return false; }
         if self.in_progress.contains(&(idx, wtype)) { return false; }
         
+        // A failed index is only workable again once its retry is due
+        let blocked_by_error = |errors: &HashMap<usize, FailedLoad>| {
+            errors.get(&idx).is_some_and(|f| !f.is_due(Instant::now()))
+        };
+
         match wtype {
             WorkType::Full => {
                 !self.images.contains_key(&idx) 
-                && !self.errors.contains_key(&idx)
+                && !blocked_by_error(&self.errors)
                 && !self.saturated.contains(&idx)
             },
             WorkType::Thumbnail => {
-                !self.thumbnails.contains_key(&idx)
-                && !self.thumbnail_errors.contains(&idx)
+                (!self.thumbnails.contains_key(&idx) || self.provisional_thumbnails.contains(&idx))
+                && !blocked_by_error(&self.thumbnail_errors)
+            }
+            // Only the current file's path is known here; prefetched ones
+            // are checked by the worker, see `read_file_metadata`
+            WorkType::Metadata => {
+                idx != self.current_idx || self.current_path.as_ref().is_some_and(|p| !self.metadata.contains_key(p))
             }
         }
     }
 
+    /// Record a failed decode, carrying over the attempt count from any
+    /// previous failure of the same index.
+    pub fn record_error(&mut self, idx: usize, wtype: WorkType, path: PathBuf, error: DecodeError) {
+        let errors = match wtype {
+            WorkType::Full => &mut self.errors,
+            WorkType::Thumbnail => &mut self.thumbnail_errors,
+            // Unreadable files just have no metadata
+            WorkType::Metadata => return,
+        };
+        let previous = errors.get(&idx).map(|f| f.attempts).unwrap_or(0);
+        let failed = FailedLoad::new(path, error, previous);
+        if let Some(at) = failed.retry_at {
+            log::debug!(
+                "Decode of {} failed ({}), retrying in {:.1}s",
+                failed.path.display(),
+                failed.error.label(),
+                at.saturating_duration_since(Instant::now()).as_secs_f64()
+            );
+        }
+        errors.insert(idx, failed);
+    }
+
+    /// Forget all failures for `idx` so it is loaded again on demand.
+    pub fn retry(&mut self, idx: usize) {
+        self.errors.remove(&idx);
+        self.thumbnail_errors.remove(&idx);
+        self.saturated.remove(&idx);
+    }
+
+    /// Drop everything cached for `idx` at `path` (image, thumbnail,
+    /// metadata, failures) so it is read again from disk.
+    pub fn invalidate(&mut self, idx: usize, path: &Path) {
+        if let Some(old) = self.images.remove(&idx) {
+            self.used_bytes -= old.mem_size();
+        }
+        self.drop_tiles(idx);
+        self.thumbnails.remove(&idx);
+        self.provisional_thumbnails.remove(&idx);
+        self.forget_metadata(path);
+        self.retry(idx);
+    }
+
+    fn forget_metadata(&mut self, path: &Path) {
+        if self.metadata.remove(path).is_some() {
+            self.metadata_order.retain(|p| p != path);
+        }
+    }
+
+    /// Ask for the cached image/thumbnail/metadata of `idx` to be checked
+    /// against `path` on disk. Returns true if no check is running, so the
+    /// caller must start one with `spawn_queued_revalidation`; otherwise the
+    /// running one picks it up, in place of any index still waiting.
+    pub fn queue_revalidation(&mut self, idx: usize, path: PathBuf) -> bool {
+        let idle = !self.revalidating;
+        self.revalidate_next = Some((idx, path));
+        self.revalidating = true;
+        idle
+    }
+
+    /// Decode options for full loads of `idx`.
+    pub fn options(&self, idx: usize) -> DecodeOptions {
+        self.decode_options.get(&idx).copied().unwrap_or_default()
+    }
+
+    /// Drop the full image of `idx` after its decode options changed. The
+    /// UI keeps showing its own copy until the replacement is ready.
+    fn drop_full(&mut self, idx: usize) {
+        if let Some(old) = self.images.remove(&idx) {
+            self.used_bytes -= old.mem_size();
+        }
+        self.drop_tiles(idx);
+        self.errors.remove(&idx);
+        self.saturated.remove(&idx);
+    }
+
+    /// Ask for vector image `idx` to be re-rasterised at `scale`.
+    /// Returns false if that scale (within 10%) was already requested.
+    pub fn request_raster_scale(&mut self, idx: usize, scale: f32) -> bool {
+        let options = self.decode_options.entry(idx).or_default();
+        if options.raster_scale.is_some_and(|s| (s / scale - 1.0).abs() < 0.1) {
+            return false;
+        }
+        options.raster_scale = Some(scale);
+        self.drop_full(idx);
+        true
+    }
+
+    /// Ask for page `page` of multi-page file `idx`. Returns false if that
+    /// page is already the one requested.
+    pub fn request_page(&mut self, idx: usize, page: usize) -> bool {
+        let options = self.decode_options.entry(idx).or_default();
+        if options.page == page {
+            return false;
+        }
+        options.page = page;
+        self.drop_full(idx);
+        true
+    }
+
+    /// Error overlay text for a failed full load, if any.
+    pub fn error_message(&self, idx: usize) -> Option<String> {
+        self.errors.get(&idx).map(|f| f.describe())
+    }
+
+    /// Earliest pending automatic retry that is still in the future.
+    /// Workers use it to bound their wait on the condvar.
+    pub fn next_retry(&self) -> Option<Instant> {
+        let now = Instant::now();
+        self.errors.values()
+            .chain(self.thumbnail_errors.values())
+            .filter_map(|f| f.retry_at)
+            .filter(|&t| t > now)
+            .min()
+    }
+
     fn get_farthest_cached(&self) -> Option<(usize, usize)> {
         self.images.keys()
             .filter(|&&i| i != self.current_idx)
-            .map(|&i| {
-                let dist = if i >= self.current_idx { i - self.current_idx } else { self.current_idx - i };
-                (i, dist)
-            })
+            .map(|&i| (i, self.travel_dist(i)))
             .max_by_key(|&(_, d)| d)
     }
 
     /// Find the nearest un-cached, non-in-progress index to current_idx.
     pub fn find_work(&self) -> Option<(usize, WorkType)> {
-        match self.mode {
+        // The overlays want the current file's metadata, and it's quick
+        if self.is_available(self.current_idx, WorkType::Metadata) {
+            return Some((self.current_idx, WorkType::Metadata));
+        }
+        let work = match self.mode {
             ViewMode::Single => self.find_work_single(),
             ViewMode::Grid => self.find_work_grid(),
+        };
+        work.or_else(|| self.find_work_metadata())
+    }
+
+    /// With `prefetch_metadata`, the next file in the list not yet handed
+    /// out, until the metadata cache is full.
+    fn find_work_metadata(&self) -> Option<(usize, WorkType)> {
+        let idx = self.metadata_cursor;
+        let open = self.prefetch_metadata
+            && idx < self.file_count
+            && self.metadata.len() < MAX_CACHED_METADATA
+            && !self.in_progress.contains(&(idx, WorkType::Metadata));
+        open.then_some((idx, WorkType::Metadata))
+    }
+
+    /// Note that a worker took `idx`, so the prefetch cursor moves past it.
+    pub fn start_work(&mut self, idx: usize, wtype: WorkType) {
+        self.in_progress.insert((idx, wtype));
+        if wtype == WorkType::Metadata && idx == self.metadata_cursor {
+            self.metadata_cursor += 1;
         }
     }
 
@@ -265,75 +1105,72 @@ impl CacheState {
         };
 
         const MAX_SCAN: usize = 2000; 
-        
-        let mut fwd_dist = 1;
-        let mut bwd_dist = 1;
-        let mut stop_fwd = false;
-        let mut stop_bwd = false;
 
-        let check_candidate = |idx: usize| -> Option<(usize, WorkType)> {
-            if self.saturated.contains(&idx) {
-                return None;
+        enum Probe {
+            Found(usize),
+            Stop,
+            Skip,
+        }
+
+        // Index `dist` steps ahead of (or behind) current_idx, in travel terms
+        let forward_is_ahead = self.nav_direction >= 0;
+        let index_at = |dist: usize, ahead: bool| -> Option<usize> {
+            if ahead == forward_is_ahead {
+                Some(self.current_idx + dist).filter(|&i| i < self.file_count)
+            } else {
+                self.current_idx.checked_sub(dist)
             }
-            if self.is_available(idx, WorkType::Full) {
-                let dist = if idx >= self.current_idx { idx - self.current_idx } else { self.current_idx - idx };
-                if !over_budget || dist < farthest_dist {
-                     return Some((idx, WorkType::Full));
+        };
+
+        let probe = |idx: Option<usize>| -> Probe {
+            let Some(idx) = idx else { return Probe::Stop };
+            if self.saturated.contains(&idx) {
+                Probe::Stop
+            } else if self.is_available(idx, WorkType::Full) {
+                // Worth loading only if it beats the farthest cached image
+                if !over_budget || self.travel_dist(idx) < farthest_dist {
+                    Probe::Found(idx)
+                } else {
+                    Probe::Stop
                 }
+            } else {
+                // Already cached, loading, or failed
+                Probe::Skip
             }
-            None
         };
 
-        // 1. Immediate neighbors
-        if fwd_dist < self.file_count {
-            let idx = self.current_idx + fwd_dist;
-            if idx < self.file_count {
-                if let Some(res) = check_candidate(idx) { return Some(res); }
+        // 1. Immediate neighbors, direction of travel first
+        for ahead in [true, false] {
+            if let Probe::Found(idx) = probe(index_at(1, ahead)) {
+                return Some((idx, WorkType::Full));
             }
-            fwd_dist += 1;
         }
-        
-        if bwd_dist <= self.current_idx {
-            let idx = self.current_idx - bwd_dist;
-             if let Some(res) = check_candidate(idx) { return Some(res); }
-            bwd_dist += 1;
-        }
-
-        // 2. Loop with bias
-        while (!stop_fwd && fwd_dist < MAX_SCAN) || (!stop_bwd && bwd_dist < MAX_SCAN) {
-             // 2 Forward
-            for _ in 0..2 {
-                if stop_fwd { break; }
-                let idx = self.current_idx + fwd_dist;
-                if idx >= self.file_count {
-                    stop_fwd = true;
-                } else {
-                    if self.saturated.contains(&idx) {
-                        stop_fwd = true;
-                    } else if let Some(res) = check_candidate(idx) {
-                        return Some(res);
-                    } else if self.is_available(idx, WorkType::Full) {
-                        stop_fwd = true;
-                    }
+
+        // 2. Loop with bias: `ratio` steps ahead for every step behind
+        let ratio = self.ahead_ratio();
+        let mut ahead_dist = 2;
+        let mut behind_dist = 2;
+        let mut stop_ahead = false;
+        let mut stop_behind = false;
+
+        while (!stop_ahead && ahead_dist < MAX_SCAN) || (!stop_behind && behind_dist < MAX_SCAN) {
+            for _ in 0..ratio {
+                if stop_ahead { break; }
+                match probe(index_at(ahead_dist, true)) {
+                    Probe::Found(idx) => return Some((idx, WorkType::Full)),
+                    Probe::Stop => stop_ahead = true,
+                    Probe::Skip => {}
                 }
-                fwd_dist += 1;
+                ahead_dist += 1;
             }
 
-            // 1 Backward
-            if !stop_bwd {
-                if bwd_dist > self.current_idx {
-                     stop_bwd = true;
-                } else {
-                    let idx = self.current_idx - bwd_dist;
-                    if self.saturated.contains(&idx) {
-                        stop_bwd = true;
-                    } else if let Some(res) = check_candidate(idx) {
-                        return Some(res);
-                    } else if self.is_available(idx, WorkType::Full) {
-                        stop_bwd = true;
-                    }
-                    bwd_dist += 1;
+            if !stop_behind {
+                match probe(index_at(behind_dist, false)) {
+                    Probe::Found(idx) => return Some((idx, WorkType::Full)),
+                    Probe::Stop => stop_behind = true,
+                    Probe::Skip => {}
                 }
+                behind_dist += 1;
             }
         }
         
@@ -353,12 +1190,11 @@ impl CacheState {
         for i in 0..limit {
             // 1. Backward (current - i)
             // We check this first to prioritize the "top" of the view (reading order)
-            if i > 0 {
-                if let Some(bwd) = self.current_idx.checked_sub(i) {
-                    if self.is_available(bwd, WorkType::Thumbnail) {
-                        return Some((bwd, WorkType::Thumbnail));
-                    }
-                }
+            if i > 0
+                && let Some(bwd) = self.current_idx.checked_sub(i)
+                && self.is_available(bwd, WorkType::Thumbnail)
+            {
+                return Some((bwd, WorkType::Thumbnail));
             }
 
             // 2. Forward (current + i)
@@ -366,10 +1202,8 @@ impl CacheState {
             // Only check if within bounds. 
             // If fwd is out of bounds, we still continue the loop because 'bwd' might still be valid
             // (e.g. if we are at the end of the list, we need to scan backwards to 0).
-            if fwd < self.file_count {
-                if self.is_available(fwd, WorkType::Thumbnail) {
-                    return Some((fwd, WorkType::Thumbnail));
-                }
+            if fwd < self.file_count && self.is_available(fwd, WorkType::Thumbnail) {
+                return Some((fwd, WorkType::Thumbnail));
             }
         }
 
@@ -382,12 +1216,8 @@ impl CacheState {
             WorkType::Full => {
                 // Budget check only for full images for now
                 if idx != self.current_idx && self.used_bytes + decoded.mem_size() > self.budget {
-                    let my_dist = if idx >= self.current_idx { idx - self.current_idx } else { self.current_idx - idx };
-                    let farthest_dist = self.images.keys()
-                        .filter(|&&i| i != self.current_idx)
-                        .map(|&i| if i >= self.current_idx { i - self.current_idx } else { self.current_idx - i })
-                        .max()
-                        .unwrap_or(0);
+                    let my_dist = self.travel_dist(idx);
+                    let farthest_dist = self.get_farthest_cached().map(|(_, d)| d).unwrap_or(0);
                     
                     if my_dist >= farthest_dist {
                         self.saturated.insert(idx);
@@ -398,6 +1228,7 @@ impl CacheState {
                 if let Some(old) = self.images.remove(&idx) {
                     self.used_bytes -= old.mem_size();
                 }
+                self.drop_tiles(idx);
                 self.used_bytes += decoded.mem_size();
                 self.images.insert(idx, Arc::new(decoded));
                 self.evict_distant();
@@ -407,32 +1238,159 @@ impl CacheState {
                 // A thumbnail is ~200x200x4 = 160KB. 1000 thumbnails = 160MB. 
                 // We should probably limit them too, but let's assume they fit for now.
                 self.thumbnails.insert(idx, Arc::new(decoded));
+                self.provisional_thumbnails.remove(&idx);
                 
                 // Optional: evict very far thumbnails if memory is tight?
                 // For now, let's keep them to ensure smooth scrolling.
             }
+            // Metadata has no pixels; see `insert_metadata`
+            WorkType::Metadata => {}
+        }
+    }
+
+    /// Stand `preview` in for the thumbnail of `idx` until the real one is
+    /// decoded, unless there already is one.
+    pub fn insert_preview(&mut self, idx: usize, preview: DecodedImage) {
+        if let Entry::Vacant(slot) = self.thumbnails.entry(idx) {
+            slot.insert(Arc::new(preview));
+            self.provisional_thumbnails.insert(idx);
         }
     }
 
+    pub fn insert_metadata(&mut self, path: PathBuf, metadata: FileMetadata) {
+        if self.metadata.insert(path.clone(), Arc::new(metadata)).is_none() {
+            self.metadata_order.push_back(path);
+        }
+        while self.metadata.len() > MAX_CACHED_METADATA {
+            let Some(oldest) = self.metadata_order.pop_front() else { break };
+            self.metadata.remove(&oldest);
+        }
+    }
+
+    /// Feed one decode's I/O/CPU split into adaptive pool sizing. Mostly
+    /// CPU-bound work gets the whole pool; the more time goes to waiting on
+    /// the disk, the fewer parallel readers we keep (they'd only make a
+    /// spinning disk seek more).
+    pub fn record_timing(&mut self, timing: DecodeTiming) {
+        let Some(max_workers) = self.adaptive_workers else { return };
+
+        const SMOOTHING: f64 = 0.2;
+        self.io_time_avg += SMOOTHING * (timing.io.as_secs_f64() - self.io_time_avg);
+        self.cpu_time_avg += SMOOTHING * (timing.cpu.as_secs_f64() - self.cpu_time_avg);
+
+        let total = self.io_time_avg + self.cpu_time_avg;
+        if total <= 0.0 {
+            return;
+        }
+        let cpu_share = self.cpu_time_avg / total;
+        let target = ((max_workers as f64 * cpu_share).ceil() as usize)
+            .clamp(MIN_ADAPTIVE_WORKERS.min(max_workers), max_workers);
+        if target != self.active_workers.min(max_workers) {
+            log::debug!(
+                "Adaptive workers: {} -> {} (io {:.0}ms, cpu {:.0}ms per image)",
+                self.active_workers.min(max_workers),
+                target,
+                self.io_time_avg * 1000.0,
+                self.cpu_time_avg * 1000.0
+            );
+            self.active_workers = target;
+        }
+    }
+
+    /// Change the budget, evicting distant images if it shrank. When it grows,
+    /// previously saturated indices become eligible for prefetch again.
+    pub fn set_budget(&mut self, budget: u64) {
+        if budget > self.budget {
+            self.saturated.clear();
+        }
+        self.budget = budget;
+        self.evict_distant();
+    }
+
     fn evict_distant(&mut self) {
+        // Tiles scrolled off screen go first
+        if self.used_bytes > self.budget {
+            let unwanted: Vec<TileKey> = self.tiles.keys()
+                .filter(|k| !self.wanted_tiles.contains(k))
+                .copied()
+                .collect();
+            for key in unwanted {
+                if self.used_bytes <= self.budget {
+                    break;
+                }
+                if let Some(tile) = self.tiles.remove(&key) {
+                    self.used_bytes -= tile.mem_size();
+                }
+            }
+        }
+
         while self.used_bytes > self.budget && self.images.len() > 1 {
-            let farthest = self.images.keys()
-                .filter(|&&idx| idx != self.current_idx)
-                .max_by_key(|&&idx| {
-                    if idx >= self.current_idx { idx - self.current_idx } else { self.current_idx - idx }
-                })
-                .copied();
-            
-            match farthest {
-                Some(evict_idx) => {
+            match self.get_farthest_cached() {
+                Some((evict_idx, _)) => {
                     if let Some(img) = self.images.remove(&evict_idx) {
                         self.used_bytes -= img.mem_size();
                     }
+                    self.drop_tiles(evict_idx);
                 }
                 None => break,
             }
         }
     }
+
+    // -- Tiles ---------------------------------------------------------------
+
+    pub fn get_tile(&self, key: &TileKey) -> Option<Arc<Tile>> {
+        self.tiles.get(key).cloned()
+    }
+
+    /// Replace the tiles the UI wants. Returns true if they changed (and
+    /// workers should be woken).
+    pub fn set_wanted_tiles(&mut self, wanted: Vec<TileKey>) -> bool {
+        if wanted == self.wanted_tiles {
+            return false;
+        }
+        self.wanted_tiles = wanted;
+        true
+    }
+
+    /// Claim the most central wanted tile that still needs reading.
+    fn take_tile_work(&mut self) -> Option<(TileKey, Arc<TiledImage>)> {
+        let (key, image) = self.wanted_tiles.iter().find_map(|key| {
+            let busy = self.tiles.contains_key(key)
+                || self.tiles_in_progress.contains(key)
+                || self.failed_tiles.contains(key);
+            let image = self.images.get(&key.idx)?.tiled.as_ref()?;
+            (!busy).then(|| (*key, Arc::clone(image)))
+        })?;
+        self.tiles_in_progress.insert(key);
+        Some((key, image))
+    }
+
+    /// Store a tile read from `image`, unless that image has been replaced
+    /// or dropped meanwhile.
+    fn insert_tile(&mut self, key: TileKey, tile: Tile, image: &Arc<TiledImage>) {
+        let current = self.images.get(&key.idx).and_then(|dec| dec.tiled.as_ref());
+        if !current.is_some_and(|c| Arc::ptr_eq(c, image)) {
+            return;
+        }
+        self.used_bytes += tile.mem_size();
+        if let Some(old) = self.tiles.insert(key, Arc::new(tile)) {
+            self.used_bytes -= old.mem_size();
+        }
+        self.evict_distant();
+    }
+
+    fn drop_tiles(&mut self, idx: usize) {
+        let used_bytes = &mut self.used_bytes;
+        self.tiles.retain(|key, tile| {
+            let keep = key.idx != idx;
+            if !keep {
+                *used_bytes -= tile.mem_size();
+            }
+            keep
+        });
+        self.failed_tiles.retain(|key| key.idx != idx);
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -443,37 +1401,282 @@ impl CacheState {
 pub enum UserEvent {
     ImageReady(usize),
     ThumbnailReady(usize),
+    TileReady(usize),
+    MetadataReady(usize),
     FileListUpdated,
 }
 
+// ---------------------------------------------------------------------------
+// Revalidation of cached files against disk
+// ---------------------------------------------------------------------------
+
+/// Stat every cached file in the background and invalidate the ones that
+/// changed since they were decoded. Workers pick the reloads up as usual.
+pub fn spawn_revalidation(shared: SharedState, files: Arc<RwLock<Vec<PathBuf>>>) {
+    thread::spawn(move || {
+        // Snapshot under the lock, stat without it (may be slow on NFS)
+        let (cached, cached_metadata) = {
+            let (lock, _) = &*shared;
+            let state = lock.lock().unwrap();
+            let images: Vec<(usize, Arc<DecodedImage>)> = state.images.iter()
+                .chain(state.thumbnails.iter().filter(|(i, _)| !state.images.contains_key(i)))
+                .map(|(&i, dec)| (i, Arc::clone(dec)))
+                .collect();
+            let metadata: Vec<(PathBuf, Arc<FileMetadata>)> = state.metadata.iter()
+                .map(|(p, meta)| (p.clone(), Arc::clone(meta)))
+                .collect();
+            (images, metadata)
+        };
+
+        let changed: Vec<(usize, PathBuf)> = {
+            let guard = files.read().unwrap();
+            cached.iter()
+                .filter_map(|(i, dec)| guard.get(*i).filter(|p| dec.is_stale(p)).map(|p| (*i, p.clone())))
+                .collect()
+        };
+        let stale_metadata: Vec<PathBuf> = cached_metadata.into_iter()
+            .filter(|(p, meta)| meta.is_stale(p))
+            .map(|(p, _)| p)
+            .collect();
+
+        if changed.is_empty() && stale_metadata.is_empty() {
+            return;
+        }
+        log::info!("{} cached file(s) changed on disk, reloading", changed.len() + stale_metadata.len());
+
+        let (lock, cvar) = &*shared;
+        let mut state = lock.lock().unwrap();
+        for (idx, path) in changed {
+            state.invalidate(idx, &path);
+        }
+        for path in stale_metadata {
+            state.forget_metadata(&path);
+        }
+        cvar.notify_all();
+    });
+}
+
+/// Check the files queued with `CacheState::queue_revalidation` one at a
+/// time, until none are left, so navigation never stats on the UI thread.
+pub fn spawn_queued_revalidation(shared: SharedState) {
+    thread::spawn(move || {
+        let (lock, cvar) = &*shared;
+        loop {
+            // Snapshot under the lock, stat without it (may be slow on NFS)
+            let (idx, path, cached, metadata) = {
+                let mut state = lock.lock().unwrap();
+                let Some((idx, path)) = state.revalidate_next.take() else {
+                    state.revalidating = false;
+                    return;
+                };
+                let cached = state.images.get(&idx).or_else(|| state.thumbnails.get(&idx)).map(Arc::clone);
+                let metadata = state.metadata.get(&path).map(Arc::clone);
+                (idx, path, cached, metadata)
+            };
+            let stale = match (cached, metadata) {
+                (Some(dec), _) => dec.is_stale(&path),
+                (None, Some(meta)) => meta.is_stale(&path),
+                (None, None) => false,
+            };
+            if stale {
+                log::info!("{} changed on disk, reloading", path.display());
+                lock.lock().unwrap().invalidate(idx, &path);
+                cvar.notify_all();
+            }
+        }
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Adaptive memory budget
+// ---------------------------------------------------------------------------
+
+const MEMORY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Never shrink the cache below this, or single view stops working.
+const MIN_ADAPTIVE_BUDGET: u64 = 64 * 1024 * 1024;
+
+/// Watch system memory and resize the cache budget between
+/// `MIN_ADAPTIVE_BUDGET` and `ceiling`, keeping ~10% of RAM free for
+/// everything else.
+pub fn spawn_memory_monitor(shared: SharedState, ceiling: u64) {
+    thread::spawn(move || {
+        let mut sys = sysinfo::System::new();
+        loop {
+            sys.refresh_memory();
+            let reserve = sys.total_memory() / 10;
+            let available = sys.available_memory();
+
+            let (lock, cvar) = &*shared;
+            let mut state = lock.lock().unwrap();
+            // What the cache could occupy if it took everything above the reserve
+            let target = (state.used_bytes + available)
+                .saturating_sub(reserve)
+                .clamp(MIN_ADAPTIVE_BUDGET.min(ceiling), ceiling);
+
+            // Ignore small wiggles so we don't churn the cache
+            let change = target.abs_diff(state.budget);
+            if change > state.budget / 20 {
+                log::debug!(
+                    "Adaptive budget: {:.0} -> {:.0} MB (available {:.0} MB)",
+                    state.budget as f64 / (1024.0 * 1024.0),
+                    target as f64 / (1024.0 * 1024.0),
+                    available as f64 / (1024.0 * 1024.0)
+                );
+                state.set_budget(target);
+                cvar.notify_all();
+            }
+            drop(state);
+
+            thread::sleep(MEMORY_POLL_INTERVAL);
+        }
+    });
+}
+
 // ---------------------------------------------------------------------------
 // Background decode workers
 // ---------------------------------------------------------------------------
 
+/// Adaptive sizing never drops below this many active workers.
+const MIN_ADAPTIVE_WORKERS: usize = 2;
+
+/// Drop the calling thread to nice 10 and the lowest best-effort I/O
+/// priority, so decoding yields to compilers and other disk users.
+#[cfg(target_os = "linux")]
+fn lower_thread_priority() {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    // SAFETY: these calls only take integers and affect the calling thread.
+    unsafe {
+        let tid = libc::gettid();
+        if libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, 10) != 0 {
+            log::warn!("Could not lower worker CPU priority: {}", io::Error::last_os_error());
+        }
+        let ioprio = (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | 7;
+        if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, ioprio) != 0 {
+            log::warn!("Could not lower worker I/O priority: {}", io::Error::last_os_error());
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lower_thread_priority() {
+    log::warn!("Lowering worker priority is only supported on Linux");
+}
+
+/// What a worker picked up: a tile of the image on screen, a file's
+/// metadata, or a decode.
+enum Job {
+    Tile(TileKey, Arc<TiledImage>),
+    Metadata(usize),
+    Decode(usize, WorkType, DecodeOptions, u64),
+}
+
+/// Read one tile and hand it to the cache. Failed tiles aren't retried; the
+/// overview shows through instead.
+fn read_tile(shared: &SharedState, proxy: &EventLoopProxy<UserEvent>, key: TileKey, image: Arc<TiledImage>) {
+    let result = image.read_tile(key.level, key.x, key.y);
+    let (lock, cvar) = &**shared;
+    let mut state = lock.lock().unwrap();
+    state.tiles_in_progress.remove(&key);
+    match result {
+        Ok(tile) => state.insert_tile(key, tile, &image),
+        Err(e) => {
+            log::warn!("Could not read tile {:?}: {}", key, e);
+            state.failed_tiles.insert(key);
+        }
+    }
+    cvar.notify_all();
+    drop(state);
+    let _ = proxy.send_event(UserEvent::TileReady(key.idx));
+}
+
+/// Read the metadata of file `idx` into the cache.
+fn read_file_metadata(
+    shared: &SharedState,
+    files: &RwLock<Vec<PathBuf>>,
+    proxy: &EventLoopProxy<UserEvent>,
+    idx: usize,
+) {
+    let path = files.read().unwrap().get(idx).cloned();
+    // Prefetch hands out every index; skip the files already read
+    let cached = path.as_ref().is_some_and(|p| {
+        let (lock, _) = &**shared;
+        lock.lock().unwrap().get_metadata(p).is_some()
+    });
+    let metadata = path.filter(|_| !cached).map(|p| {
+        let metadata = load_metadata(&p);
+        (p, metadata)
+    });
+    let (lock, cvar) = &**shared;
+    let mut state = lock.lock().unwrap();
+    state.in_progress.remove(&(idx, WorkType::Metadata));
+    if let Some((path, metadata)) = metadata {
+        state.insert_metadata(path, metadata);
+    }
+    cvar.notify_all();
+    drop(state);
+    let _ = proxy.send_event(UserEvent::MetadataReady(idx));
+}
+
 pub fn spawn_decode_workers(
     shared: SharedState,
     files: Arc<RwLock<Vec<PathBuf>>>,
     proxy: EventLoopProxy<UserEvent>,
     num_threads: usize,
+    low_priority: bool,
 ) {
-    for _ in 0..num_threads {
+    for worker_id in 0..num_threads {
         let shared = Arc::clone(&shared);
         let files = Arc::clone(&files);
         let proxy = proxy.clone();
         thread::spawn(move || {
+            if low_priority {
+                lower_thread_priority();
+            }
             loop {
-                // Wait for work
-                let (idx, wtype) = {
+                // Wait for work; tiles of the image on screen come first
+                let job = {
                     let (lock, cvar) = &*shared;
                     let mut state = lock.lock().unwrap();
                     loop {
-                        if let Some((idx, wtype)) = state.find_work() {
-                            state.in_progress.insert((idx, wtype));
-                            break (idx, wtype);
+                        if worker_id < state.active_workers {
+                            if let Some((key, image)) = state.take_tile_work() {
+                                break Job::Tile(key, image);
+                            }
+                            if let Some((idx, wtype)) = state.find_work() {
+                                state.start_work(idx, wtype);
+                                if wtype == WorkType::Metadata {
+                                    break Job::Metadata(idx);
+                                }
+                                let options = match wtype {
+                                    WorkType::Full => state.options(idx),
+                                    WorkType::Thumbnail | WorkType::Metadata => DecodeOptions::default(),
+                                };
+                                break Job::Decode(idx, wtype, options, state.budget);
+                            }
                         }
-                        state = cvar.wait(state).unwrap();
+                        // Wake up by ourselves when a retry comes due
+                        state = match state.next_retry() {
+                            Some(at) => {
+                                let timeout = at.saturating_duration_since(Instant::now());
+                                cvar.wait_timeout(state, timeout).unwrap().0
+                            }
+                            None => cvar.wait(state).unwrap(),
+                        };
                     }
                 };
+                let (idx, wtype, options, budget) = match job {
+                    Job::Tile(key, image) => {
+                        read_tile(&shared, &proxy, key, image);
+                        continue;
+                    }
+                    Job::Metadata(idx) => {
+                        read_file_metadata(&shared, &files, &proxy, idx);
+                        continue;
+                    }
+                    Job::Decode(idx, wtype, options, budget) => (idx, wtype, options, budget),
+                };
 
                 let path_opt = {
                     let guard = files.read().unwrap();
@@ -485,40 +1688,57 @@ pub fn spawn_decode_workers(
                 };
 
                 if let Some(path) = path_opt {
+                    // The user is waiting on this one: put up the embedded
+                    // preview (if any) while the full decode runs.
+                    let want_preview = wtype == WorkType::Full && {
+                        let (lock, _) = &*shared;
+                        let state = lock.lock().unwrap();
+                        idx == state.current_idx && !state.thumbnails.contains_key(&idx)
+                    };
+                    if want_preview && let Some(preview) = extract_embedded_preview(&path) {
+                        let (lock, _) = &*shared;
+                        lock.lock().unwrap().insert_preview(idx, preview);
+                        let _ = proxy.send_event(UserEvent::ThumbnailReady(idx));
+                    }
+
                     // Decide size
                     let target_size = match wtype {
-                        WorkType::Full => None,
                         WorkType::Thumbnail => Some((200, 200)), // Fixed thumbnail size
+                        _ => None,
                     };
 
-                    let result = decode_image(&path, target_size);
+                    let result = decode_image(&path, target_size, options, budget);
 
                     {
                         let (lock, cvar) = &*shared;
                         let mut state = lock.lock().unwrap();
                         state.in_progress.remove(&(idx, wtype));
+
+                        // The UI asked for a different page/scale meanwhile
+                        if wtype == WorkType::Full && state.options(idx) != options {
+                            cvar.notify_all();
+                            continue;
+                        }
                         
                         match result {
-                            Ok(decoded) => {
+                            Ok((decoded, timing)) => {
+                                state.record_timing(timing);
+                                match wtype {
+                                    WorkType::Thumbnail => { state.thumbnail_errors.remove(&idx); }
+                                    _ => { state.errors.remove(&idx); }
+                                }
                                 state.insert(idx, decoded, wtype);
                             }
                             Err(e) => {
-                                match wtype {
-                                    WorkType::Full => {
-                                        state.errors.insert(idx, format!("{}: {}", path.display(), e));
-                                    }
-                                    WorkType::Thumbnail => {
-                                        state.thumbnail_errors.insert(idx);
-                                    }
-                                }
+                                state.record_error(idx, wtype, path.clone(), e);
                             }
                         }
                         cvar.notify_all();
                     }
 
                     match wtype {
-                        WorkType::Full => { let _ = proxy.send_event(UserEvent::ImageReady(idx)); },
                         WorkType::Thumbnail => { let _ = proxy.send_event(UserEvent::ThumbnailReady(idx)); },
+                        _ => { let _ = proxy.send_event(UserEvent::ImageReady(idx)); },
                     }
                 } else {
                     let (lock, _) = &*shared;
@@ -1,18 +1,39 @@
+mod actions;
 mod cli;
+mod cluster;
 pub mod dedupe;
 mod files;
+mod geo;
+mod hash_cache;
+mod hash_index;
+#[cfg(feature = "heif")]
+mod heif;
+mod histogram;
+mod keep;
 mod loader;
+mod metadata;
+mod pages;
+mod raw;
+mod report;
+mod rows;
+#[cfg(feature = "svg")]
+mod svg;
+mod tiles;
 mod ui;
 
 use clap::Parser;
+use std::io::IsTerminal;
 use std::collections::HashMap;
 use std::sync::{Arc, Condvar, Mutex, RwLock};
 use winit::event_loop::EventLoop;
 
-use crate::cli::{parse_memory_budget, default_memory_budget, Cli};
-use crate::dedupe::{spawn_dedupe_scanner, DuplicateInfo};
+use crate::actions::{ActionOptions, DuplicateAction};
+use crate::cli::{parse_hash_size, parse_memory_budget, parse_threshold, default_memory_budget, Cli};
+use crate::dedupe::{spawn_dedupe_scanner, DedupeOptions, DuplicateInfo, ScanPaths};
 use crate::files::spawn_file_scanner;
-use crate::loader::{spawn_decode_workers, CacheState, SharedState, UserEvent};
+use crate::keep::KeepPolicy;
+use crate::geo::spawn_location_scanner;
+use crate::loader::{spawn_decode_workers, spawn_memory_monitor, CacheState, SharedState, UserEvent};
 use crate::ui::state::ViewerState;
 use crate::ui::App;
 
@@ -23,6 +44,71 @@ use crate::ui::App;
 fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     let cli = Cli::parse();
+    let (threshold, hash_size) = match (parse_threshold(&cli.threshold), parse_hash_size(&cli.hash_size)) {
+        (Ok(threshold), Ok(hash_size)) => (threshold, hash_size),
+        (Err(e), _) => {
+            eprintln!("Error: --threshold: {}", e);
+            std::process::exit(1);
+        }
+        (_, Err(e)) => {
+            eprintln!("Error: --hash-size: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let keep = KeepPolicy::parse(&cli.keep).unwrap_or_else(|e| {
+        eprintln!("Error: --keep: {}", e);
+        std::process::exit(1);
+    });
+    let sources = ScanPaths {
+        paths: cli.paths.clone(),
+        file_list: cli.file_list.clone(),
+        recursive: cli.recursive,
+        follow_links: cli.follow_links,
+    };
+    let dedupe_options = DedupeOptions {
+        threshold,
+        all_pages: cli.hash_all_pages,
+        exact_only: cli.exact_only,
+        cluster: cli.cluster,
+        keep,
+        hash_alg: cli.hash_alg,
+        hash_size,
+        hash_filter: cli.hash_filter,
+        dct: cli.hash_dct,
+        diff_gauss: cli.hash_diff_gauss,
+        orientations: cli.hash_orientations,
+        cache_path: if cli.no_hash_cache {
+            None
+        } else {
+            cli.hash_cache.clone().or_else(crate::hash_cache::default_path)
+        },
+    };
+
+    if dedupe_options.max_distance() > dedupe_options.hash_bits() {
+        eprintln!(
+            "Error: --threshold {} is more than the {}-bit hash (see --hash-size)",
+            cli.threshold,
+            dedupe_options.hash_bits()
+        );
+        std::process::exit(1);
+    }
+
+    if let Some(journal) = &cli.undo {
+        crate::actions::run_undo(journal, cli.dry_run);
+        return;
+    }
+    if cli.dry_run && cli.action.is_none() {
+        eprintln!("Error: --dry-run requires --action or --undo");
+        std::process::exit(1);
+    }
+    if (cli.action == Some(DuplicateAction::Move)) != cli.move_to.is_some() {
+        eprintln!("Error: --action move and --move-to go together");
+        std::process::exit(1);
+    }
+    if cli.action.is_some() && !cli.dry_run && !cli.yes && !std::io::stdin().is_terminal() {
+        eprintln!("Error: --action needs --yes when there's no terminal to confirm the plan on (preview it with --dry-run)");
+        std::process::exit(1);
+    }
 
     if let Some(dump_path) = cli.dump {
         if !cli.find_duplicates {
@@ -34,20 +120,52 @@ fn main() {
             eprintln!("Warning: Output file '{}' already exists. It will be overwritten.", dump_path.display());
         }
 
-        crate::dedupe::run_headless_dedupe(
-            cli.paths,
-            cli.recursive,
-            cli.follow_links,
-            cli.threshold,
-            dump_path,
-        );
+        let action = cli.action.map(|action| ActionOptions {
+            action,
+            move_to: cli.move_to.clone(),
+            dry_run: cli.dry_run,
+            yes: cli.yes,
+            journal: cli.undo_journal.clone().unwrap_or_else(|| {
+                let mut journal = dump_path.clone().into_os_string();
+                journal.push(".undo");
+                journal.into()
+            }),
+        });
+
+        crate::dedupe::run_headless_dedupe(sources, dedupe_options, dump_path, cli.dump_format, action);
         return;
     }
 
+    if let Some(output) = &cli.export_geojson {
+        if output.exists() {
+            eprintln!("Warning: Output file '{}' already exists. It will be overwritten.", output.display());
+        }
+        crate::geo::run_geojson_export(&sources, output);
+        return;
+    }
+
+    if cli.group_by_location {
+        if cli.find_duplicates {
+            eprintln!("Error: --group-by-location can't be combined with -D");
+            std::process::exit(1);
+        }
+        if cli.location_radius.is_nan() || cli.location_radius <= 0.0 {
+            eprintln!("Error: --location-radius must be positive");
+            std::process::exit(1);
+        }
+    }
+
     let budget = match &cli.memory {
-        Some(s) => parse_memory_budget(s),
+        Some(s) => match parse_memory_budget(s) {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("Error: --memory: {}", e);
+                std::process::exit(1);
+            }
+        },
         None => default_memory_budget(),
     };
+    log::info!("Image cache budget: {:.0} MB", budget as f64 / (1024.0 * 1024.0));
 
     // Shared file list, initially empty. Populated by background scanner.
     let files = Arc::new(RwLock::new(Vec::new()));
@@ -61,25 +179,34 @@ fn main() {
         Condvar::new(),
     ));
 
-    let num_threads = std::thread::available_parallelism()
-        .map(|n| n.get())
-        .unwrap_or(4)
-        .clamp(4, 16);
+    if cli.adaptive_memory {
+        spawn_memory_monitor(Arc::clone(&shared), budget);
+    }
+
+    let num_threads = match cli.threads {
+        Some(n) => n.max(1),
+        None => std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4)
+            .clamp(4, 16),
+    };
+    if cli.adaptive_threads {
+        let (lock, _) = &*shared;
+        lock.lock().unwrap().adaptive_workers = Some(num_threads);
+    }
+    if cli.prefetch_metadata {
+        let (lock, _) = &*shared;
+        lock.lock().unwrap().prefetch_metadata = true;
+    }
 
     let event_loop = EventLoop::<UserEvent>::with_user_event().build().expect("create event loop");
     let proxy = event_loop.create_proxy();
 
     // Spawn file scanner (producer)
     if cli.find_duplicates {
-        spawn_dedupe_scanner(
-            cli.paths.clone(),
-            cli.recursive,
-            cli.follow_links,
-            cli.threshold,
-            Arc::clone(&files),
-            Arc::clone(&dupe_info),
-            proxy.clone(),
-        );
+        spawn_dedupe_scanner(sources, dedupe_options, Arc::clone(&files), Arc::clone(&dupe_info), proxy.clone());
+    } else if cli.group_by_location {
+        spawn_location_scanner(sources, cli.location_radius, Arc::clone(&files), proxy.clone());
     } else {
         spawn_file_scanner(
             cli.paths.clone(),
@@ -92,7 +219,7 @@ fn main() {
     }
 
     // Spawn decode workers (consumers)
-    spawn_decode_workers(Arc::clone(&shared), Arc::clone(&files), proxy, num_threads);
+    spawn_decode_workers(Arc::clone(&shared), Arc::clone(&files), proxy, num_threads, cli.low_priority);
 
     let initial_delay = cli.initial_delay as f64 / 1000.0;
     let repeat_delay = cli.repeat_delay as f64 / 1000.0;
@@ -106,6 +233,7 @@ fn main() {
         if cli.find_duplicates { Some(dupe_info) } else { None },
         cli.font_size,
     );
+    state.metadata_format = cli.metadata_format;
 
     if cli.find_duplicates {
         state.view_mode = crate::loader::ViewMode::Grid;
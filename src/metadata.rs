@@ -0,0 +1,605 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+// ---------------------------------------------------------------------------
+// Image metadata: EXIF, XMP and IPTC
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Default)]
+pub struct ImageMetadata {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub datetime: Option<String>,
+    pub exposure_time: Option<String>,
+    pub f_number: Option<String>,
+    pub iso: Option<String>,
+    pub focal_length: Option<String>,
+    pub gps: Option<String>,
+    /// GPS position in decimal degrees (north and east positive) and
+    /// metres above sea level.
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub altitude: Option<f64>,
+    /// Every tag found, in `GROUPS` order.
+    pub fields: Vec<MetadataField>,
+}
+
+/// One tag of the full listing.
+#[derive(Clone, Debug)]
+pub struct MetadataField {
+    /// One of `GROUPS`.
+    pub group: &'static str,
+    pub name: String,
+    pub value: String,
+}
+
+/// Where fields come from, in display order. The EXIF groups are its IFDs.
+pub const GROUPS: &[&str] = &["IFD0", "Exif", "GPS", "Interop", "Thumbnail", "XMP", "IPTC"];
+
+/// Longer values (maker notes, embedded blobs) are cut off here.
+const MAX_VALUE_LEN: usize = 200;
+
+/// IFD0 tags of TIFF-based files (and most RAWs) that hold whole XMP,
+/// IPTC-IIM and Photoshop resource blocks rather than single values.
+const TAG_XMP: u16 = 700;
+const TAG_IPTC: u16 = 33723;
+const TAG_PHOTOSHOP: u16 = 34377;
+
+/// How the `M` key prints metadata.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MetadataFormat {
+    /// Camera, exposure, time and GPS
+    #[default]
+    Summary,
+    /// Every EXIF, XMP and IPTC field, grouped
+    All,
+    /// Every field, as one JSON object per line
+    Json,
+}
+
+/// Gather metadata from parsed EXIF and, if given, (the start of) the file
+/// bytes, searched for XMP and IPTC when EXIF doesn't carry them. None if
+/// there is none at all.
+pub fn read_metadata(exif: Option<&exif::Exif>, bytes: Option<&[u8]>) -> Option<ImageMetadata> {
+    let mut meta = exif.map(summary_from_exif).unwrap_or_default();
+    if let Some(exif) = exif {
+        meta.fields.extend(exif_fields(exif));
+    }
+
+    let tag = |number| exif.and_then(|exif| tag_bytes(exif, number));
+    if let Some(xml) = tag(TAG_XMP).or(bytes).and_then(xmp_packet) {
+        meta.fields.extend(parse_xmp(xml));
+    }
+    let iim = tag(TAG_IPTC)
+        .or_else(|| tag(TAG_PHOTOSHOP).and_then(iptc_block))
+        .or_else(|| bytes.and_then(iptc_block));
+    if let Some(iim) = iim {
+        meta.fields.extend(parse_iptc(iim));
+    }
+    meta.fields.sort_by_key(|f| GROUPS.iter().position(|&g| g == f.group));
+    (exif.is_some() || !meta.fields.is_empty()).then_some(meta)
+}
+
+fn summary_from_exif(exif: &exif::Exif) -> ImageMetadata {
+    let get_field = |tag| {
+        exif.get_field(tag, exif::In::PRIMARY)
+            .map(|f| f.display_value().with_unit(exif).to_string())
+    };
+
+    let make = get_field(exif::Tag::Make);
+    let model = get_field(exif::Tag::Model);
+    let datetime = get_field(exif::Tag::DateTimeOriginal)
+        .or_else(|| get_field(exif::Tag::DateTime));
+    let exposure_time = get_field(exif::Tag::ExposureTime);
+    let f_number = get_field(exif::Tag::FNumber);
+    let iso = get_field(exif::Tag::PhotographicSensitivity);
+    let focal_length = get_field(exif::Tag::FocalLength);
+
+    // GPS logic
+    let lat_ref = get_field(exif::Tag::GPSLatitudeRef);
+    let lat = get_field(exif::Tag::GPSLatitude);
+    let lon_ref = get_field(exif::Tag::GPSLongitudeRef);
+    let lon = get_field(exif::Tag::GPSLongitude);
+
+    let gps = match (lat, lat_ref, lon, lon_ref) {
+        (Some(lat), Some(lat_ref), Some(lon), Some(lon_ref)) => {
+            Some(format!("{} {}  {} {}", lat, lat_ref, lon, lon_ref))
+        },
+        _ => None,
+    };
+    let latitude = gps_degrees(exif, exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef, b'S');
+    let longitude = gps_degrees(exif, exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef, b'W');
+    let altitude = gps_altitude(exif);
+
+    ImageMetadata {
+        make,
+        model,
+        datetime,
+        exposure_time,
+        f_number,
+        iso,
+        focal_length,
+        gps,
+        latitude,
+        longitude,
+        altitude,
+        fields: Vec::new(),
+    }
+}
+
+/// A GPS latitude/longitude (degrees, minutes, seconds) in decimal
+/// degrees, negative if `ref_tag` is `negative_ref` (S or W).
+fn gps_degrees(exif: &exif::Exif, tag: exif::Tag, ref_tag: exif::Tag, negative_ref: u8) -> Option<f64> {
+    let exif::Value::Rational(dms) = &exif.get_field(tag, exif::In::PRIMARY)?.value else {
+        return None;
+    };
+    let degrees = dms.iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(v, div)| v.to_f64() / div)
+        .sum::<f64>();
+    let negative = match &exif.get_field(ref_tag, exif::In::PRIMARY)?.value {
+        exif::Value::Ascii(s) => s.first().and_then(|s| s.first()).map(u8::to_ascii_uppercase) == Some(negative_ref),
+        _ => false,
+    };
+    let degrees = if negative { -degrees } else { degrees };
+    degrees.is_finite().then_some(degrees)
+}
+
+/// GPS altitude in metres, negative below sea level.
+fn gps_altitude(exif: &exif::Exif) -> Option<f64> {
+    let exif::Value::Rational(v) = &exif.get_field(exif::Tag::GPSAltitude, exif::In::PRIMARY)?.value else {
+        return None;
+    };
+    let metres = v.first()?.to_f64();
+    let below_sea = exif.get_field(exif::Tag::GPSAltitudeRef, exif::In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0)) == Some(1);
+    let metres = if below_sea { -metres } else { metres };
+    metres.is_finite().then_some(metres)
+}
+
+fn truncate(mut value: String) -> String {
+    if value.len() > MAX_VALUE_LEN {
+        let mut end = MAX_VALUE_LEN;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.truncate(end);
+        value.push_str("...");
+    }
+    value
+}
+
+/// Contents of an IFD0 tag stored as bytes.
+fn tag_bytes(exif: &exif::Exif, number: u16) -> Option<&[u8]> {
+    match &exif.get_field(exif::Tag(exif::Context::Tiff, number), exif::In::PRIMARY)?.value {
+        exif::Value::Byte(bytes) | exif::Value::Undefined(bytes, _) => Some(bytes),
+        _ => None,
+    }
+}
+
+/// Every field kamadak-exif can read, including ones it has no name for,
+/// except the XMP/IPTC blocks listed separately.
+fn exif_fields(exif: &exif::Exif) -> Vec<MetadataField> {
+    exif.fields()
+        .filter(|field| {
+            field.tag.context() != exif::Context::Tiff
+                || ![TAG_XMP, TAG_IPTC, TAG_PHOTOSHOP].contains(&field.tag.number())
+        })
+        .map(|field| {
+            let group = if field.ifd_num == exif::In::THUMBNAIL {
+                "Thumbnail"
+            } else {
+                match field.tag.context() {
+                    exif::Context::Exif => "Exif",
+                    exif::Context::Gps => "GPS",
+                    exif::Context::Interop => "Interop",
+                    _ => "IFD0",
+                }
+            };
+            // Text without the quotes `display_value` adds
+            let value = match &field.value {
+                exif::Value::Ascii(strings) => strings
+                    .iter()
+                    .map(|s| String::from_utf8_lossy(s).trim_end_matches('\0').trim().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                _ => field.display_value().with_unit(exif).to_string(),
+            };
+            MetadataField { group, name: field.tag.to_string(), value: truncate(value) }
+        })
+        .collect()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// -- XMP ----------------------------------------------------------------------
+
+const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+
+/// The XMP packet, wherever the container keeps it (JPEG APP1, PNG iTXt,
+/// TIFF tag 700, WebP chunk, ...), as long as it is stored uncompressed.
+fn xmp_packet(bytes: &[u8]) -> Option<&str> {
+    let (start, end_tag) = match find(bytes, b"<x:xmpmeta") {
+        Some(start) => (start, &b"</x:xmpmeta>"[..]),
+        None => (find(bytes, b"<rdf:RDF")?, &b"</rdf:RDF>"[..]),
+    };
+    let end = start + find(&bytes[start..], end_tag)? + end_tag.len();
+    std::str::from_utf8(&bytes[start..end]).ok()
+}
+
+/// `prefix:name` of an element or attribute, as written in the packet.
+fn xmp_name(node: roxmltree::Node, namespace: Option<&str>, local: &str) -> String {
+    match namespace.and_then(|ns| node.lookup_prefix(ns)) {
+        Some(prefix) => format!("{}:{}", prefix, local),
+        None => local.to_string(),
+    }
+}
+
+fn is_rdf(node: &roxmltree::Node, names: &[&str]) -> bool {
+    node.is_element() && node.tag_name().namespace() == Some(RDF_NS) && names.contains(&node.tag_name().name())
+}
+
+fn push_xmp(fields: &mut Vec<MetadataField>, name: String, value: &str) {
+    let value = value.trim();
+    if !value.is_empty() {
+        fields.push(MetadataField { group: "XMP", name, value: truncate(value.to_string()) });
+    }
+}
+
+/// Flatten the properties of an rdf:Description (or a struct value) into
+/// fields. Arrays are joined with ", "; struct members become `parent/member`.
+fn xmp_properties(desc: roxmltree::Node, parent: &str, fields: &mut Vec<MetadataField>) {
+    for attr in desc.attributes().filter(|a| a.namespace() != Some(RDF_NS)) {
+        let name = format!("{}{}", parent, xmp_name(desc, attr.namespace(), attr.name()));
+        push_xmp(fields, name, attr.value());
+    }
+
+    for prop in desc.children().filter(|n| n.is_element()) {
+        let name = format!("{}{}", parent, xmp_name(prop, prop.tag_name().namespace(), prop.tag_name().name()));
+        if let Some(array) = prop.children().find(|n| is_rdf(n, &["Bag", "Seq", "Alt"])) {
+            let items: Vec<&str> = array
+                .children()
+                .filter(|n| is_rdf(n, &["li"]))
+                .filter_map(|li| li.text())
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .collect();
+            push_xmp(fields, name, &items.join(", "));
+        } else if prop.children().any(|n| n.is_element())
+            || prop.attributes().any(|a| a.namespace() != Some(RDF_NS))
+        {
+            // Struct, either inline or wrapped in its own rdf:Description
+            let inner = prop.children().find(|n| is_rdf(n, &["Description"])).unwrap_or(prop);
+            xmp_properties(inner, &format!("{}/", name), fields);
+        } else {
+            let value = prop.text().or_else(|| prop.attribute((RDF_NS, "resource"))).unwrap_or("");
+            push_xmp(fields, name, value);
+        }
+    }
+}
+
+fn parse_xmp(xml: &str) -> Vec<MetadataField> {
+    let mut fields = Vec::new();
+    let Ok(doc) = roxmltree::Document::parse(xml) else {
+        return fields;
+    };
+    for desc in doc.descendants().filter(|n| is_rdf(n, &["Description"])) {
+        // Nested descriptions are struct values, handled by their parent
+        if desc.ancestors().skip(1).any(|n| is_rdf(&n, &["Description"])) {
+            continue;
+        }
+        xmp_properties(desc, "", &mut fields);
+    }
+    fields
+}
+
+// -- IPTC ---------------------------------------------------------------------
+
+/// Photoshop image resource holding IPTC-IIM data.
+const IPTC_RESOURCE: &[u8] = b"8BIM\x04\x04";
+
+/// Names of the IIM application record (2) datasets worth showing.
+const IPTC_NAMES: &[(u8, &str)] = &[
+    (5, "ObjectName"),
+    (15, "Category"),
+    (20, "SupplementalCategory"),
+    (25, "Keywords"),
+    (40, "SpecialInstructions"),
+    (55, "DateCreated"),
+    (60, "TimeCreated"),
+    (80, "By-line"),
+    (85, "By-lineTitle"),
+    (90, "City"),
+    (92, "Sublocation"),
+    (95, "Province/State"),
+    (100, "CountryCode"),
+    (101, "Country"),
+    (103, "OriginalTransmissionReference"),
+    (105, "Headline"),
+    (110, "Credit"),
+    (115, "Source"),
+    (116, "CopyrightNotice"),
+    (120, "Caption/Abstract"),
+    (122, "Writer"),
+];
+
+/// The IIM data of the first IPTC resource in a Photoshop block (JPEG
+/// APP13, TIFF tag 34377 or a bare .8bim file).
+fn iptc_block(bytes: &[u8]) -> Option<&[u8]> {
+    let start = find(bytes, IPTC_RESOURCE)? + IPTC_RESOURCE.len();
+    // Pascal-string name, padded to an even length
+    let name_len = *bytes.get(start)? as usize;
+    let size_at = start + (name_len + 2) / 2 * 2;
+    let size = u32::from_be_bytes(bytes.get(size_at..size_at + 4)?.try_into().ok()?) as usize;
+    let data_at = size_at + 4;
+    bytes.get(data_at..data_at.checked_add(size)?)
+}
+
+fn parse_iptc(mut iim: &[u8]) -> Vec<MetadataField> {
+    let mut fields: Vec<MetadataField> = Vec::new();
+    // Each dataset: 0x1C, record, dataset, u16 BE length, data
+    while let [0x1C, record, dataset, hi, lo, rest @ ..] = iim {
+        // Extended (>32 KB) datasets are never text we show; stop there
+        if hi & 0x80 != 0 {
+            break;
+        }
+        let len = u16::from_be_bytes([*hi, *lo]) as usize;
+        let Some(data) = rest.get(..len) else { break };
+        iim = &rest[len..];
+
+        let name = IPTC_NAMES.iter().find(|(id, _)| *record == 2 && id == dataset);
+        let Some((_, name)) = name else { continue };
+        let value = match std::str::from_utf8(data) {
+            Ok(s) => s.to_string(),
+            Err(_) => data.iter().map(|&b| b as char).collect(), // Latin-1
+        };
+        let value = value.trim_end_matches('\0').trim().to_string();
+        if value.is_empty() {
+            continue;
+        }
+        // Repeatable datasets (keywords, categories, ...) become one list
+        match fields.iter_mut().find(|f| f.name == *name) {
+            Some(field) => {
+                field.value.push_str(", ");
+                field.value.push_str(&value);
+            }
+            None => fields.push(MetadataField { group: "IPTC", name: name.to_string(), value }),
+        }
+    }
+    for field in &mut fields {
+        field.value = truncate(std::mem::take(&mut field.value));
+    }
+    fields
+}
+
+// -- Output -------------------------------------------------------------------
+
+/// `s` as a quoted JSON string.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Print `meta` for `path` to stdout, for the `M` key.
+pub fn dump(path: &Path, meta: &ImageMetadata, format: MetadataFormat) {
+    match format {
+        MetadataFormat::Summary => {
+            println!("[METADATA] {}", path.display());
+            if let Some(v) = &meta.make { println!("  Make: {}", v); }
+            if let Some(v) = &meta.model { println!("  Model: {}", v); }
+            if let Some(v) = &meta.datetime { println!("  Time: {}", v); }
+            if let Some(v) = &meta.exposure_time { println!("  Exposure: {}", v); }
+            if let Some(v) = &meta.f_number { println!("  F-Number: {}", v); }
+            if let Some(v) = &meta.iso { println!("  ISO: {}", v); }
+            if let Some(v) = &meta.focal_length { println!("  Focal Length: {}", v); }
+            if let Some(v) = &meta.gps { println!("  GPS: {}", v); }
+            if let (Some(lat), Some(lon)) = (meta.latitude, meta.longitude) {
+                match meta.altitude {
+                    Some(alt) => println!("  Position: {:.6}, {:.6} ({:.0} m)", lat, lon, alt),
+                    None => println!("  Position: {:.6}, {:.6}", lat, lon),
+                }
+            }
+            println!("----------------------------------------");
+        }
+        MetadataFormat::All => {
+            println!("[METADATA] {}", path.display());
+            let mut group = "";
+            for field in &meta.fields {
+                if field.group != group {
+                    group = field.group;
+                    println!("  [{}]", group);
+                }
+                println!("    {}: {}", field.name, field.value);
+            }
+            println!("----------------------------------------");
+        }
+        MetadataFormat::Json => {
+            // {"path": ..., "groups": {"IFD0": {"Make": ..., ...}, ...}}. A
+            // name that comes up again in its group (several XMP packets,
+            // repeated IPTC datasets) gets "#2", "#3", ... to keep keys unique
+            let mut out = format!("{{\"path\":{},\"groups\":{{", json_string(&path.to_string_lossy()));
+            let mut group = "";
+            let mut seen: HashMap<&str, usize> = HashMap::new();
+            for field in &meta.fields {
+                if field.group != group {
+                    if !group.is_empty() {
+                        out.push_str("},");
+                    }
+                    group = field.group;
+                    seen.clear();
+                    out.push_str(&json_string(group));
+                    out.push_str(":{");
+                } else {
+                    out.push(',');
+                }
+                let count = seen.entry(field.name.as_str()).or_default();
+                *count += 1;
+                if *count == 1 {
+                    out.push_str(&json_string(&field.name));
+                } else {
+                    out.push_str(&json_string(&format!("{}#{}", field.name, count)));
+                }
+                out.push(':');
+                out.push_str(&json_string(&field.value));
+            }
+            if !group.is_empty() {
+                out.push('}');
+            }
+            out.push_str("}}");
+            println!("{}", out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A little-endian TIFF whose IFD0 only points at a GPS IFD of
+    /// `entries`: (tag, type, count, value).
+    fn gps_exif(entries: &[(u16, u16, u32, Vec<u8>)]) -> exif::Exif {
+        const GPS_IFD: u32 = 26;
+        let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+        tiff.extend(1u16.to_le_bytes());
+        tiff.extend([0x25, 0x88, 4, 0]);
+        tiff.extend(1u32.to_le_bytes());
+        tiff.extend(GPS_IFD.to_le_bytes());
+        tiff.extend(0u32.to_le_bytes());
+
+        // Values over four bytes go after the IFD
+        let mut data_at = GPS_IFD as usize + 2 + 12 * entries.len() + 4;
+        let mut data: Vec<u8> = Vec::new();
+        tiff.extend((entries.len() as u16).to_le_bytes());
+        for (tag, kind, count, value) in entries {
+            tiff.extend(tag.to_le_bytes());
+            tiff.extend(kind.to_le_bytes());
+            tiff.extend(count.to_le_bytes());
+            if value.len() <= 4 {
+                let mut inline = value.clone();
+                inline.resize(4, 0);
+                tiff.extend(inline);
+            } else {
+                tiff.extend((data_at as u32).to_le_bytes());
+                data.extend(value);
+                data_at += value.len();
+            }
+        }
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(data);
+        exif::Reader::new().read_raw(tiff).unwrap()
+    }
+
+    fn rationals(values: &[(u32, u32)]) -> Vec<u8> {
+        values.iter().flat_map(|&(n, d)| n.to_le_bytes().into_iter().chain(d.to_le_bytes())).collect()
+    }
+
+    #[test]
+    fn gps_position_is_signed_by_its_refs() {
+        const ASCII: u16 = 2;
+        const RATIONAL: u16 = 5;
+        let position = |lat_ref: &[u8], lon_ref: &[u8], below_sea: u8| {
+            let exif = gps_exif(&[
+                (1, ASCII, 2, lat_ref.to_vec()),
+                (2, RATIONAL, 3, rationals(&[(48, 1), (51, 1), (295, 10)])),
+                (3, ASCII, 2, lon_ref.to_vec()),
+                (4, RATIONAL, 3, rationals(&[(2, 1), (17, 1), (402, 10)])),
+                (5, 1, 1, vec![below_sea]),
+                (6, RATIONAL, 1, rationals(&[(71, 2)])),
+            ]);
+            let meta = read_metadata(Some(&exif), None).unwrap();
+            (meta.latitude.unwrap(), meta.longitude.unwrap(), meta.altitude.unwrap())
+        };
+        let (lat, lon, alt) = position(b"N\0", b"E\0", 0);
+        assert!((lat - (48.0 + 51.0 / 60.0 + 29.5 / 3600.0)).abs() < 1e-9);
+        assert!((lon - (2.0 + 17.0 / 60.0 + 40.2 / 3600.0)).abs() < 1e-9);
+        assert_eq!(alt, 35.5);
+        // Refs are matched case-insensitively
+        let (south, west, below) = position(b"s\0", b"W\0", 1);
+        assert_eq!((south, west, below), (-lat, -lon, -alt));
+    }
+
+    #[test]
+    fn repeated_iptc_datasets_become_one_list() {
+        let dataset = |record: u8, number: u8, value: &str| {
+            let mut out = vec![0x1C, record, number];
+            out.extend((value.len() as u16).to_be_bytes());
+            out.extend(value.as_bytes());
+            out
+        };
+        let iim: Vec<u8> = [
+            dataset(2, 25, "cat"),
+            dataset(2, 90, "Paris"),
+            dataset(2, 25, "dog"),
+            // Not in the application record, or not shown
+            dataset(1, 90, "\x1b%G"),
+            dataset(2, 200, "x"),
+            dataset(2, 25, "  "),
+        ]
+        .concat();
+        // A Photoshop resource with an empty name, padded to even length
+        let mut block = b"Photoshop 3.0\08BIM\x04\x04\0\0".to_vec();
+        block.extend((iim.len() as u32).to_be_bytes());
+        block.extend(&iim);
+
+        let meta = read_metadata(None, Some(&block)).unwrap();
+        let fields: Vec<(&str, &str, &str)> =
+            meta.fields.iter().map(|f| (f.group, f.name.as_str(), f.value.as_str())).collect();
+        assert_eq!(fields, [("IPTC", "Keywords", "cat, dog"), ("IPTC", "City", "Paris")]);
+    }
+
+    #[test]
+    fn xmp_attributes_and_elements_are_both_properties() {
+        let packet = br#"JFIF...<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:core="http://iptc.org/std/Iptc4xmpCore/1.0/xmlns/"
+    xmp:Rating="5"
+    xmp:CreatorTool=" Camera ">
+   <dc:subject><rdf:Bag><rdf:li>cat</rdf:li><rdf:li>dog</rdf:li></rdf:Bag></dc:subject>
+   <xmp:Label>Red</xmp:Label>
+   <dc:source rdf:resource="http://example.com/a.jpg"/>
+   <core:CreatorContactInfo core:CiAdrCity="Paris">
+    <core:CiAdrCtry>France</core:CiAdrCtry>
+   </core:CreatorContactInfo>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>...trailing bytes"#;
+
+        let meta = read_metadata(None, Some(packet)).unwrap();
+        let fields: Vec<(&str, &str)> = meta
+            .fields
+            .iter()
+            .filter(|f| f.group == "XMP")
+            .map(|f| (f.name.as_str(), f.value.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("xmp:Rating", "5"),
+                ("xmp:CreatorTool", "Camera"),
+                ("dc:subject", "cat, dog"),
+                ("xmp:Label", "Red"),
+                ("dc:source", "http://example.com/a.jpg"),
+                ("core:CreatorContactInfo/core:CiAdrCity", "Paris"),
+                ("core:CreatorContactInfo/core:CiAdrCtry", "France"),
+            ]
+        );
+    }
+}
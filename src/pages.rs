@@ -0,0 +1,159 @@
+use image::ImageFormat;
+use std::borrow::Cow;
+
+// ---------------------------------------------------------------------------
+// Multi-image files (TIFF pages, ICO entries, DDS surfaces)
+// ---------------------------------------------------------------------------
+//
+// The image crate only decodes one image from these containers. To get at
+// page k we rewrite the container's header so that page k is the image it
+// decodes; the pixel data itself is left where it is.
+
+/// Stop walking a page chain here, in case it is corrupt or loops.
+pub const MAX_PAGES: usize = 10_000;
+
+pub struct Selected<'a> {
+    /// File contents with the requested page in front.
+    pub data: Cow<'a, [u8]>,
+    /// The page actually selected (the request is clamped).
+    pub page: usize,
+    pub count: usize,
+}
+
+/// Select page `page` of a multi-image file. None if the format has no
+/// pages or the file has just one; decode it as is then.
+pub fn select(format: ImageFormat, bytes: &[u8], page: usize) -> Option<Selected<'_>> {
+    let selected = match format {
+        ImageFormat::Tiff => select_tiff(bytes, page),
+        ImageFormat::Ico => select_ico(bytes, page),
+        ImageFormat::Dds => select_dds(bytes, page),
+        _ => None,
+    }?;
+    (selected.count > 1).then_some(selected)
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// TIFF: pages are a linked list of IFDs; point the header at the wanted one.
+/// BigTIFF isn't handled and shows its first page only.
+fn select_tiff(bytes: &[u8], page: usize) -> Option<Selected<'_>> {
+    let little_endian = match bytes.get(..4)? {
+        b"II*\0" => true,
+        b"MM\0*" => false,
+        _ => return None,
+    };
+    let read_u16 = |at: usize| {
+        let b: [u8; 2] = bytes.get(at..at + 2)?.try_into().ok()?;
+        Some(if little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    };
+    let read_u32 = |at: usize| {
+        let b: [u8; 4] = bytes.get(at..at + 4)?.try_into().ok()?;
+        Some(if little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    };
+
+    let mut ifds = Vec::new();
+    let mut next = read_u32(4)? as usize;
+    while next != 0 && ifds.len() < MAX_PAGES && !ifds.contains(&next) {
+        let Some(entries) = read_u16(next) else { break };
+        ifds.push(next);
+        next = read_u32(next + 2 + entries as usize * 12).unwrap_or(0) as usize;
+    }
+
+    let page = page.min(ifds.len().checked_sub(1)?);
+    let data = if page == 0 {
+        Cow::Borrowed(bytes)
+    } else {
+        let offset = ifds[page] as u32;
+        let mut data = bytes.to_vec();
+        data[4..8].copy_from_slice(&if little_endian { offset.to_le_bytes() } else { offset.to_be_bytes() });
+        Cow::Owned(data)
+    };
+    Some(Selected { data, page, count: ifds.len() })
+}
+
+/// ICO: build a one-entry icon from the wanted directory entry. Pages are
+/// ordered largest first, so page 0 is what the decoder would pick anyway.
+fn select_ico(bytes: &[u8], page: usize) -> Option<Selected<'_>> {
+    if bytes.get(..4)? != [0, 0, 1, 0] {
+        return None;
+    }
+    let count = u16::from_le_bytes(bytes.get(4..6)?.try_into().ok()?) as usize;
+    let mut entries: Vec<&[u8]> = (0..count)
+        .map_while(|i| bytes.get(6 + i * 16..6 + (i + 1) * 16))
+        .collect();
+    // Width/height of 0 mean 256; then prefer more bits per pixel
+    let dim = |b: u8| if b == 0 { 256u32 } else { b as u32 };
+    entries.sort_by_key(|e| std::cmp::Reverse((dim(e[0]) * dim(e[1]), u16::from_le_bytes([e[6], e[7]]))));
+
+    let page = page.min(entries.len().checked_sub(1)?);
+    let entry = entries[page];
+    let size = u32_le(entry, 8)? as usize;
+    let offset = u32_le(entry, 12)? as usize;
+    let image = bytes.get(offset..offset.checked_add(size)?)?;
+
+    let mut data = Vec::with_capacity(22 + size);
+    data.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+    data.extend_from_slice(&entry[..12]);
+    data.extend_from_slice(&22u32.to_le_bytes());
+    data.extend_from_slice(image);
+    Some(Selected { data: Cow::Owned(data), page, count: entries.len() })
+}
+
+/// DDS: every mip level of every array layer / cube face is a page. The
+/// wanted surface is given a header of its own. Only the block-compressed
+/// formats the image crate decodes are handled, and only surfaces whose
+/// sides are multiples of 4 (the decoder's requirement).
+fn select_dds(bytes: &[u8], page: usize) -> Option<Selected<'_>> {
+    if bytes.get(..4)? != b"DDS " {
+        return None;
+    }
+    let flags = u32_le(bytes, 8)?;
+    let height = u32_le(bytes, 12)?;
+    let width = u32_le(bytes, 16)?;
+    let mips = if flags & 0x20000 != 0 { u32_le(bytes, 28)?.clamp(1, 32) } else { 1 };
+    let is_cube = u32_le(bytes, 112)? & 0x200 != 0;
+
+    let (header_len, block_bytes, layers) = match bytes.get(84..88)? {
+        b"DXT1" => (128, 8, if is_cube { 6 } else { 1 }),
+        b"DXT3" | b"DXT5" => (128, 16, if is_cube { 6 } else { 1 }),
+        b"DX10" => {
+            let block_bytes = match u32_le(bytes, 128)? {
+                70..=72 => 8,
+                73..=78 => 16,
+                _ => return None,
+            };
+            let faces = if u32_le(bytes, 136)? & 0x4 != 0 { 6 } else { 1 };
+            (148, block_bytes, u32_le(bytes, 140)?.max(1) * faces)
+        }
+        _ => return None,
+    };
+
+    // (offset, width, height, len) of each decodable surface, in file order
+    let mut surfaces = Vec::new();
+    let mut offset = header_len;
+    'layers: for _ in 0..layers {
+        for mip in 0..mips {
+            let (w, h) = ((width >> mip).max(1), (height >> mip).max(1));
+            let len = w.div_ceil(4) as usize * h.div_ceil(4) as usize * block_bytes;
+            if offset + len > bytes.len() || surfaces.len() >= MAX_PAGES {
+                break 'layers;
+            }
+            if w % 4 == 0 && h % 4 == 0 {
+                surfaces.push((offset, w, h, len));
+            }
+            offset += len;
+        }
+    }
+
+    let page = page.min(surfaces.len().checked_sub(1)?);
+    let (offset, w, h, len) = surfaces[page];
+    let mut data = Vec::with_capacity(header_len + len);
+    data.extend_from_slice(&bytes[..header_len]);
+    data[12..16].copy_from_slice(&h.to_le_bytes());
+    data[16..20].copy_from_slice(&w.to_le_bytes());
+    data[28..32].copy_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&bytes[offset..offset + len]);
+    Some(Selected { data: Cow::Owned(data), page, count: surfaces.len() })
+}
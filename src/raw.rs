@@ -0,0 +1,145 @@
+use image::metadata::Orientation;
+use image::DynamicImage;
+use std::io::Cursor;
+#[cfg(feature = "raw")]
+use std::path::Path;
+
+use crate::loader::DecodeError;
+
+// ---------------------------------------------------------------------------
+// Camera RAW files
+// ---------------------------------------------------------------------------
+//
+// Every common RAW format carries one or more JPEG renderings of the shot
+// (thumbnail, screen preview, often a full-size preview). Without the "raw"
+// feature those are all we show; with it, full views are developed from the
+// sensor data and the previews are still used for thumbnails and hashing.
+
+/// Find the largest JPEG embedded in `bytes` that the JPEG decoder can
+/// handle. Lossless JPEG (used by some formats for the sensor data itself)
+/// is skipped. The returned slice starts at the JPEG's SOI marker and runs
+/// to the end of the file; the decoder stops at EOI.
+fn largest_embedded_jpeg(bytes: &[u8]) -> Option<&[u8]> {
+    let mut best: Option<(u64, usize)> = None;
+    let mut pos = 0;
+    while let Some(found) = bytes[pos..].windows(3).position(|w| w == [0xFF, 0xD8, 0xFF]) {
+        let start = pos + found;
+        if let Some((w, h)) = jpeg_dimensions(&bytes[start..]) {
+            let area = w as u64 * h as u64;
+            if best.is_none_or(|(best_area, _)| area > best_area) {
+                best = Some((area, start));
+            }
+        }
+        pos = start + 2;
+    }
+    best.map(|(_, start)| &bytes[start..])
+}
+
+/// Walk the marker segments of a JPEG up to its frame header and return
+/// the frame size, if it is a baseline/extended/progressive Huffman frame.
+fn jpeg_dimensions(jpeg: &[u8]) -> Option<(u16, u16)> {
+    let be16 = |at: usize| Some(u16::from_be_bytes([*jpeg.get(at)?, *jpeg.get(at + 1)?]));
+    let mut pos = 2;
+    loop {
+        if *jpeg.get(pos)? != 0xFF {
+            return None;
+        }
+        while *jpeg.get(pos)? == 0xFF {
+            pos += 1;
+        }
+        let marker = jpeg[pos];
+        pos += 1;
+        match marker {
+            0xC0..=0xC2 => {
+                let (h, w) = (be16(pos + 3)?, be16(pos + 5)?);
+                return (w > 0 && h > 0).then_some((w, h));
+            }
+            // Other frame types, start of scan or end of image before a frame
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xDA | 0xD9 => return None,
+            // Standalone markers without a length
+            0x01 | 0xD0..=0xD7 => {}
+            _ => pos += be16(pos)? as usize,
+        }
+    }
+}
+
+/// Read EXIF from a RAW file. TIFF-based formats (CR2, NEF, ARW, DNG, PEF,
+/// ...) parse directly; a few others need help. `bytes` may be just the
+/// head of the file: fields and IFDs that lie past it are left out.
+pub fn read_exif(bytes: &[u8]) -> Option<exif::Exif> {
+    let mut reader = exif::Reader::new();
+    reader.continue_on_error(true);
+    let reader = PartialReader(reader);
+    if let Some(exif) = reader.read_from_container(bytes) {
+        return Some(exif);
+    }
+
+    // Olympus ORF and Panasonic RW2 are TIFF with a private magic number
+    if bytes.len() > 8 && (bytes.starts_with(b"IIRO") || bytes.starts_with(b"IIU\0") || bytes.starts_with(b"MMOR")) {
+        let mut patched = bytes.to_vec();
+        patched[..4].copy_from_slice(if bytes[0] == b'I' { b"II*\0" } else { b"MM\0*" });
+        if let Some(exif) = reader.read_raw(patched) {
+            return Some(exif);
+        }
+    }
+
+    // Canon CR3 (ISO media) keeps IFD0 as a TIFF blob in a "CMT1" box
+    if let Some(tag) = bytes.windows(4).position(|w| w == b"CMT1")
+        && tag >= 4
+    {
+        let size = u32::from_be_bytes(bytes[tag - 4..tag].try_into().ok()?) as usize;
+        if let Some(tiff) = bytes.get(tag + 4..(tag - 4).checked_add(size)?)
+            && let Some(exif) = reader.read_raw(tiff.to_vec())
+        {
+            return Some(exif);
+        }
+    }
+
+    // Fuji RAF and others: the preview JPEG carries a copy
+    let jpeg = largest_embedded_jpeg(bytes)?;
+    reader.read_from_container(jpeg)
+}
+
+/// An `exif::Reader` set to continue on errors, keeping whatever parsed.
+struct PartialReader(exif::Reader);
+
+impl PartialReader {
+    fn read_from_container(&self, bytes: &[u8]) -> Option<exif::Exif> {
+        Self::partial(self.0.read_from_container(&mut Cursor::new(bytes)))
+    }
+
+    fn read_raw(&self, data: Vec<u8>) -> Option<exif::Exif> {
+        Self::partial(self.0.read_raw(data))
+    }
+
+    fn partial(result: Result<exif::Exif, exif::Error>) -> Option<exif::Exif> {
+        result.or_else(|e| e.distill_partial_result(|_| {})).ok()
+    }
+}
+
+pub fn orientation(exif: &exif::Exif) -> Option<Orientation> {
+    let value = exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?.value.get_uint(0)?;
+    Orientation::from_exif(value as u8)
+}
+
+/// Decode the largest embedded preview, rotated per `orientation`.
+pub fn decode_preview(bytes: &[u8], orientation: Option<Orientation>) -> Result<DynamicImage, DecodeError> {
+    let jpeg = largest_embedded_jpeg(bytes)
+        .ok_or_else(|| DecodeError::Unsupported("no embedded preview in RAW file".to_string()))?;
+    let mut img = image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg)?;
+    if let Some(orientation) = orientation {
+        img.apply_orientation(orientation);
+    }
+    Ok(img)
+}
+
+/// Demosaic and colour-process the sensor data (cargo feature "raw").
+/// Orientation is applied by the pipeline.
+#[cfg(feature = "raw")]
+pub fn develop(path: &Path) -> Result<DynamicImage, DecodeError> {
+    let mut pipeline = imagepipe::Pipeline::new_from_file(path).map_err(DecodeError::Unsupported)?;
+    let out = pipeline.output_8bit(None).map_err(DecodeError::Corrupt)?;
+    image::RgbImage::from_raw(out.width as u32, out.height as u32, out.data)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or_else(|| DecodeError::Corrupt("RAW pipeline returned a short buffer".to_string()))
+}
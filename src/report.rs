@@ -0,0 +1,456 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use image::metadata::Orientation;
+use image_hasher::ImageHash;
+
+use crate::cluster::{transform_name, Duplicate, Group, ORIENTATIONS};
+use crate::dedupe::DedupeOptions;
+use crate::metadata::json_string;
+
+// ---------------------------------------------------------------------------
+// Writing the `--dump` duplicate report
+// ---------------------------------------------------------------------------
+
+/// Layout of the `--dump` report (`--dump-format`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DumpFormat {
+    /// `# original`, then `E 0 path` / `D distance path` /
+    /// `T distance transform path` per duplicate
+    #[default]
+    Text,
+    /// One JSON document with a summary and every group's files
+    Json,
+    /// One row per file; the summary goes to `<name>.summary.csv`
+    Csv,
+}
+
+/// How long each stage of the scan took.
+#[derive(Default)]
+pub struct Timings {
+    pub collect: Duration,
+    pub exact: Duration,
+    pub hash: Duration,
+    pub cluster: Duration,
+}
+
+impl Timings {
+    fn total(&self) -> Duration {
+        self.collect + self.exact + self.hash + self.cluster
+    }
+}
+
+/// Everything a report says about a finished scan.
+pub struct Report<'a> {
+    pub started: chrono::DateTime<chrono::Local>,
+    /// Paths given on the command line.
+    pub roots: &'a [PathBuf],
+    pub options: &'a DedupeOptions,
+    /// Scanned files, in scan order; groups refer to them by position.
+    pub files: &'a [PathBuf],
+    /// Page hashes and first-page size of each file, if it was hashed (or
+    /// is a copy of one that was).
+    pub hashed: &'a [Option<(Vec<ImageHash>, u32, u32)>],
+    pub exact_copies: usize,
+    /// Files that couldn't be decoded for hashing.
+    pub failures: &'a [usize],
+    pub groups: &'a [Group],
+    pub timings: Timings,
+}
+
+/// What the JSON and CSV reports list for each file.
+struct FileFacts {
+    size: Option<u64>,
+    mtime: Option<String>,
+    format: String,
+    dimensions: Option<(u32, u32)>,
+    hash: Option<String>,
+}
+
+impl Report<'_> {
+    /// Hashes per page; only the upright ones are reported.
+    fn stride(&self) -> usize {
+        if self.options.orientations { ORIENTATIONS.len() } else { 1 }
+    }
+
+    fn facts(&self, item: usize) -> FileFacts {
+        let path = &self.files[item];
+        let meta = fs::metadata(path).ok();
+        let hashed = self.hashed[item].as_ref();
+        FileFacts {
+            size: meta.as_ref().map(|m| m.len()),
+            mtime: meta.and_then(|m| m.modified().ok()).map(rfc3339),
+            format: path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+            dimensions: hashed.map(|&(_, w, h)| (w, h)).filter(|&(w, h)| w > 0 && h > 0),
+            hash: hashed
+                .filter(|(hashes, ..)| !hashes.is_empty())
+                .map(|(hashes, ..)| hex(hashes, self.stride())),
+        }
+    }
+
+    /// Every file in group order, the original first, with its group
+    /// number and whether it is the original.
+    fn rows(&self) -> impl Iterator<Item = (usize, bool, Duplicate)> + '_ {
+        self.groups.iter().enumerate().flat_map(|(g, group)| {
+            std::iter::once((g + 1, true, original(group)))
+                .chain(group.duplicates.iter().map(move |d| (g + 1, false, d.clone())))
+        })
+    }
+
+    /// Scan parameters and results as (key, value) pairs, for the CSV
+    /// summary file.
+    fn summary(&self) -> Vec<(&'static str, String)> {
+        let options = self.options;
+        let mut summary = vec![
+            ("time", self.started.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+            ("files_scanned", self.files.len().to_string()),
+            ("exact_copies", self.exact_copies.to_string()),
+            ("decode_failures", self.failures.len().to_string()),
+            ("groups", self.groups.len().to_string()),
+            ("exact_only", options.exact_only.to_string()),
+        ];
+        if !options.exact_only {
+            summary.extend([
+                ("hash", options.describe_hash()),
+                ("threshold_bits", options.max_distance().to_string()),
+                ("pages", if options.all_pages { "all" } else { "first" }.to_string()),
+                ("cluster", options.cluster.name().to_string()),
+            ]);
+        }
+        summary.extend([
+            ("keep", options.keep.describe()),
+            ("collect_ms", self.timings.collect.as_millis().to_string()),
+            ("exact_ms", self.timings.exact.as_millis().to_string()),
+            ("hash_ms", self.timings.hash.as_millis().to_string()),
+            ("cluster_ms", self.timings.cluster.as_millis().to_string()),
+            ("total_ms", self.timings.total().as_millis().to_string()),
+        ]);
+        summary
+    }
+}
+
+/// A group's original, listed like its duplicates: at distance 0 from
+/// itself, upright. Reports leave its `exact` empty, since there is nothing
+/// for it to be a copy of.
+fn original(group: &Group) -> Duplicate {
+    Duplicate {
+        item: group.original,
+        distance: 0,
+        exact: true,
+        transform: Orientation::NoTransforms,
+    }
+}
+
+/// `time` in UTC, e.g. "2024-05-01T12:00:00Z".
+fn rfc3339(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Page hashes in hex, comma separated: every `stride`th one.
+fn hex(hashes: &[ImageHash], stride: usize) -> String {
+    let pages: Vec<String> = hashes
+        .iter()
+        .step_by(stride)
+        .map(|h| h.as_bytes().iter().map(|b| format!("{:02x}", b)).collect())
+        .collect();
+    pages.join(",")
+}
+
+/// `path` for the JSON and CSV reports: as is if it is UTF-8, else its
+/// bytes with '%', controls and non-ASCII percent-encoded. True if encoded.
+fn encode_path(path: &Path) -> (String, bool) {
+    if let Some(s) = path.to_str() {
+        return (s.to_string(), false);
+    }
+    let mut out = String::new();
+    for &b in path.as_os_str().as_encoded_bytes() {
+        if (0x20..0x7f).contains(&b) && b != b'%' {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    (out, true)
+}
+
+/// Where the CSV report's summary goes: `dupes.csv` -> `dupes.summary.csv`.
+fn summary_path(dump: &Path) -> PathBuf {
+    dump.with_extension("summary.csv")
+}
+
+/// Write the report to `path` (and for CSV, its summary next to it).
+pub fn write_report(path: &Path, report: &Report, format: DumpFormat) -> io::Result<()> {
+    let out = BufWriter::new(fs::File::create(path)?);
+    match format {
+        DumpFormat::Text => write_text(out, report),
+        DumpFormat::Json => write_json(out, report),
+        DumpFormat::Csv => {
+            write_csv(out, report)?;
+            write_csv_summary(BufWriter::new(fs::File::create(summary_path(path))?), report)
+        }
+    }
+}
+
+// -- Text -----------------------------------------------------------------------
+
+fn write_text(mut out: impl Write, report: &Report) -> io::Result<()> {
+    let options = report.options;
+    writeln!(out, "Duplicate Scan Report")?;
+    writeln!(out, "Time: {}", report.started.format("%Y-%m-%d %H:%M:%S"))?;
+    writeln!(out, "Scanned Directories:")?;
+    for p in report.roots {
+        writeln!(out, "  - {}", p.display())?;
+    }
+    writeln!(out, "Total Files Scanned: {}", report.files.len())?;
+    writeln!(out, "Exact Copies: {}", report.exact_copies)?;
+    writeln!(out, "Decode Failures: {}", report.failures.len())?;
+    if options.exact_only {
+        writeln!(out, "Mode: exact copies only")?;
+    } else {
+        writeln!(out, "Hash: {}", options.describe_hash())?;
+        writeln!(out, "Threshold: {}", options.describe_threshold())?;
+        writeln!(out, "Pages Hashed: {}", if options.all_pages { "all" } else { "first" })?;
+        writeln!(out, "Clustering: {}", options.cluster.name())?;
+    }
+    writeln!(out, "Keep: {}", options.keep.describe())?;
+    writeln!(out, "--------------------------------------------------")?;
+
+    for group in report.groups {
+        // The original is the file the keep policy prefers
+        writeln!(out, "# {}", report.files[group.original].display())?;
+        for dup in &group.duplicates {
+            let path = report.files[dup.item].display();
+            if dup.exact {
+                writeln!(out, "E 0 {}", path)?;
+            } else if dup.transform != Orientation::NoTransforms {
+                writeln!(out, "T {} {} {}", dup.distance, transform_name(dup.transform), path)?;
+            } else {
+                writeln!(out, "D {} {}", dup.distance, path)?;
+            }
+        }
+    }
+    out.flush()
+}
+
+// -- JSON -----------------------------------------------------------------------
+
+fn json_or_null(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "null".to_string())
+}
+
+/// `"path":...`, plus `"path_encoding":"percent"` if it isn't UTF-8.
+fn json_path(path: &Path) -> String {
+    match encode_path(path) {
+        (path, false) => format!("\"path\":{}", json_string(&path)),
+        (path, true) => format!("\"path\":{},\"path_encoding\":\"percent\"", json_string(&path)),
+    }
+}
+
+/// One file of a group; `exact` is null for the original.
+fn json_file(report: &Report, file: &Duplicate, is_original: bool) -> String {
+    let facts = report.facts(file.item);
+    let (width, height) = match facts.dimensions {
+        Some((w, h)) => (w.to_string(), h.to_string()),
+        None => ("null".to_string(), "null".to_string()),
+    };
+    format!(
+        "{{{},\"size\":{},\"width\":{},\"height\":{},\"format\":{},\"mtime\":{},\"hash\":{},\"distance\":{},\"exact\":{},\"transform\":{}}}",
+        json_path(&report.files[file.item]),
+        json_or_null(facts.size.map(|s| s.to_string())),
+        width,
+        height,
+        json_string(&facts.format),
+        json_or_null(facts.mtime.as_deref().map(json_string)),
+        json_or_null(facts.hash.as_deref().map(json_string)),
+        file.distance,
+        if is_original { "null".to_string() } else { file.exact.to_string() },
+        json_string(transform_name(file.transform))
+    )
+}
+
+/// {"version": 1, "summary": {...}, "failures": [{"path": ...}, ...],
+///  "groups": [{"original": file, "duplicates": [file, ...]}, ...]}
+fn write_json(mut out: impl Write, report: &Report) -> io::Result<()> {
+    let options = report.options;
+    let timings = &report.timings;
+    let roots: Vec<String> = report.roots.iter().map(|p| format!("{{{}}}", json_path(p))).collect();
+    let (hash, threshold) = if options.exact_only {
+        ("null".to_string(), "null".to_string())
+    } else {
+        let bits = options.max_distance();
+        (
+            format!(
+                "{{\"algorithm\":{},\"width\":{},\"height\":{},\"bits\":{},\"filter\":{},\"dct\":{},\"diff_gauss\":{},\"orientations\":{},\"pages\":{}}}",
+                json_string(options.hash_alg.name()),
+                options.hash_size.0,
+                options.hash_size.1,
+                options.hash_bits(),
+                json_string(options.hash_filter.name()),
+                options.uses_dct(),
+                options.diff_gauss,
+                options.orientations,
+                json_string(if options.all_pages { "all" } else { "first" })
+            ),
+            format!(
+                "{{\"bits\":{},\"similarity\":{:.1}}}",
+                bits,
+                100.0 * (1.0 - bits as f64 / options.hash_bits() as f64)
+            ),
+        )
+    };
+
+    writeln!(out, "{{\"version\":1,")?;
+    writeln!(
+        out,
+        "\"summary\":{{\"time\":{},\"paths\":[{}],\"files_scanned\":{},\"exact_copies\":{},\"decode_failures\":{},\"groups\":{},",
+        json_string(&report.started.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+        roots.join(","),
+        report.files.len(),
+        report.exact_copies,
+        report.failures.len(),
+        report.groups.len()
+    )?;
+    writeln!(
+        out,
+        "\"exact_only\":{},\"hash\":{},\"threshold\":{},\"cluster\":{},\"keep\":{},",
+        options.exact_only,
+        hash,
+        threshold,
+        json_string(options.cluster.name()),
+        json_string(&options.keep.describe())
+    )?;
+    writeln!(
+        out,
+        "\"timings_ms\":{{\"collect\":{},\"exact\":{},\"hash\":{},\"cluster\":{},\"total\":{}}}}},",
+        timings.collect.as_millis(),
+        timings.exact.as_millis(),
+        timings.hash.as_millis(),
+        timings.cluster.as_millis(),
+        timings.total().as_millis()
+    )?;
+
+    let failures: Vec<String> = report.failures.iter().map(|&i| format!("{{{}}}", json_path(&report.files[i]))).collect();
+    writeln!(out, "\"failures\":[{}],", failures.join(","))?;
+
+    writeln!(out, "\"groups\":[")?;
+    for (g, group) in report.groups.iter().enumerate() {
+        let duplicates: Vec<String> = group
+            .duplicates
+            .iter()
+            .map(|d| json_file(report, d, false))
+            .collect();
+        write!(
+            out,
+            "{}{{\"original\":{},\"duplicates\":[{}]}}",
+            if g > 0 { ",\n" } else { "" },
+            json_file(report, &original(group), true),
+            duplicates.join(",")
+        )?;
+    }
+    writeln!(out, "\n]}}")?;
+    out.flush()
+}
+
+// -- CSV ------------------------------------------------------------------------
+
+/// `s` as a CSV field, quoted if it needs to be (RFC 4180).
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) || s.starts_with(' ') || s.ends_with(' ') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// `key,value` rows, one `path` per scanned root (`path_percent` if it
+/// had to be encoded).
+fn write_csv_summary(mut out: impl Write, report: &Report) -> io::Result<()> {
+    writeln!(out, "key,value")?;
+    for (key, value) in report.summary() {
+        writeln!(out, "{},{}", key, csv_field(&value))?;
+    }
+    for root in report.roots {
+        let (path, encoded) = encode_path(root);
+        writeln!(out, "{},{}", if encoded { "path_percent" } else { "path" }, csv_field(&path))?;
+    }
+    out.flush()
+}
+
+/// group,role,path,path_encoding,size,width,height,format,mtime,hash,distance,exact,transform
+/// with role "original" or "duplicate" (the original's `exact` is empty).
+/// Undecodable files follow with role "failed" and no group.
+fn write_csv(mut out: impl Write, report: &Report) -> io::Result<()> {
+    writeln!(out, "group,role,path,path_encoding,size,width,height,format,mtime,hash,distance,exact,transform")?;
+
+    // `file` is None for undecodable files, which have no match columns
+    let row = |out: &mut dyn Write, group: String, role: &str, item: usize, file: Option<&Duplicate>| {
+        let exact = file.filter(|_| role != "original").map(|f| f.exact.to_string());
+        let facts = report.facts(item);
+        let (width, height) = match facts.dimensions {
+            Some((w, h)) => (w.to_string(), h.to_string()),
+            None => (String::new(), String::new()),
+        };
+        let (path, encoded) = encode_path(&report.files[item]);
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            group,
+            role,
+            csv_field(&path),
+            if encoded { "percent" } else { "" },
+            facts.size.map(|s| s.to_string()).unwrap_or_default(),
+            width,
+            height,
+            csv_field(&facts.format),
+            facts.mtime.unwrap_or_default(),
+            csv_field(&facts.hash.unwrap_or_default()),
+            file.map(|f| f.distance.to_string()).unwrap_or_default(),
+            exact.unwrap_or_default(),
+            file.map(|f| transform_name(f.transform)).unwrap_or_default()
+        )
+    };
+    for (group, is_original, file) in report.rows() {
+        let role = if is_original { "original" } else { "duplicate" };
+        row(&mut out, group.to_string(), role, file.item, Some(&file))?;
+    }
+    for &item in report.failures {
+        row(&mut out, String::new(), "failed", item, None)?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field("a.jpg"), "a.jpg");
+        assert_eq!(csv_field("a,\"b\".jpg"), "\"a,\"\"b\"\".jpg\"");
+        assert_eq!(csv_field(" a.jpg"), "\" a.jpg\"");
+    }
+
+    #[test]
+    fn utf8_paths_are_kept_as_is() {
+        assert_eq!(encode_path(Path::new("/photos/été 100%.jpg")), ("/photos/été 100%.jpg".to_string(), false));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn other_paths_are_percent_encoded() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"/photos/caf\xe9 100%.jpg"));
+        assert_eq!(encode_path(path), ("/photos/caf%E9 100%25.jpg".to_string(), true));
+        assert_eq!(json_path(path), "\"path\":\"/photos/caf%E9 100%25.jpg\",\"path_encoding\":\"percent\"");
+    }
+
+    #[test]
+    fn summary_sits_next_to_the_report() {
+        assert_eq!(summary_path(Path::new("out/dupes.csv")), Path::new("out/dupes.summary.csv"));
+        assert_eq!(summary_path(Path::new("dupes")), Path::new("dupes.summary.csv"));
+    }
+}
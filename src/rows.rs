@@ -0,0 +1,656 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+use std::path::Path;
+
+use crate::loader::DecodeError;
+
+// ---------------------------------------------------------------------------
+// Large PNG and JPEG files, read top to bottom
+// ---------------------------------------------------------------------------
+//
+// The image crate decodes these formats into one buffer, which images far
+// larger than memory can't have. Here they are read a row at a time instead,
+// for `tiles` to downsample as the rows go by. PNG rows come from the png
+// crate at full resolution. JPEG has no cheap way to a smaller size in the
+// image crate, but the DC coefficient of an 8x8 block is the block's mean:
+// decoding only those, and skipping over the rest of the entropy-coded data
+// without an inverse DCT, gives the image at 1/8 scale. That works for
+// baseline JPEGs with all components in one scan, i.e. nearly all large
+// ones; progressive files have no DC-only pass to read top to bottom.
+
+/// Rows of an image, top to bottom.
+pub trait RowDecoder {
+    /// Full-resolution size of the image.
+    fn dimensions(&self) -> (u32, u32);
+    /// Pyramid level of the rows: each is the image scaled by 1/2^level,
+    /// rounded up.
+    fn level(&self) -> u32;
+    /// The next row as RGBA8, or None after the last one.
+    fn next_row(&mut self) -> Result<Option<&[u8]>, DecodeError>;
+}
+
+/// Open `path` for reading row by row. None unless it is a PNG or JPEG
+/// laid out in a way that can be read that way.
+pub fn open(path: &Path) -> Result<Option<Box<dyn RowDecoder>>, DecodeError> {
+    let open = || -> io::Result<BufReader<File>> { Ok(BufReader::new(File::open(path)?)) };
+    Ok(match image::ImageFormat::from_path(path) {
+        Ok(image::ImageFormat::Png) => PngRows::new(open()?)?.map(|rows| Box::new(rows) as Box<dyn RowDecoder>),
+        Ok(image::ImageFormat::Jpeg) => JpegRows::new(open()?)?.map(|rows| Box::new(rows) as Box<dyn RowDecoder>),
+        _ => None,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// PNG
+// ---------------------------------------------------------------------------
+
+struct PngRows<R: BufRead + Seek> {
+    reader: png::Reader<R>,
+    color: png::ColorType,
+    rgba: Vec<u8>,
+}
+
+impl<R: BufRead + Seek> PngRows<R> {
+    /// None for interlaced files, whose passes don't come top to bottom.
+    fn new(reader: R) -> Result<Option<Self>, DecodeError> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let reader = decoder.read_info().map_err(png_error)?;
+        if reader.info().interlaced {
+            return Ok(None);
+        }
+        let (color, _) = reader.output_color_type();
+        Ok(Some(Self { reader, color, rgba: Vec::new() }))
+    }
+}
+
+fn png_error(e: png::DecodingError) -> DecodeError {
+    match e {
+        png::DecodingError::IoError(e) => e.into(),
+        png::DecodingError::LimitsExceeded => DecodeError::Limits(e.to_string()),
+        _ => DecodeError::Corrupt(e.to_string()),
+    }
+}
+
+impl<R: BufRead + Seek> RowDecoder for PngRows<R> {
+    fn dimensions(&self) -> (u32, u32) {
+        let info = self.reader.info();
+        (info.width, info.height)
+    }
+
+    fn level(&self) -> u32 {
+        0
+    }
+
+    fn next_row(&mut self) -> Result<Option<&[u8]>, DecodeError> {
+        let Some(row) = self.reader.next_row().map_err(png_error)? else { return Ok(None) };
+        let data = row.data();
+        self.rgba.clear();
+        // Palettes and low bit depths are expanded by the decoder
+        match self.color {
+            png::ColorType::Grayscale => {
+                for &v in data {
+                    self.rgba.extend_from_slice(&[v, v, v, 255]);
+                }
+            }
+            png::ColorType::GrayscaleAlpha => {
+                for p in data.chunks_exact(2) {
+                    self.rgba.extend_from_slice(&[p[0], p[0], p[0], p[1]]);
+                }
+            }
+            png::ColorType::Rgb => {
+                for p in data.chunks_exact(3) {
+                    self.rgba.extend_from_slice(&[p[0], p[1], p[2], 255]);
+                }
+            }
+            _ => self.rgba.extend_from_slice(data),
+        }
+        Ok(Some(&self.rgba))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// JPEG, DC coefficients only
+// ---------------------------------------------------------------------------
+
+/// Canonical Huffman table (ITU T.81 annex C).
+struct Huffman {
+    /// (code length, symbol) for codes of up to 8 bits, by the next 8 bits
+    /// of input; length 0 for longer codes.
+    fast: [(u8, u8); 256],
+    /// Largest code of each length (index 1-16), -1 if there is none.
+    max_code: [i32; 17],
+    /// Added to a code of each length to get its index in `symbols`.
+    offset: [i32; 17],
+    symbols: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8], symbols: Vec<u8>) -> Result<Self, DecodeError> {
+        let corrupt = || DecodeError::Corrupt("invalid JPEG Huffman table".to_string());
+        let mut table = Self { fast: [(0, 0); 256], max_code: [-1; 17], offset: [0; 17], symbols };
+        let (mut code, mut index) = (0i32, 0i32);
+        for len in 1..=16 {
+            let n = counts[len - 1] as i32;
+            if code + n > 1 << len || (index + n) as usize > table.symbols.len() {
+                return Err(corrupt());
+            }
+            table.offset[len] = index - code;
+            if n > 0 {
+                table.max_code[len] = code + n - 1;
+            }
+            if len <= 8 {
+                let spread = 8 - len;
+                for c in code..code + n {
+                    let symbol = table.symbols[(c + table.offset[len]) as usize];
+                    for low in 0..1 << spread {
+                        table.fast[((c << spread) | low) as usize] = (len as u8, symbol);
+                    }
+                }
+            }
+            code = (code + n) << 1;
+            index += n;
+        }
+        Ok(table)
+    }
+}
+
+/// Entropy-coded data, with byte stuffing undone. A marker ends the data:
+/// zeros are read past it until the marker is dealt with.
+struct BitReader<R> {
+    reader: R,
+    /// Unread bits, most significant first.
+    bits: u64,
+    count: u32,
+    marker: Option<u8>,
+}
+
+impl<R: BufRead> BitReader<R> {
+    fn read_byte(&mut self) -> Result<Option<u8>, DecodeError> {
+        let buf = self.reader.fill_buf()?;
+        let Some(&byte) = buf.first() else { return Ok(None) };
+        self.reader.consume(1);
+        Ok(Some(byte))
+    }
+
+    /// The byte after a 0xFF, skipping fill bytes; None at end of file.
+    fn read_marker_byte(&mut self) -> Result<Option<u8>, DecodeError> {
+        loop {
+            match self.read_byte()? {
+                Some(0xFF) => {}
+                other => return Ok(other),
+            }
+        }
+    }
+
+    fn fill(&mut self) -> Result<(), DecodeError> {
+        while self.count <= 56 {
+            let byte = match self.marker {
+                Some(_) => 0,
+                None => match self.read_byte()? {
+                    Some(0xFF) => match self.read_marker_byte()? {
+                        Some(0) => 0xFF,
+                        found => {
+                            // A cut-off file reads as if it ended properly
+                            self.marker = Some(found.unwrap_or(0xD9));
+                            0
+                        }
+                    },
+                    Some(byte) => byte,
+                    None => {
+                        self.marker = Some(0xD9);
+                        0
+                    }
+                },
+            };
+            self.bits |= (byte as u64) << (56 - self.count);
+            self.count += 8;
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, n: u32) -> Result<u32, DecodeError> {
+        if n == 0 {
+            return Ok(0);
+        }
+        if self.count < n {
+            self.fill()?;
+        }
+        let value = (self.bits >> (64 - n)) as u32;
+        self.bits <<= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn decode(&mut self, table: &Huffman) -> Result<u8, DecodeError> {
+        if self.count < 16 {
+            self.fill()?;
+        }
+        let (len, symbol) = table.fast[(self.bits >> 56) as usize];
+        if len > 0 {
+            self.bits <<= len;
+            self.count -= len as u32;
+            return Ok(symbol);
+        }
+        let mut code = 0i32;
+        for len in 1..=16 {
+            code = (code << 1) | self.take(1)? as i32;
+            if code <= table.max_code[len] {
+                return Ok(table.symbols[(code + table.offset[len]) as usize]);
+            }
+        }
+        Err(DecodeError::Corrupt("invalid JPEG Huffman code".to_string()))
+    }
+
+    /// An `n`-bit coefficient value (T.81 figure F.12).
+    fn receive_extend(&mut self, n: u32) -> Result<i32, DecodeError> {
+        let value = self.take(n)? as i32;
+        Ok(if n > 0 && value < 1 << (n - 1) { value - (1 << n) + 1 } else { value })
+    }
+
+    /// Drop what is left of a restart interval, up to and including its
+    /// RSTn marker.
+    fn restart(&mut self) -> Result<(), DecodeError> {
+        self.bits = 0;
+        self.count = 0;
+        match self.marker {
+            Some(0xD0..=0xD7) => self.marker = None,
+            // Not a restart: the data ended early
+            Some(_) => {}
+            None => loop {
+                match self.read_byte()? {
+                    Some(0xFF) => match self.read_marker_byte()? {
+                        Some(0xD0..=0xD7) => break,
+                        Some(0) => {}
+                        found => {
+                            self.marker = Some(found.unwrap_or(0xD9));
+                            break;
+                        }
+                    },
+                    Some(_) => {}
+                    None => {
+                        self.marker = Some(0xD9);
+                        break;
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// A component as the frame header declares it.
+struct FrameComponent {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant_table: usize,
+}
+
+struct Component {
+    /// Blocks per MCU across and down.
+    h: usize,
+    v: usize,
+    /// Quantisation of the DC coefficient.
+    quant: i32,
+    dc_table: usize,
+    ac_table: usize,
+    /// Last DC value, which the next one is coded against.
+    pred: i32,
+    /// Block means of the current MCU row: `v` rows of blocks.
+    means: Vec<u8>,
+}
+
+/// Baseline JPEG at 1/8 scale: one pixel per 8x8 block.
+struct JpegRows<R> {
+    bits: BitReader<R>,
+    width: u32,
+    height: u32,
+    /// In scan order, which is the order of their blocks in an MCU.
+    components: Vec<Component>,
+    /// 3 components that are RGB rather than YCbCr (Adobe transform 0).
+    rgb: bool,
+    h_max: usize,
+    v_max: usize,
+    mcus_across: usize,
+    restart_interval: usize,
+    /// MCUs until the next restart marker.
+    until_restart: usize,
+    dc_tables: [Option<Huffman>; 4],
+    ac_tables: [Option<Huffman>; 4],
+    /// Rows returned so far, and the next one's index within its MCU row.
+    rows_done: u32,
+    mcu_row_line: usize,
+    rgba: Vec<u8>,
+}
+
+fn read_u8(reader: &mut impl Read) -> Result<u8, DecodeError> {
+    let mut byte = [0u8];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> Result<u16, DecodeError> {
+    let mut bytes = [0u8; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_be_bytes(bytes))
+}
+
+impl<R: BufRead> JpegRows<R> {
+    /// Read the headers up to the first scan. None for JPEGs that aren't
+    /// baseline, have more than one scan or aren't gray or YCbCr/RGB.
+    fn new(mut reader: R) -> Result<Option<Self>, DecodeError> {
+        let corrupt = |what: &str| DecodeError::Corrupt(format!("invalid JPEG {}", what));
+        if read_u16(&mut reader)? != 0xFFD8 {
+            return Err(corrupt("header"));
+        }
+
+        let mut quant = [None; 4];
+        let mut dc_tables: [Option<Huffman>; 4] = Default::default();
+        let mut ac_tables: [Option<Huffman>; 4] = Default::default();
+        let mut restart_interval = 0;
+        let mut adobe_transform = None;
+        // Width, height and components
+        let mut frame: Option<(u32, u32, Vec<FrameComponent>)> = None;
+
+        loop {
+            if read_u8(&mut reader)? != 0xFF {
+                return Err(corrupt("marker"));
+            }
+            let mut marker = read_u8(&mut reader)?;
+            while marker == 0xFF {
+                marker = read_u8(&mut reader)?;
+            }
+            if matches!(marker, 0x01 | 0xD0..=0xD7) {
+                continue;
+            }
+            if marker == 0xD9 {
+                return Err(corrupt("file: no image data"));
+            }
+            let len = read_u16(&mut reader)?.checked_sub(2).ok_or_else(|| corrupt("segment"))?;
+            let mut segment = vec![0u8; len as usize];
+            reader.read_exact(&mut segment)?;
+            let mut s = segment.as_slice();
+
+            match marker {
+                // Baseline and extended sequential Huffman
+                0xC0 | 0xC1 => {
+                    let precision = read_u8(&mut s)?;
+                    let height = read_u16(&mut s)? as u32;
+                    let width = read_u16(&mut s)? as u32;
+                    let count = read_u8(&mut s)?;
+                    // 12-bit samples, or the height in a DNL marker after the scan
+                    if precision != 8 || height == 0 || width == 0 {
+                        return Ok(None);
+                    }
+                    let mut components = Vec::new();
+                    for _ in 0..count {
+                        let id = read_u8(&mut s)?;
+                        let sampling = read_u8(&mut s)?;
+                        let (h, v) = ((sampling >> 4) as usize, (sampling & 15) as usize);
+                        let table = read_u8(&mut s)? as usize;
+                        if !(1..=4).contains(&h) || !(1..=4).contains(&v) || table > 3 {
+                            return Err(corrupt("frame header"));
+                        }
+                        components.push(FrameComponent { id, h, v, quant_table: table });
+                    }
+                    frame = Some((width, height, components));
+                }
+                // Progressive, lossless, arithmetic coding
+                0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return Ok(None),
+                0xC4 => {
+                    while !s.is_empty() {
+                        let class_id = read_u8(&mut s)?;
+                        let mut counts = [0u8; 16];
+                        s.read_exact(&mut counts)?;
+                        let mut symbols = vec![0u8; counts.iter().map(|&n| n as usize).sum()];
+                        s.read_exact(&mut symbols)?;
+                        let id = (class_id & 15) as usize;
+                        let table = Some(Huffman::new(&counts, symbols)?);
+                        match class_id >> 4 {
+                            0 if id < 4 => dc_tables[id] = table,
+                            1 if id < 4 => ac_tables[id] = table,
+                            _ => return Err(corrupt("Huffman table")),
+                        }
+                    }
+                }
+                0xDB => {
+                    while !s.is_empty() {
+                        let precision_id = read_u8(&mut s)?;
+                        let id = (precision_id & 15) as usize;
+                        let wide = precision_id >> 4 != 0;
+                        // The DC value comes first in zigzag order
+                        let dc = if wide { read_u16(&mut s)? as i32 } else { read_u8(&mut s)? as i32 };
+                        let mut rest = vec![0u8; if wide { 126 } else { 63 }];
+                        s.read_exact(&mut rest)?;
+                        *quant.get_mut(id).ok_or_else(|| corrupt("quantisation table"))? = Some(dc);
+                    }
+                }
+                0xDD => restart_interval = read_u16(&mut s)? as usize,
+                0xEE if s.starts_with(b"Adobe") && s.len() >= 12 => adobe_transform = Some(s[11]),
+                0xDA => {
+                    let Some((width, height, frame_components)) = frame else {
+                        return Err(corrupt("scan before frame header"));
+                    };
+                    let count = read_u8(&mut s)? as usize;
+                    // Each component in a scan of its own can't be read row by row
+                    if count != frame_components.len() || !matches!(count, 1 | 3) {
+                        return Ok(None);
+                    }
+                    let mut components = Vec::new();
+                    for _ in 0..count {
+                        let id = read_u8(&mut s)?;
+                        let tables = read_u8(&mut s)?;
+                        let &FrameComponent { h, v, quant_table, .. } = frame_components
+                            .iter()
+                            .find(|c| c.id == id)
+                            .ok_or_else(|| corrupt("scan header"))?;
+                        let (dc_table, ac_table) = ((tables >> 4) as usize, (tables & 15) as usize);
+                        if dc_tables.get(dc_table).is_none_or(Option::is_none)
+                            || ac_tables.get(ac_table).is_none_or(Option::is_none)
+                        {
+                            return Err(corrupt("scan header"));
+                        }
+                        let quant = quant[quant_table].ok_or_else(|| corrupt("quantisation table"))?;
+                        // A lone component's MCU is one block, whatever its sampling
+                        let (h, v) = if count == 1 { (1, 1) } else { (h, v) };
+                        components.push(Component { h, v, quant, dc_table, ac_table, pred: 0, means: Vec::new() });
+                    }
+
+                    let h_max = components.iter().map(|c| c.h).max().unwrap_or(1);
+                    let v_max = components.iter().map(|c| c.v).max().unwrap_or(1);
+                    let mcus_across = width.div_ceil(8 * h_max as u32) as usize;
+                    for c in &mut components {
+                        c.means = vec![0; mcus_across * c.h * c.v];
+                    }
+                    return Ok(Some(Self {
+                        bits: BitReader { reader, bits: 0, count: 0, marker: None },
+                        width,
+                        height,
+                        components,
+                        rgb: count == 3 && adobe_transform == Some(0),
+                        h_max,
+                        v_max,
+                        mcus_across,
+                        restart_interval,
+                        until_restart: restart_interval,
+                        dc_tables,
+                        ac_tables,
+                        rows_done: 0,
+                        mcu_row_line: v_max,
+                        rgba: Vec::new(),
+                    }));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Decode the next row of MCUs into the components' block means.
+    fn decode_mcu_row(&mut self) -> Result<(), DecodeError> {
+        let missing = || DecodeError::Corrupt("invalid JPEG scan header".to_string());
+        for mcu in 0..self.mcus_across {
+            if self.restart_interval > 0 {
+                if self.until_restart == 0 {
+                    self.bits.restart()?;
+                    for c in &mut self.components {
+                        c.pred = 0;
+                    }
+                    self.until_restart = self.restart_interval;
+                }
+                self.until_restart -= 1;
+            }
+            for c in &mut self.components {
+                let dc_table = self.dc_tables[c.dc_table].as_ref().ok_or_else(missing)?;
+                let ac_table = self.ac_tables[c.ac_table].as_ref().ok_or_else(missing)?;
+                for by in 0..c.v {
+                    for bx in 0..c.h {
+                        let size = self.bits.decode(dc_table)? as u32;
+                        c.pred += self.bits.receive_extend(size)?;
+                        // Skip the AC coefficients (T.81 figure F.13)
+                        let mut k = 1;
+                        while k < 64 {
+                            let run_size = self.bits.decode(ac_table)?;
+                            let (run, size) = (run_size >> 4, run_size & 15);
+                            if size == 0 {
+                                if run != 15 {
+                                    break;
+                                }
+                                k += 16;
+                            } else {
+                                self.bits.take(size as u32)?;
+                                k += run as usize + 1;
+                            }
+                        }
+                        // DC is 8 times the block mean, level shifted by 128
+                        let mean = ((c.pred * c.quant + 4).div_euclid(8) + 128).clamp(0, 255) as u8;
+                        c.means[by * self.mcus_across * c.h + mcu * c.h + bx] = mean;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> RowDecoder for JpegRows<R> {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn level(&self) -> u32 {
+        3
+    }
+
+    fn next_row(&mut self) -> Result<Option<&[u8]>, DecodeError> {
+        if self.rows_done == self.height.div_ceil(8) {
+            return Ok(None);
+        }
+        // Each MCU row gives `v_max` rows of block means
+        if self.mcu_row_line == self.v_max {
+            self.decode_mcu_row()?;
+            self.mcu_row_line = 0;
+        }
+        let line = self.mcu_row_line;
+        let (h_max, v_max) = (self.h_max, self.v_max);
+        let width = self.width.div_ceil(8) as usize;
+        // Subsampled components cover more than one pixel per block
+        let sample = |c: &Component, x: usize| {
+            let stride = self.mcus_across * c.h;
+            c.means[line * c.v / v_max * stride + x * c.h / h_max] as f32
+        };
+
+        self.rgba.clear();
+        for x in 0..width {
+            let pixel = match self.components.as_slice() {
+                [gray] => {
+                    let v = sample(gray, x) as u8;
+                    [v, v, v, 255]
+                }
+                [a, b, c] if self.rgb => [sample(a, x) as u8, sample(b, x) as u8, sample(c, x) as u8, 255],
+                [y, cb, cr] => {
+                    let (y, cb, cr) = (sample(y, x), sample(cb, x) - 128.0, sample(cr, x) - 128.0);
+                    let clamp = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+                    [clamp(y + 1.402 * cr), clamp(y - 0.344_136 * cb - 0.714_136 * cr), clamp(y + 1.772 * cb), 255]
+                }
+                _ => unreachable!("checked when the scan started"),
+            };
+            self.rgba.extend_from_slice(&pixel);
+        }
+        self.mcu_row_line += 1;
+        self.rows_done += 1;
+        Ok(Some(&self.rgba))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageEncoder, RgbImage};
+    use std::io::Cursor;
+
+    /// Smooth colours, so 8x8 block means are well defined.
+    fn gradient(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| image::Rgb([(x * 3) as u8, (y * 4) as u8, ((x + y) * 2) as u8]))
+    }
+
+    fn collect(rows: &mut dyn RowDecoder) -> Vec<Vec<u8>> {
+        let mut out = Vec::new();
+        while let Some(row) = rows.next_row().unwrap() {
+            out.push(row.to_vec());
+        }
+        out
+    }
+
+    #[test]
+    fn png_rows_match_the_image() {
+        let img = gradient(37, 21);
+        let mut png = Vec::new();
+        image::codecs::png::PngEncoder::new(&mut png)
+            .write_image(img.as_raw(), img.width(), img.height(), image::ExtendedColorType::Rgb8)
+            .unwrap();
+        let mut rows = PngRows::new(Cursor::new(png)).unwrap().unwrap();
+        assert_eq!((rows.dimensions(), rows.level()), ((37, 21), 0));
+        let rgba = image::DynamicImage::ImageRgb8(img).to_rgba8();
+        assert_eq!(collect(&mut rows).concat(), rgba.into_raw());
+    }
+
+    #[test]
+    fn jpeg_rows_are_the_block_means() {
+        // Not a multiple of the 16x16 MCU, so edge blocks are padded
+        let img = gradient(70, 45);
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 95).encode_image(&img).unwrap();
+        let decoded = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+
+        let mut rows = JpegRows::new(Cursor::new(jpeg)).unwrap().unwrap();
+        assert_eq!((rows.dimensions(), rows.level()), ((70, 45), 3));
+        let rows = collect(&mut rows);
+        assert_eq!(rows.len(), 6);
+        for (by, row) in rows.iter().enumerate() {
+            assert_eq!(row.len(), 9 * 4);
+            for (bx, pixel) in row.chunks_exact(4).enumerate() {
+                // Mean of the full decode over the block's pixels inside the image
+                let (x0, y0) = (bx as u32 * 8, by as u32 * 8);
+                let (x1, y1) = ((x0 + 8).min(70), (y0 + 8).min(45));
+                let n = ((x1 - x0) * (y1 - y0)) as f32;
+                for (c, &value) in pixel[..3].iter().enumerate() {
+                    let sum: f32 = (y0..y1).flat_map(|y| (x0..x1).map(move |x| (x, y)))
+                        .map(|(x, y)| decoded.get_pixel(x, y)[c] as f32)
+                        .sum();
+                    let mean = sum / n;
+                    // Padding and chroma subsampling shift edge blocks a little
+                    assert!((value as f32 - mean).abs() <= 12.0, "block {},{} channel {}: {} vs {}", bx, by, c, value, mean);
+                }
+                assert_eq!(pixel[3], 255);
+            }
+        }
+    }
+
+    #[test]
+    fn progressive_jpegs_are_left_to_the_normal_decoder() {
+        // SOI, then SOF2 with a one-component 8x8 frame
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xC2, 0, 11, 8, 0, 8, 0, 8, 1, 1, 0x11, 0];
+        assert!(JpegRows::new(Cursor::new(jpeg)).unwrap().is_none());
+    }
+}
@@ -0,0 +1,63 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use resvg::{tiny_skia, usvg};
+use std::sync::OnceLock;
+
+use crate::loader::DecodeError;
+
+// ---------------------------------------------------------------------------
+// SVG rasterisation (cargo feature "svg")
+// ---------------------------------------------------------------------------
+
+/// Longest side of any raster we produce, whatever the zoom.
+const MAX_RASTER_DIM: f32 = 16384.0;
+/// Without a requested scale, small icons are rendered up to this size so
+/// the first view isn't a blurry upscale.
+const DEFAULT_MIN_DIM: f32 = 1024.0;
+
+/// Parser options. Loading system fonts is slow, so it happens once.
+fn options() -> &'static usvg::Options<'static> {
+    static OPTIONS: OnceLock<usvg::Options<'static>> = OnceLock::new();
+    OPTIONS.get_or_init(|| {
+        let mut opt = usvg::Options::default();
+        opt.fontdb_mut().load_system_fonts();
+        opt
+    })
+}
+
+/// Parse SVG (or gzipped SVGZ) data.
+pub fn parse(bytes: &[u8]) -> Result<usvg::Tree, DecodeError> {
+    usvg::Tree::from_data(bytes, options()).map_err(|e| DecodeError::Corrupt(e.to_string()))
+}
+
+/// Scale used when nobody asked for a particular one.
+pub fn default_scale(tree: &usvg::Tree) -> f32 {
+    let size = tree.size();
+    (DEFAULT_MIN_DIM / size.width().max(size.height())).max(1.0)
+}
+
+/// Scale that fits the drawing inside `w` x `h`.
+pub fn fit_scale(tree: &usvg::Tree, (w, h): (u32, u32)) -> f32 {
+    let size = tree.size();
+    (w as f32 / size.width()).min(h as f32 / size.height())
+}
+
+/// Rasterise at `scale` x the intrinsic size. Returns the image and the
+/// scale actually used (it is capped at `MAX_RASTER_DIM`).
+pub fn render(tree: &usvg::Tree, scale: f32) -> Result<(DynamicImage, f32), DecodeError> {
+    let size = tree.size();
+    let scale = scale.min(MAX_RASTER_DIM / size.width().max(size.height()));
+    let w = (size.width() * scale).round().max(1.0) as u32;
+    let h = (size.height() * scale).round().max(1.0) as u32;
+
+    let mut pixmap = tiny_skia::Pixmap::new(w, h)
+        .ok_or_else(|| DecodeError::Limits(format!("cannot allocate {}x{} raster", w, h)))?;
+    resvg::render(tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    // tiny-skia works premultiplied; the rest of the pipeline expects straight alpha
+    let mut rgba = RgbaImage::new(w, h);
+    for (dst, src) in rgba.pixels_mut().zip(pixmap.pixels()) {
+        let c = src.demultiply();
+        *dst = Rgba([c.red(), c.green(), c.blue(), c.alpha()]);
+    }
+    Ok((DynamicImage::ImageRgba8(rgba), scale))
+}
@@ -0,0 +1,599 @@
+use image::DynamicImage;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::{ColorType, TiffError};
+
+use crate::loader::DecodeError;
+use crate::rows::RowDecoder;
+
+// ---------------------------------------------------------------------------
+// Tiled viewing of very large images
+// ---------------------------------------------------------------------------
+//
+// Stitched panoramas and slide scans are not decoded into one pixel buffer.
+// They get a small overview, shown when zoomed out and as a backdrop, plus
+// tiles of an image pyramid read on demand for the visible region: level L
+// is the image scaled by 1/2^L, and tiles are TILE_SIZE pixels at every
+// level. TIFFs are read strip by strip (or tile by tile) straight from disk,
+// so their uncompressed size never has to fit in memory. PNGs and JPEGs are
+// read once, top to bottom (see `rows`), and kept at the finest level that
+// fits the cache budget; zooming in further magnifies that level. Other
+// formats have no random access in the image crate: they are decoded whole
+// if that fits, and refused otherwise.
+
+/// Side of a tile, in pixels of its own level.
+pub const TILE_SIZE: u32 = 512;
+/// Images with more pixels than this are viewed through tiles.
+pub const TILED_MIN_PIXELS: u64 = 64 * 1024 * 1024;
+/// Longest side of the overview of a tiled image.
+const OVERVIEW_MAX_DIM: u32 = 2048;
+/// Deepest pyramid level; the box filter's 32-bit sums would overflow below it.
+const MAX_LEVEL: u32 = 11;
+/// Decoded TIFF chunks kept per image, so neighbouring tiles don't decode
+/// the same strip again.
+const CHUNK_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+/// TIFFs with bigger chunks (e.g. the whole image in one strip) can't be
+/// read piecemeal and take the normal decode path.
+const MAX_CHUNK_BYTES: u64 = 32 * 1024 * 1024;
+/// Rows converted at a time when cutting regions from an in-memory image.
+const BAND_ROWS: u32 = 256;
+
+impl From<TiffError> for DecodeError {
+    fn from(e: TiffError) -> Self {
+        match e {
+            TiffError::IoError(e) => e.into(),
+            TiffError::UnsupportedError(_) => DecodeError::Unsupported(e.to_string()),
+            TiffError::LimitsExceeded => DecodeError::Limits(e.to_string()),
+            _ => DecodeError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+/// Half-open pixel rectangle, in pixels of the source (full resolution
+/// unless the image is kept downsampled, see `TiledImage::min_level`).
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+/// RGBA8 source pixels at (x, y); may extend past the requested region.
+struct Block<'a> {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    rgba: &'a [u8],
+}
+
+/// Where the pixels of a tiled image come from, at its `min_level`.
+trait RegionSource: Send + Sync {
+    /// Pass blocks covering `rect` to `visit`, top to bottom.
+    fn visit(&self, rect: Rect, visit: &mut dyn FnMut(&Block)) -> Result<(), DecodeError>;
+    /// Memory held, for the cache budget.
+    fn mem_size(&self) -> u64;
+}
+
+// ---------------------------------------------------------------------------
+// TIFF read chunk by chunk from disk
+// ---------------------------------------------------------------------------
+
+struct TiffSource {
+    path: PathBuf,
+    page: usize,
+    width: u32,
+    height: u32,
+    /// Strip or tile size
+    chunk_width: u32,
+    chunk_height: u32,
+    /// 1 = gray, 2 = gray + alpha, 3 = RGB, 4 = RGBA
+    channels: usize,
+    cache: Mutex<ChunkCache>,
+}
+
+/// Least-recently-used decoded chunks, as RGBA8.
+#[derive(Default)]
+struct ChunkCache {
+    chunks: HashMap<u32, (Arc<Vec<u8>>, u64)>,
+    bytes: u64,
+    clock: u64,
+}
+
+impl ChunkCache {
+    fn get(&mut self, index: u32) -> Option<Arc<Vec<u8>>> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.chunks.get_mut(&index).map(|(chunk, used)| {
+            *used = clock;
+            Arc::clone(chunk)
+        })
+    }
+
+    fn insert(&mut self, index: u32, chunk: Arc<Vec<u8>>) {
+        self.clock += 1;
+        self.bytes += chunk.len() as u64;
+        if let Some((old, _)) = self.chunks.insert(index, (chunk, self.clock)) {
+            self.bytes -= old.len() as u64;
+        }
+        while self.bytes > CHUNK_CACHE_BYTES && self.chunks.len() > 1 {
+            let Some(oldest) = self.chunks.iter().min_by_key(|(_, (_, used))| *used).map(|(&i, _)| i) else {
+                break;
+            };
+            if let Some((old, _)) = self.chunks.remove(&oldest) {
+                self.bytes -= old.len() as u64;
+            }
+        }
+    }
+}
+
+/// Open page `page` of a TIFF for tiled viewing. None if it is small enough
+/// to decode whole, or laid out in a way only the normal path handles.
+/// Also returns the page actually opened and the page count.
+pub fn open_tiff(path: &Path, page: usize) -> Option<(TiledImage, usize, usize)> {
+    let mut decoder = Decoder::new(BufReader::new(File::open(path).ok()?)).ok()?;
+
+    // Most TIFFs are small: settle that from the page's own header before
+    // walking every IFD to count pages
+    if decoder.seek_to_image(page).is_ok() && !is_large(&mut decoder)? {
+        return None;
+    }
+
+    let mut page_count = 1;
+    while decoder.more_images() && page_count < crate::pages::MAX_PAGES && decoder.next_image().is_ok() {
+        page_count += 1;
+    }
+    let page = page.min(page_count - 1);
+    decoder.seek_to_image(page).ok()?;
+    if !is_large(&mut decoder)? {
+        return None;
+    }
+    let (width, height) = decoder.dimensions().ok()?;
+
+    let (channels, bits) = match decoder.colortype().ok()? {
+        ColorType::Gray(bits) => (1, bits),
+        ColorType::GrayA(bits) | ColorType::Multiband { bit_depth: bits, num_samples: 2 } => (2, bits),
+        ColorType::RGB(bits) => (3, bits),
+        ColorType::RGBA(bits) => (4, bits),
+        _ => return None,
+    };
+    let planar = decoder.find_tag_unsigned::<u16>(Tag::PlanarConfiguration).ok()? == Some(2);
+    let unsigned = decoder
+        .find_tag_unsigned_vec::<u16>(Tag::SampleFormat)
+        .ok()?
+        .is_none_or(|formats| formats.iter().all(|&f| f == 1));
+    if !matches!(bits, 8 | 16) || planar || !unsigned {
+        return None;
+    }
+
+    let (chunk_width, chunk_height) = decoder.chunk_dimensions();
+    if chunk_width == 0 || chunk_height == 0 || chunk_width as u64 * chunk_height as u64 * 4 > MAX_CHUNK_BYTES {
+        log::debug!("{}: chunks too large for tiled viewing", path.display());
+        return None;
+    }
+
+    let source = TiffSource {
+        path: path.to_path_buf(),
+        page,
+        width,
+        height,
+        chunk_width,
+        chunk_height,
+        channels,
+        cache: Mutex::new(ChunkCache::default()),
+    };
+    Some((TiledImage::new(width, height, 0, Box::new(source)), page, page_count))
+}
+
+/// Whether the decoder's current page is big enough for tiled viewing.
+fn is_large(decoder: &mut Decoder<BufReader<File>>) -> Option<bool> {
+    let (width, height) = decoder.dimensions().ok()?;
+    Some(width as u64 * height as u64 > TILED_MIN_PIXELS)
+}
+
+impl TiffSource {
+    fn open(&self) -> Result<Decoder<BufReader<File>>, DecodeError> {
+        let mut decoder = Decoder::new(BufReader::new(File::open(&self.path)?))?;
+        if self.page > 0 {
+            decoder.seek_to_image(self.page)?;
+        }
+        Ok(decoder)
+    }
+
+    /// Convert a decoded chunk of `pixels` pixels to RGBA8.
+    fn to_rgba(&self, data: DecodingResult, pixels: usize) -> Result<Vec<u8>, DecodeError> {
+        let samples: Vec<u8> = match data {
+            DecodingResult::U8(v) => v,
+            DecodingResult::U16(v) => v.into_iter().map(|s| (s >> 8) as u8).collect(),
+            _ => return Err(DecodeError::Unsupported("unsupported TIFF sample type".to_string())),
+        };
+        // Extra samples beyond the ones we know are skipped
+        let stride = samples.len() / pixels.max(1);
+        if stride < self.channels {
+            return Err(DecodeError::Corrupt("TIFF chunk smaller than its size".to_string()));
+        }
+        let mut rgba = Vec::with_capacity(pixels * 4);
+        for p in samples.chunks_exact(stride).take(pixels) {
+            rgba.extend_from_slice(&match self.channels {
+                1 => [p[0], p[0], p[0], 255],
+                2 => [p[0], p[0], p[0], p[1]],
+                3 => [p[0], p[1], p[2], 255],
+                _ => [p[0], p[1], p[2], p[3]],
+            });
+        }
+        Ok(rgba)
+    }
+}
+
+impl RegionSource for TiffSource {
+    fn visit(&self, rect: Rect, visit: &mut dyn FnMut(&Block)) -> Result<(), DecodeError> {
+        // Opened on the first chunk that isn't cached
+        let mut decoder = None;
+        let across = self.width.div_ceil(self.chunk_width);
+        for cy in rect.y0 / self.chunk_height..rect.y1.div_ceil(self.chunk_height) {
+            for cx in rect.x0 / self.chunk_width..rect.x1.div_ceil(self.chunk_width) {
+                let (x, y) = (cx * self.chunk_width, cy * self.chunk_height);
+                let width = self.chunk_width.min(self.width - x);
+                let height = self.chunk_height.min(self.height - y);
+                let index = cy * across + cx;
+
+                let cached = self.cache.lock().unwrap().get(index);
+                let chunk = match cached {
+                    Some(chunk) => chunk,
+                    None => {
+                        let decoder = match &mut decoder {
+                            Some(decoder) => decoder,
+                            None => decoder.insert(self.open()?),
+                        };
+                        let data = decoder.read_chunk(index)?;
+                        let chunk = Arc::new(self.to_rgba(data, width as usize * height as usize)?);
+                        self.cache.lock().unwrap().insert(index, Arc::clone(&chunk));
+                        chunk
+                    }
+                };
+                visit(&Block { x, y, width, height, rgba: &chunk });
+            }
+        }
+        Ok(())
+    }
+
+    fn mem_size(&self) -> u64 {
+        CHUNK_CACHE_BYTES
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Other formats, read once
+// ---------------------------------------------------------------------------
+
+/// Formats without random access are decoded once and kept in their own
+/// (usually 3 bytes per pixel) layout, or as RGBA8 at a coarser level when
+/// read row by row; only tiles and the overview are converted for display.
+/// The pixels are in memory, counted against the cache budget, unlike
+/// `TiffSource`.
+struct MemorySource {
+    img: DynamicImage,
+}
+
+impl RegionSource for MemorySource {
+    fn visit(&self, rect: Rect, visit: &mut dyn FnMut(&Block)) -> Result<(), DecodeError> {
+        let width = rect.x1 - rect.x0;
+        for y in (rect.y0..rect.y1).step_by(BAND_ROWS as usize) {
+            let height = BAND_ROWS.min(rect.y1 - y);
+            let band = self.img.crop_imm(rect.x0, y, width, height).to_rgba8();
+            visit(&Block { x: rect.x0, y, width, height, rgba: band.as_raw() });
+        }
+        Ok(())
+    }
+
+    fn mem_size(&self) -> u64 {
+        self.img.as_bytes().len() as u64
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Pyramid
+// ---------------------------------------------------------------------------
+
+/// Display-ready pixels of one tile (or of the overview), in the same
+/// layout as `DecodedImage::pixels`.
+pub struct Tile {
+    pub pixels: Vec<u32>,
+    pub opaque: bool,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    pub fn mem_size(&self) -> u64 {
+        self.pixels.len() as u64 * 4
+    }
+}
+
+pub struct TiledImage {
+    pub width: u32,
+    pub height: u32,
+    /// Pyramid level of the overview.
+    pub overview_level: u32,
+    /// Finest level there are pixels for: 0 unless the source is kept
+    /// downsampled.
+    min_level: u32,
+    source: Box<dyn RegionSource>,
+}
+
+impl TiledImage {
+    fn new(width: u32, height: u32, min_level: u32, source: Box<dyn RegionSource>) -> Self {
+        let mut image = Self { width, height, overview_level: 0, min_level, source };
+        image.overview_level = image.fit_level(OVERVIEW_MAX_DIM);
+        image
+    }
+
+    /// Tile an image that is already decoded.
+    pub fn from_memory(img: DynamicImage) -> Self {
+        let (width, height) = (img.width(), img.height());
+        Self::new(width, height, 0, Box::new(MemorySource { img }))
+    }
+
+    /// Read `rows` once and keep them at the finest level, at or above
+    /// their own, that fits in `max_bytes`.
+    pub fn from_rows(mut rows: Box<dyn RowDecoder>, max_bytes: u64) -> Result<Self, DecodeError> {
+        let (width, height) = rows.dimensions();
+        let base = rows.level();
+        // Rows at their own level are kept as they come; coarser levels
+        // need the downsampler's sums (16 bytes per pixel) next to the result
+        let bytes = |level: u32| {
+            let pixels = width.div_ceil(1 << level) as u64 * height.div_ceil(1 << level) as u64;
+            pixels * if level == base { 4 } else { 20 }
+        };
+        let level = (base..MAX_LEVEL).find(|&level| bytes(level) <= max_bytes).unwrap_or(MAX_LEVEL);
+        let (w, h) = (width.div_ceil(1 << level), height.div_ceil(1 << level));
+
+        let rgba = if level == base {
+            let len = w as usize * h as usize * 4;
+            let mut rgba = Vec::with_capacity(len);
+            while rgba.len() < len
+                && let Some(row) = rows.next_row()?
+            {
+                rgba.extend_from_slice(row);
+            }
+            // A file cut short leaves the rest transparent
+            rgba.resize(len, 0);
+            rgba
+        } else {
+            downsample_rows(&mut *rows, level - base)?.into_rgba()
+        };
+        let img = image::RgbaImage::from_raw(w, h, rgba)
+            .ok_or_else(|| DecodeError::Corrupt("image rows of the wrong size".to_string()))?;
+        if level > 0 {
+            log::info!("{}x{} image kept at 1/{} scale to fit the cache budget", width, height, 1 << level);
+        }
+        Ok(Self::new(width, height, level, Box::new(MemorySource { img: DynamicImage::ImageRgba8(img) })))
+    }
+
+    /// Shallowest level with pixels at which the whole image fits in
+    /// `max_dim`.
+    fn fit_level(&self, max_dim: u32) -> u32 {
+        fit_level(self.width, self.height, max_dim).max(self.min_level)
+    }
+
+    /// Downsampled copy of the whole image to show when zoomed out.
+    pub fn overview(&self) -> Result<Tile, DecodeError> {
+        self.read_level(self.overview_level - self.min_level, self.full_rect())
+    }
+
+    /// Whole image at the pyramid level that fits in `max_dim`.
+    pub fn thumbnail(&self, max_dim: u32) -> Result<Tile, DecodeError> {
+        self.read_level(self.fit_level(max_dim) - self.min_level, self.full_rect())
+    }
+
+    /// Level to show at `scale` display pixels per image pixel: the
+    /// smallest one that still has at least one pixel per display pixel,
+    /// or the finest there is.
+    pub fn level_for_scale(&self, scale: f32) -> u32 {
+        if scale >= 1.0 {
+            self.min_level
+        } else {
+            ((1.0 / scale).log2().floor() as u32).clamp(self.min_level, self.overview_level)
+        }
+    }
+
+    /// Tiles of `level` overlapping the full-resolution rectangle
+    /// (x0, y0)-(x1, y1), nearest to its centre first.
+    pub fn tiles_in(&self, level: u32, x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<(u32, u32)> {
+        let span = (TILE_SIZE << level) as f32;
+        let across = self.width.div_ceil(TILE_SIZE << level);
+        let down = self.height.div_ceil(TILE_SIZE << level);
+        let tx0 = (x0.max(0.0) / span) as u32;
+        let ty0 = (y0.max(0.0) / span) as u32;
+        let tx1 = ((x1 / span).ceil().max(0.0) as u32).min(across);
+        let ty1 = ((y1 / span).ceil().max(0.0) as u32).min(down);
+
+        let (cx, cy) = ((x0 + x1) / 2.0 / span, (y0 + y1) / 2.0 / span);
+        let mut tiles: Vec<(u32, u32)> = (ty0..ty1).flat_map(|ty| (tx0..tx1).map(move |tx| (tx, ty))).collect();
+        tiles.sort_by(|a, b| {
+            let dist = |&(tx, ty): &(u32, u32)| (tx as f32 + 0.5 - cx).powi(2) + (ty as f32 + 0.5 - cy).powi(2);
+            dist(a).total_cmp(&dist(b))
+        });
+        tiles
+    }
+
+    /// Full-resolution rectangle drawn by a `width` x `height` tile at
+    /// (tx, ty) of `level`. Edge tiles overhang the image by less than
+    /// 2^level pixels, as their last pixels average partial blocks.
+    pub fn tile_rect(&self, level: u32, tx: u32, ty: u32, width: u32, height: u32) -> (f32, f32, f32, f32) {
+        let (x0, y0) = ((tx * TILE_SIZE) << level, (ty * TILE_SIZE) << level);
+        let scale = (1u32 << level) as f32;
+        (x0 as f32, y0 as f32, x0 as f32 + width as f32 * scale, y0 as f32 + height as f32 * scale)
+    }
+
+    /// Tile (tx, ty) of `level`, which is at least `level_for_scale` of any
+    /// scale.
+    pub fn read_tile(&self, level: u32, tx: u32, ty: u32) -> Result<Tile, DecodeError> {
+        let relative = level.saturating_sub(self.min_level);
+        let span = TILE_SIZE << relative;
+        let full = self.full_rect();
+        let rect = Rect {
+            x0: tx * span,
+            y0: ty * span,
+            x1: full.x1.min((tx + 1).saturating_mul(span)),
+            y1: full.y1.min((ty + 1).saturating_mul(span)),
+        };
+        self.read_level(relative, rect)
+    }
+
+    /// Memory held besides the overview and tiles, for the cache budget.
+    pub fn mem_size(&self) -> u64 {
+        self.source.mem_size()
+    }
+
+    /// The whole image, in pixels of the source.
+    fn full_rect(&self) -> Rect {
+        let scale = 1 << self.min_level;
+        Rect { x0: 0, y0: 0, x1: self.width.div_ceil(scale), y1: self.height.div_ceil(scale) }
+    }
+
+    /// `rect` of the source, downsampled by 2^`level` relative to it.
+    fn read_level(&self, level: u32, rect: Rect) -> Result<Tile, DecodeError> {
+        if rect.x0 >= rect.x1 || rect.y0 >= rect.y1 {
+            return Err(DecodeError::Corrupt("tile outside the image".to_string()));
+        }
+        let mut downsampler = Downsampler::new(rect, level);
+        self.source.visit(rect, &mut |block| downsampler.add(block))?;
+        Ok(downsampler.finish())
+    }
+}
+
+/// Shallowest level at which a `width` x `height` image fits in `max_dim`.
+fn fit_level(width: u32, height: u32, max_dim: u32) -> u32 {
+    let longest = width.max(height);
+    (0..MAX_LEVEL).find(|&level| longest.div_ceil(1 << level) <= max_dim).unwrap_or(MAX_LEVEL)
+}
+
+/// Thumbnail fitting `max_dim` of the image in `rows`, read without
+/// keeping more of it than the thumbnail.
+pub fn thumbnail_from_rows(mut rows: Box<dyn RowDecoder>, max_dim: u32) -> Result<Tile, DecodeError> {
+    let (width, height) = rows.dimensions();
+    let level = fit_level(width, height, max_dim).max(rows.level());
+    let relative = level - rows.level();
+    Ok(downsample_rows(&mut *rows, relative)?.finish())
+}
+
+/// Feed every row of `rows` to a downsampler by 2^`level` relative to them.
+fn downsample_rows(rows: &mut dyn RowDecoder, level: u32) -> Result<Downsampler, DecodeError> {
+    let (width, height) = rows.dimensions();
+    let scale = 1 << rows.level();
+    let rect = Rect { x0: 0, y0: 0, x1: width.div_ceil(scale), y1: height.div_ceil(scale) };
+    let mut downsampler = Downsampler::new(rect, level);
+    let mut y = 0;
+    while y < rect.y1
+        && let Some(row) = rows.next_row()?
+    {
+        if row.len() < rect.x1 as usize * 4 {
+            return Err(DecodeError::Corrupt("image row shorter than its width".to_string()));
+        }
+        downsampler.add(&Block { x: 0, y, width: rect.x1, height: 1, rgba: row });
+        y += 1;
+    }
+    Ok(downsampler)
+}
+
+/// Box-filter a region down by 2^level. Sums are of premultiplied colour,
+/// so transparent pixels don't bleed into their neighbours.
+struct Downsampler {
+    rect: Rect,
+    level: u32,
+    width: u32,
+    height: u32,
+    sums: Vec<[u32; 4]>,
+}
+
+impl Downsampler {
+    fn new(rect: Rect, level: u32) -> Self {
+        let width = (rect.x1 - rect.x0).div_ceil(1 << level);
+        let height = (rect.y1 - rect.y0).div_ceil(1 << level);
+        Self { rect, level, width, height, sums: vec![[0; 4]; width as usize * height as usize] }
+    }
+
+    fn add(&mut self, block: &Block) {
+        let x0 = block.x.max(self.rect.x0);
+        let x1 = (block.x + block.width).min(self.rect.x1);
+        let y0 = block.y.max(self.rect.y0);
+        let y1 = (block.y + block.height).min(self.rect.y1);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        for y in y0..y1 {
+            let row = &block.rgba[(y - block.y) as usize * block.width as usize * 4..][..block.width as usize * 4];
+            let out = ((y - self.rect.y0) >> self.level) as usize * self.width as usize;
+            for x in x0..x1 {
+                let p = &row[(x - block.x) as usize * 4..][..4];
+                let sum = &mut self.sums[out + ((x - self.rect.x0) >> self.level) as usize];
+                let a = p[3] as u32;
+                if a == 255 {
+                    sum[0] += p[0] as u32;
+                    sum[1] += p[1] as u32;
+                    sum[2] += p[2] as u32;
+                } else {
+                    let premul = |c: u8| (c as u32 * a + 127) / 255;
+                    sum[0] += premul(p[0]);
+                    sum[1] += premul(p[1]);
+                    sum[2] += premul(p[2]);
+                }
+                sum[3] += a;
+            }
+        }
+    }
+
+    /// The result as straight (not premultiplied) RGBA8, to keep as a
+    /// source.
+    fn into_rgba(self) -> Vec<u8> {
+        let step = 1u32 << self.level;
+        let count = |o: u32, len: u32| step.min(len - (o << self.level)) as u64;
+        let (rect_w, rect_h) = (self.rect.x1 - self.rect.x0, self.rect.y1 - self.rect.y0);
+
+        let mut rgba = Vec::with_capacity(self.sums.len() * 4);
+        for (i, sum) in self.sums.iter().enumerate() {
+            let (ox, oy) = (i as u32 % self.width, i as u32 / self.width);
+            let n = count(ox, rect_w) * count(oy, rect_h);
+            let alpha = sum[3] as u64;
+            if alpha == 0 {
+                rgba.extend_from_slice(&[0; 4]);
+                continue;
+            }
+            // Undo the premultiplication: colour sums over alpha sums
+            let straight = |v: u32| ((v as u64 * 255 + alpha / 2) / alpha).min(255) as u8;
+            rgba.extend_from_slice(&[straight(sum[0]), straight(sum[1]), straight(sum[2]), ((alpha + n / 2) / n) as u8]);
+        }
+        rgba
+    }
+
+    fn finish(self) -> Tile {
+        let step = 1u32 << self.level;
+        // Source pixels behind output pixel `o` along an axis of `len`
+        let count = |o: u32, len: u32| step.min(len - (o << self.level));
+        let (rect_w, rect_h) = (self.rect.x1 - self.rect.x0, self.rect.y1 - self.rect.y0);
+
+        let mut opaque = true;
+        let mut pixels = Vec::with_capacity(self.sums.len());
+        for (i, sum) in self.sums.iter().enumerate() {
+            let (ox, oy) = (i as u32 % self.width, i as u32 / self.width);
+            let n = count(ox, rect_w) * count(oy, rect_h);
+            let avg = |v: u32| (v + n / 2) / n;
+            opaque &= sum[3] == 255 * n;
+            pixels.push(avg(sum[3]) << 24 | avg(sum[0]) << 16 | avg(sum[1]) << 8 | avg(sum[2]));
+        }
+        if opaque {
+            for p in &mut pixels {
+                *p &= 0x00FF_FFFF;
+            }
+        }
+        Tile { pixels, opaque, width: self.width, height: self.height }
+    }
+}
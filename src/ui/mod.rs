@@ -8,7 +8,7 @@ use winit::keyboard::{Key, NamedKey};
 use winit::window::{Window, WindowId};
 use softbuffer::Surface;
 
-use crate::loader::UserEvent;
+use crate::loader::{spawn_revalidation, UserEvent, ViewMode};
 use crate::ui::state::ViewerState;
 
 pub mod render;
@@ -24,6 +24,7 @@ pub struct App {
     pub context: Option<softbuffer::Context<Arc<Window>>>,
     pub surface: Option<Surface<Arc<Window>, Arc<Window>>>,
     pub next_redraw: Option<Instant>,
+    pub shift: bool,
 }
 
 impl App {
@@ -34,6 +35,7 @@ impl App {
             context: None,
             surface: None,
             next_redraw: None,
+            shift: false,
         }
     }
 }
@@ -65,13 +67,12 @@ impl ApplicationHandler<UserEvent> for App {
                     let state = lock.lock().unwrap();
                     if let Some(img) = state.get(idx) {
                         drop(state);
-                        self.state.current_decoded = Some(img);
-                        self.state.displayed_index = idx;
+                        self.state.show_full(idx, img);
                         self.state.error_message = None;
-                    } else if let Some(err) = state.errors.get(&idx) {
-                        let msg = format!("Could not load: {}", err);
+                    } else if let Some(msg) = state.error_message(idx) {
                         drop(state);
                         self.state.error_message = Some(msg);
+                        self.state.showing_preview = false;
                         self.state.current_decoded = None; // clear old image on error? or keep it? 
                         // Let's clear it so the error is visible on black background, 
                         // or we could overlay error. For now, clear to match old behavior for errors.
@@ -98,7 +99,34 @@ impl ApplicationHandler<UserEvent> for App {
                     window.request_redraw();
                 }
             }
-            UserEvent::ThumbnailReady(_) => {
+            UserEvent::TileReady(idx) => {
+                if idx == self.state.displayed_index
+                    && let Some(ref window) = self.window
+                {
+                    window.request_redraw();
+                }
+            }
+            UserEvent::MetadataReady(idx) => {
+                if idx == self.state.current_index
+                    && let Some(ref window) = self.window
+                {
+                    window.request_redraw();
+                }
+            }
+            UserEvent::ThumbnailReady(idx) => {
+                // In single view, a thumbnail for the image we're waiting on
+                // can stand in until the full decode lands
+                let waiting = self.state.view_mode == ViewMode::Single
+                    && idx == self.state.current_index
+                    && self.state.error_message.is_none()
+                    && (self.state.displayed_index != idx || self.state.current_decoded.is_none());
+                if waiting {
+                    let (lock, _) = &*self.state.shared;
+                    let thumb = lock.lock().unwrap().get_thumbnail(idx);
+                    if let Some(thumb) = thumb {
+                        self.state.show_preview(idx, thumb);
+                    }
+                }
                 // Request redraw to show the new thumbnail
                 if let Some(ref window) = self.window {
                     window.request_redraw();
@@ -118,6 +146,11 @@ impl ApplicationHandler<UserEvent> for App {
                 event_loop.exit();
             }
 
+            WindowEvent::Focused(true) => {
+                // Files may have been edited while we were in the background
+                spawn_revalidation(Arc::clone(&self.state.shared), Arc::clone(&self.state.files));
+            }
+
             WindowEvent::Resized(PhysicalSize { width, height }) => {
                 let w = width.max(1);
                 let h = height.max(1);
@@ -132,6 +165,10 @@ impl ApplicationHandler<UserEvent> for App {
                 }
             }
 
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.shift = modifiers.state().shift_key();
+            }
+
             WindowEvent::KeyboardInput { event, .. } => {
                 let pressed = event.state == ElementState::Pressed;
                 match &event.logical_key {
@@ -147,14 +184,19 @@ impl ApplicationHandler<UserEvent> for App {
                     }
                     Key::Character(s) => {
                         if let Some(c) = s.chars().next() {
-                            let c = c.to_ascii_lowercase();
+                            // Presses are upper case with Shift (so 'M' is
+                            // Shift+M whatever Caps Lock says); held keys
+                            // never are, so releasing Shift first can't
+                            // leave one stuck down
+                            let lower = c.to_ascii_lowercase();
                             if pressed {
                                 if !event.repeat {
+                                    let c = if self.shift { c.to_ascii_uppercase() } else { lower };
                                     self.state.chars_pressed.insert(c);
                                 }
-                                self.state.chars_down.insert(c);
+                                self.state.chars_down.insert(lower);
                             } else {
-                                self.state.chars_down.remove(&c);
+                                self.state.chars_down.remove(&lower);
                             }
                         }
                     }
@@ -6,6 +6,8 @@
 // If I put `ViewerState` in `state.rs`, it can have a `render` method that calls functions in `render.rs`.
 // So `render.rs` will contain the primitives.
 
+use crate::histogram::{is_clipped_highlight, is_clipped_shadow};
+
 // Constants
 pub const BG_COLOR: [u8; 4] = [31, 31, 31, 255]; // ~0.12 * 255
 
@@ -283,16 +285,98 @@ pub fn fill_rect(buf: &mut [u32], stride: u32, buf_h: u32, rx: i32, ry: i32, rw:
     }
 }
 
+/// Paint clipped pixels inside the rectangle (x0, y0)-(x1, y1): blown-out
+/// highlights red, pure black shadows blue.
+pub fn mark_clipping(buf: &mut [u32], stride: u32, buf_h: u32, x0: i32, y0: i32, x1: i32, y1: i32) {
+    let (x0, x1) = (x0.max(0) as usize, x1.clamp(0, stride as i32) as usize);
+    let (y0, y1) = (y0.max(0) as usize, y1.clamp(0, buf_h as i32) as usize);
+    if x0 >= x1 {
+        return;
+    }
+    for y in y0..y1 {
+        for px in &mut buf[y * stride as usize..][x0..x1] {
+            if is_clipped_highlight(*px) {
+                *px = rgb(255, 0, 0);
+            } else if is_clipped_shadow(*px) {
+                *px = rgb(0, 0, 255);
+            }
+        }
+    }
+}
+
 pub fn fit_scale(img_w: f32, img_h: f32, win_w: f32, win_h: f32) -> f32 {
     (win_w / img_w).min(win_h / img_h)
 }
 
+/// Top-left corner of the source rectangle (x0, y0, x1, y1) of a
+/// `src_w` x `src_h` image after rotating it like `blit_scaled_rotated`.
+pub fn rotated_origin(rect: (f32, f32, f32, f32), src_w: f32, src_h: f32, rotation: u8) -> (f32, f32) {
+    let (x0, y0, x1, y1) = rect;
+    match rotation {
+        1 => (y0, src_w - x1),
+        2 => (src_w - x1, src_h - y1),
+        3 => (src_h - y1, x0),
+        _ => (x0, y0),
+    }
+}
+
+/// The source rectangle that lands on `rect` of the rotated image; the
+/// inverse of `rotated_origin`.
+pub fn unrotate_rect(rect: (f32, f32, f32, f32), src_w: f32, src_h: f32, rotation: u8) -> (f32, f32, f32, f32) {
+    let (x0, y0, x1, y1) = rect;
+    match rotation {
+        1 => (src_w - y1, x0, src_w - y0, x1),
+        2 => (src_w - x1, src_h - y1, src_w - x0, src_h - y0),
+        3 => (y0, src_h - x1, y1, src_h - x0),
+        _ => rect,
+    }
+}
+
+/// Blend a premultiplied `0xAARRGGBB` pixel over an opaque framebuffer pixel.
+fn blend_premultiplied(dst: u32, src: u32) -> u32 {
+    let inv = 255 - (src >> 24);
+    let (sr, sg, sb) = unpack_rgb(src);
+    let (dr, dg, db) = unpack_rgb(dst);
+    let r = sr as u32 + (dr as u32 * inv) / 255;
+    let g = sg as u32 + (dg as u32 * inv) / 255;
+    let b = sb as u32 + (db as u32 * inv) / 255;
+    rgb(r.min(255) as u8, g.min(255) as u8, b.min(255) as u8)
+}
+
+/// Write one source pixel (see `DecodedImage::pixels`) into the framebuffer.
+#[inline]
+fn put_pixel(dst: &mut u32, src: u32, opaque: bool) {
+    if opaque {
+        *dst = src;
+    } else {
+        let sa = src >> 24;
+        if sa == 255 {
+            *dst = src & 0x00FF_FFFF;
+        } else if sa > 0 {
+            *dst = blend_premultiplied(*dst, src);
+        }
+    }
+}
+
+/// Where and how `blit_scaled_rotated` draws its source.
+#[derive(Clone, Copy, Debug)]
+pub struct Blit {
+    /// Top left corner of the drawn image in the framebuffer.
+    pub x0: f32,
+    pub y0: f32,
+    pub scale: f32,
+    /// Quarter turns, as in `ViewerState::rotation`.
+    pub rotation: u8,
+    /// The source has no transparent pixels, so it needn't be blended.
+    pub opaque: bool,
+}
+
 pub fn blit_scaled_rotated(
     dst: &mut [u32], dst_w: u32, dst_h: u32,
-    src: &[u8], src_w: u32, src_h: u32,
-    x0: f32, y0: f32, scale: f32,
-    rotation: u8,
+    src: &[u32], src_w: u32, src_h: u32,
+    blit: Blit,
 ) {
+    let Blit { x0, y0, scale, rotation, opaque } = blit;
     let (draw_w, draw_h) = if rotation % 2 == 1 {
         (src_h as f32 * scale, src_w as f32 * scale)
     } else {
@@ -304,8 +388,45 @@ pub fn blit_scaled_rotated(
     let dx_end = ((x0 + draw_w).ceil() as u32).min(dst_w);
     let dy_end = ((y0 + draw_h).ceil() as u32).min(dst_h);
 
+    if dx_start >= dx_end || dy_start >= dy_end {
+        return;
+    }
+
     let inv_scale = 1.0 / scale;
 
+    if rotation == 0 {
+        // 1:1 on whole pixels: opaque rows are a plain memcpy
+        if opaque && scale == 1.0 && x0.fract() == 0.0 && y0.fract() == 0.0 {
+            let sx0 = (dx_start as f32 - x0) as usize;
+            let n = (dx_end - dx_start) as usize;
+            for dy in dy_start..dy_end {
+                let sy = (dy as f32 - y0) as usize;
+                let si = sy * src_w as usize + sx0;
+                let di = dy as usize * dst_w as usize + dx_start as usize;
+                dst[di..di + n].copy_from_slice(&src[si..si + n]);
+            }
+            return;
+        }
+
+        // Scaled: source column per destination column is the same for every row
+        let cols: Vec<u32> = (dx_start..dx_end)
+            .map(|dx| (((dx as f32 - x0) * inv_scale) as u32).min(src_w - 1))
+            .collect();
+        for dy in dy_start..dy_end {
+            let sy = ((dy as f32 - y0) * inv_scale) as u32;
+            if sy >= src_h {
+                continue;
+            }
+            let src_row = &src[sy as usize * src_w as usize..][..src_w as usize];
+            let di = dy as usize * dst_w as usize + dx_start as usize;
+            let dst_row = &mut dst[di..di + cols.len()];
+            for (d, &sx) in dst_row.iter_mut().zip(&cols) {
+                put_pixel(d, src_row[sx as usize], opaque);
+            }
+        }
+        return;
+    }
+
     for dy in dy_start..dy_end {
         let vy = (dy as f32 - y0) * inv_scale;
         for dx in dx_start..dx_end {
@@ -315,7 +436,6 @@ pub fn blit_scaled_rotated(
             // Source dims are (src_w, src_h)
             // (vx, vy) are in the rotated space (0..draw_w/scale, 0..draw_h/scale)
             let (sx, sy) = match rotation {
-                0 => (vx as u32, vy as u32),
                 1 => ((src_w as f32 - 1.0 - vy) as u32, vx as u32), // 90 CCW
                 2 => ((src_w as f32 - 1.0 - vx) as u32, (src_h as f32 - 1.0 - vy) as u32), // 180
                 3 => (vy as u32, (src_h as f32 - 1.0 - vx) as u32), // 270 CCW (90 CW)
@@ -326,21 +446,9 @@ pub fn blit_scaled_rotated(
                 continue;
             }
 
-            let si = (sy as usize * src_w as usize + sx as usize) * 4;
+            let si = sy as usize * src_w as usize + sx as usize;
             let di = dy as usize * dst_w as usize + dx as usize;
-
-            // ... pixel copy ...
-            let sa = src[si + 3] as u32;
-            if sa == 255 {
-                dst[di] = rgb(src[si], src[si + 1], src[si + 2]);
-            } else if sa > 0 {
-                let inv = 255 - sa;
-                let (dr, dg, db) = unpack_rgb(dst[di]);
-                let r = ((src[si] as u32 * sa + dr as u32 * inv) / 255) as u8;
-                let g = ((src[si + 1] as u32 * sa + dg as u32 * inv) / 255) as u8;
-                let b = ((src[si + 2] as u32 * sa + db as u32 * inv) / 255) as u8;
-                dst[di] = rgb(r, g, b);
-            }
+            put_pixel(&mut dst[di], src[si], opaque);
         }
     }
 }
@@ -4,14 +4,20 @@ use std::io::Write;
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 use std::time::Instant;
+use image::metadata::Orientation;
 use winit::window::{Fullscreen, Window};
 use winit::keyboard::NamedKey;
 
 use crate::cli::HELP_KEYS;
+use crate::cluster::transform_name;
 use crate::dedupe::DuplicateInfo;
-use crate::loader::{DecodedImage, SharedState, ViewMode};
+use crate::loader::{DecodedImage, FileMetadata, SharedState, TileKey, ViewMode};
+use crate::histogram::{Histogram, BLUE, GREEN, LUMA, RED};
+use crate::metadata::{self, MetadataFormat};
+use crate::tiles::TiledImage;
 use crate::ui::render::{
-    blit_scaled_rotated, draw_text, fill_rect, fit_scale, rgb, BG_COLOR,
+    blit_scaled_rotated, draw_text, fill_rect, fit_scale, mark_clipping, rgb, rotated_origin, unrotate_rect,
+    Blit, BG_COLOR,
 };
 
 // ---------------------------------------------------------------------------
@@ -20,6 +26,8 @@ use crate::ui::render::{
 
 const ZOOM_FACTOR: f32 = 0.25;
 const GRID_COLS: usize = 20;
+/// Lines the metadata panel moves per wheel notch.
+const METADATA_SCROLL_STEP: f32 = 3.0;
 
 // ---------------------------------------------------------------------------
 // Viewer state
@@ -34,7 +42,14 @@ pub struct ViewerState {
     /// May differ from `current_index` if we are waiting for a load.
     pub displayed_index: usize,
     pub current_decoded: Option<Arc<DecodedImage>>,
+    /// `current_decoded` is a low-res thumbnail/embedded preview standing in
+    /// until the full image for `displayed_index` arrives.
+    pub showing_preview: bool,
     pub error_message: Option<String>,
+    /// Page of the current file the user asked for, and how many it has
+    /// (1 until a decode says otherwise).
+    pub page: usize,
+    pub page_count: usize,
 
     pub view_mode: ViewMode,
 
@@ -55,6 +70,11 @@ pub struct ViewerState {
     pub nav_past_initial: bool,
     pub last_frame: Instant,
 
+    // Browsing motion, passed to the loader to steer prefetch
+    pub nav_direction: i32,
+    pub nav_speed: f32, // images per second, smoothed
+    pub last_nav: Instant,
+
     // Track which keys are currently held
     pub keys_down: HashSet<NamedKey>,
     pub chars_down: HashSet<char>,
@@ -71,6 +91,13 @@ pub struct ViewerState {
     pub show_help: bool,
     pub rotation: u8, // 0=0, 1=90, 2=180, 3=270 (CW)
     pub font_size: u32,
+    pub show_metadata: bool,
+    /// First line of the metadata panel in view.
+    pub metadata_scroll: usize,
+    pub metadata_format: MetadataFormat,
+    pub show_histogram: bool,
+    /// Mark clipped highlights and shadows on the image.
+    pub show_clipping: bool,
 }
 
 impl ViewerState {
@@ -90,7 +117,10 @@ impl ViewerState {
             current_index: 0,
             displayed_index: 0,
             current_decoded: None,
+            showing_preview: false,
             error_message: None,
+            page: 0,
+            page_count: 1,
             view_mode: ViewMode::Single,
             zoom: 0.0,
             offset_x: 0.0,
@@ -106,6 +136,9 @@ impl ViewerState {
             nav_hold_timer: 0.0,
             nav_past_initial: false,
             last_frame: Instant::now(),
+            nav_direction: 1,
+            nav_speed: 0.0,
+            last_nav: Instant::now(),
             keys_down: HashSet::new(),
             chars_down: HashSet::new(),
             keys_pressed: HashSet::new(),
@@ -115,6 +148,11 @@ impl ViewerState {
             show_help: false,
             rotation: 0,
             font_size,
+            show_metadata: false,
+            metadata_scroll: 0,
+            metadata_format: MetadataFormat::default(),
+            show_histogram: false,
+            show_clipping: false,
         }
     }
 
@@ -134,6 +172,37 @@ impl ViewerState {
         self.chars_down.contains(&c)
     }
 
+    /// Show the full image for `idx`. If it replaces another raster of the
+    /// same file (a preview, or a vector image re-rendered at a new scale),
+    /// the zoom is rescaled so the image keeps its on-screen size.
+    pub fn show_full(&mut self, idx: usize, img: Arc<DecodedImage>) {
+        if self.displayed_index == idx && self.zoom != 0.0
+            && let Some(ref preview) = self.current_decoded
+        {
+            self.zoom *= preview.full_size().0 as f32 / img.full_size().0.max(1) as f32;
+        }
+        self.page = img.page;
+        self.page_count = img.page_count;
+        self.current_decoded = Some(img);
+        self.displayed_index = idx;
+        self.showing_preview = false;
+    }
+
+    /// A different page of the displayed file has been requested and is
+    /// still decoding.
+    pub fn page_pending(&self) -> bool {
+        self.displayed_index == self.current_index
+            && !self.showing_preview
+            && self.current_decoded.as_ref().is_some_and(|dec| dec.page != self.page)
+    }
+
+    /// Show a low-res stand-in for `idx` while its full decode is pending.
+    pub fn show_preview(&mut self, idx: usize, img: Arc<DecodedImage>) {
+        self.current_decoded = Some(img);
+        self.displayed_index = idx;
+        self.showing_preview = true;
+    }
+
     /// Run the per-frame logic: input handling, cache polling, etc.
     /// Returns true if the app should quit.
     pub fn update(&mut self, window: &Window) -> bool {
@@ -161,18 +230,21 @@ impl ViewerState {
             };
             
             // Notify loader of mode change
-            let (lock, cvar) = &*self.shared;
+            let shared = Arc::clone(&self.shared);
+            let (lock, cvar) = &*shared;
             let mut state = lock.lock().unwrap();
             state.set_mode(self.view_mode);
             
             // If switching to Single mode, update current_decoded immediately
             if self.view_mode == ViewMode::Single {
                 if let Some(img) = state.get(self.current_index) {
-                    self.current_decoded = Some(img);
-                    self.displayed_index = self.current_index;
+                    self.show_full(self.current_index, img);
+                } else if let Some(thumb) = state.get_thumbnail(self.current_index) {
+                    self.show_preview(self.current_index, thumb);
                 } else {
                     // Clear old image so we don't show a stale one while loading
                     self.current_decoded = None;
+                    self.showing_preview = false;
                 }
             }
 
@@ -311,6 +383,7 @@ impl ViewerState {
             // In Single mode, we might wait for loading (existing logic).
             let is_loading = self.view_mode == ViewMode::Single && (
                 self.displayed_index != self.current_index
+                || self.showing_preview
                 || (self.current_decoded.is_none() && self.error_message.is_none())
             );
 
@@ -331,6 +404,7 @@ impl ViewerState {
                 };
 
                 if new_idx != self.current_index {
+                    self.track_motion(new_idx, explicit_target.is_some(), files_len, now);
                     self.current_index = new_idx;
                     self.error_message = None;
                     
@@ -341,18 +415,31 @@ impl ViewerState {
                     }
 
                     // Update shared state and wake workers
-                    let (lock, cvar) = &*self.shared;
+                    let shared = Arc::clone(&self.shared);
+                    let (lock, cvar) = &*shared;
+                    let path = self.files.read().unwrap().get(new_idx).cloned();
                     let mut state = lock.lock().unwrap();
-                    state.set_current_idx(new_idx);
+                    state.set_current_idx(new_idx, path.clone(), self.nav_direction, self.nav_speed);
+                    self.page = state.options(new_idx).page;
+                    self.page_count = 1;
+
+                    // Replace the cached copy if the file was edited since
+                    if let Some(path) = path
+                        && state.queue_revalidation(new_idx, path)
+                    {
+                        crate::loader::spawn_queued_revalidation(Arc::clone(&shared));
+                    }
                     
                     if self.view_mode == ViewMode::Single {
                         if let Some(img) = state.get(new_idx) {
-                            self.current_decoded = Some(img);
-                            self.displayed_index = new_idx;
-                        } else if let Some(err) = state.errors.get(&new_idx) {
-                            self.error_message = Some(format!("Could not load: {}", err));
+                            self.show_full(new_idx, img);
+                        } else if let Some(msg) = state.error_message(new_idx) {
+                            self.error_message = Some(msg);
                             self.current_decoded = None;
+                            self.showing_preview = false;
                             self.displayed_index = new_idx;
+                        } else if let Some(thumb) = state.get_thumbnail(new_idx) {
+                            self.show_preview(new_idx, thumb);
                         }
                     }
                     // For Grid mode, we don't update `current_decoded` because we render from cache directly
@@ -361,11 +448,40 @@ impl ViewerState {
                 }
             } else if is_loading && explicit_target.is_none() {
                  // Check if current became available?
-                let (lock, cvar) = &*self.shared;
+                let shared = Arc::clone(&self.shared);
+                let (lock, cvar) = &*shared;
+                let path = self.files.read().unwrap().get(self.current_index).cloned();
                 let mut state = lock.lock().unwrap();
-                state.set_current_idx(self.current_index);
+                state.set_current_idx(self.current_index, path, self.nav_direction, self.nav_speed);
                 if let Some(img) = state.get(self.current_index) {
-                    self.current_decoded = Some(img);
+                    self.show_full(self.current_index, img);
+                }
+                cvar.notify_all();
+            }
+        }
+
+        // ------------------------------------------------------------------
+        // Page within a multi-page file ([ / ])
+        // ------------------------------------------------------------------
+        let page_step = self.is_char_pressed(']') as i64 - self.is_char_pressed('[') as i64;
+        if self.view_mode == ViewMode::Single && page_step != 0 && self.page_count > 1 {
+            let page = (self.page as i64 + page_step).clamp(0, self.page_count as i64 - 1) as usize;
+            if page != self.page {
+                self.page = page;
+                self.zoom = 0.0;
+                self.offset_x = 0.0;
+                self.offset_y = 0.0;
+
+                // Keep showing the old page until the new one is decoded
+                let shared = Arc::clone(&self.shared);
+                let (lock, cvar) = &*shared;
+                let mut state = lock.lock().unwrap();
+                state.request_page(self.current_index, page);
+                if let Some(img) = state.get(self.current_index).filter(|img| img.page == page) {
+                    self.show_full(self.current_index, img);
+                } else if self.error_message.is_some() {
+                    self.error_message = None;
+                    self.current_decoded = None;
                     self.displayed_index = self.current_index;
                 }
                 cvar.notify_all();
@@ -379,44 +495,89 @@ impl ViewerState {
             self.show_info = !self.show_info;
         }
 
+        // ------------------------------------------------------------------
+        // Histogram (g) and clipping warning (c)
+        // ------------------------------------------------------------------
+        if self.is_char_pressed('g') {
+            self.show_histogram = !self.show_histogram;
+        }
+        if self.is_char_pressed('c') {
+            self.show_clipping = !self.show_clipping;
+        }
+
         // ------------------------------------------------------------------
         // Dump Metadata (M)
         // ------------------------------------------------------------------
         if self.is_char_pressed('M') {
-            let files_guard = self.files.read().unwrap();
-            if self.current_index < files_guard.len() {
-                let path = &files_guard[self.current_index];
-                
-                // We need to get the metadata. In Single mode, it might be in current_decoded.
-                // In Grid mode, or if not loaded, we might need to check cache or load it?
-                // For simplicity, we check current_decoded (Single) or try to get from cache.
-                
-                let metadata = if let Some(ref dec) = self.current_decoded {
-                     dec.metadata.clone()
-                } else {
-                     // Try to get from shared cache (thumbnail or full)
-                     let (lock, _) = &*self.shared;
-                     let state = lock.lock().unwrap();
-                     state.get(self.current_index)
-                        .or_else(|| state.get_thumbnail(self.current_index))
-                        .and_then(|dec| dec.metadata.clone())
-                };
+            let path = self.files.read().unwrap().get(self.current_index).cloned();
+            if let Some(path) = path {
+                match self.metadata_of(self.current_index).as_ref().and_then(|m| m.metadata.as_ref()) {
+                    Some(meta) => metadata::dump(&path, meta, self.metadata_format),
+                    None => println!("[METADATA] {} - No metadata available (or image not loaded)", path.display()),
+                }
+            }
+        }
 
-                if let Some(meta) = metadata {
-                    println!("[METADATA] {}", path.display());
-                    if let Some(v) = &meta.make { println!("  Make: {}", v); }
-                    if let Some(v) = &meta.model { println!("  Model: {}", v); }
-                    if let Some(v) = &meta.datetime { println!("  Time: {}", v); }
-                    if let Some(v) = &meta.exposure_time { println!("  Exposure: {}", v); }
-                    if let Some(v) = &meta.f_number { println!("  F-Number: {}", v); }
-                    if let Some(v) = &meta.iso { println!("  ISO: {}", v); }
-                    if let Some(v) = &meta.focal_length { println!("  Focal Length: {}", v); }
-                    if let Some(v) = &meta.gps { println!("  GPS: {}", v); }
-                    println!("----------------------------------------");
-                } else {
-                    println!("[METADATA] {} - No metadata available (or image not loaded)", path.display());
+        // ------------------------------------------------------------------
+        // Metadata panel (I), scrolled with Up/Down (single view) or the wheel
+        // ------------------------------------------------------------------
+        if self.is_char_pressed('I') {
+            self.show_metadata = !self.show_metadata;
+            self.metadata_scroll = 0;
+        }
+        if self.show_metadata {
+            let mut scroll = self.metadata_scroll as f32 - self.wheel_y * METADATA_SCROLL_STEP;
+            if self.view_mode == ViewMode::Single {
+                if self.is_key_pressed_named(NamedKey::ArrowDown) || self.is_char_pressed('j') {
+                    scroll += 1.0;
+                }
+                if self.is_key_pressed_named(NamedKey::ArrowUp) || self.is_char_pressed('k') {
+                    scroll -= 1.0;
                 }
             }
+            // The wheel scrolls the panel instead of zooming
+            self.wheel_y = 0.0;
+
+            let fb_h = window.inner_size().height;
+            let max_scroll = self.metadata_lines().len().saturating_sub(self.metadata_rows(fb_h));
+            self.metadata_scroll = (scroll.max(0.0) as usize).min(max_scroll);
+        }
+
+        // ------------------------------------------------------------------
+        // Retry failed load (x)
+        // ------------------------------------------------------------------
+        if self.is_char_pressed('x') {
+            let (lock, cvar) = &*self.shared;
+            let mut state = lock.lock().unwrap();
+            state.retry(self.current_index);
+            cvar.notify_all();
+            if self.error_message.is_some() {
+                // Show "Loading..." until the retry finishes
+                self.error_message = None;
+                self.current_decoded = None;
+                self.showing_preview = false;
+                self.displayed_index = self.current_index;
+            }
+        }
+
+        // ------------------------------------------------------------------
+        // Reload current file from disk (F5)
+        // ------------------------------------------------------------------
+        if self.is_key_pressed_named(NamedKey::F5) {
+            let (lock, cvar) = &*self.shared;
+            let path = self.files.read().unwrap().get(self.current_index).cloned();
+            let mut state = lock.lock().unwrap();
+            if let Some(path) = path {
+                state.invalidate(self.current_index, &path);
+            }
+            cvar.notify_all();
+            drop(state);
+            if self.view_mode == ViewMode::Single {
+                self.error_message = None;
+                self.current_decoded = None;
+                self.showing_preview = false;
+                self.displayed_index = self.current_index;
+            }
         }
 
         // ------------------------------------------------------------------
@@ -511,8 +672,9 @@ impl ViewerState {
                 let size = window.inner_size();
                 let sw = size.width as f32;
                 let sh = size.height as f32;
+                let (full_w, full_h) = dec.full_size();
                 let old_zoom = if self.zoom == 0.0 {
-                    fit_scale(dec.width as f32, dec.height as f32, sw, sh)
+                    fit_scale(full_w as f32, full_h as f32, sw, sh)
                 } else {
                     self.zoom
                 };
@@ -524,8 +686,8 @@ impl ViewerState {
                 let anchor_y = if my >= 0.0 && my <= sh { my } else { sh / 2.0 };
 
                 // Image point under anchor before zoom
-                let img_w = dec.width as f32;
-                let img_h = dec.height as f32;
+                let img_w = full_w as f32;
+                let img_h = full_h as f32;
                 let old_dw = img_w * old_zoom;
                 let old_dh = img_h * old_zoom;
                 let old_x0 = (sw - old_dw) / 2.0 + self.offset_x;
@@ -545,6 +707,64 @@ impl ViewerState {
             }
         }
 
+        // ------------------------------------------------------------------
+        // Vector images: re-rasterise when the display scale drifts far
+        // from 1:1 instead of scaling a bitmap
+        // ------------------------------------------------------------------
+        if self.view_mode == ViewMode::Single
+            && !self.showing_preview
+            && self.displayed_index == self.current_index
+            && let Some(ref dec) = self.current_decoded
+            && let Some(raster_scale) = dec.raster_scale
+        {
+            let size = window.inner_size();
+            let (img_w, img_h) = if self.rotation % 2 == 1 {
+                (dec.height as f32, dec.width as f32)
+            } else {
+                (dec.width as f32, dec.height as f32)
+            };
+            let display_scale = if self.zoom == 0.0 {
+                fit_scale(img_w, img_h, size.width as f32, size.height as f32)
+            } else {
+                self.zoom
+            };
+            if !(0.5..=1.25).contains(&display_scale) {
+                let (lock, cvar) = &*self.shared;
+                let mut state = lock.lock().unwrap();
+                if state.request_raster_scale(self.current_index, raster_scale * display_scale) {
+                    cvar.notify_all();
+                }
+            }
+        }
+
+        // ------------------------------------------------------------------
+        // Tiled images: ask for the tiles covering the window at the
+        // pyramid level that matches the zoom
+        // ------------------------------------------------------------------
+        let mut wanted = Vec::new();
+        if self.view_mode == ViewMode::Single
+            && let Some(ref dec) = self.current_decoded
+            && let Some(ref tiled) = dec.tiled
+        {
+            let size = window.inner_size();
+            let (win_w, win_h) = (size.width as f32, size.height as f32);
+            let layout = self.single_layout(dec, win_w, win_h);
+            let level = tiled.level_for_scale(layout.0);
+            if level < tiled.overview_level {
+                wanted = self.visible_tiles(tiled, level, layout, win_w, win_h)
+                    .into_iter()
+                    .map(|(x, y)| TileKey { idx: self.displayed_index, level, x, y })
+                    .collect();
+            }
+        }
+        {
+            let (lock, cvar) = &*self.shared;
+            let mut state = lock.lock().unwrap();
+            if state.set_wanted_tiles(wanted) {
+                cvar.notify_all();
+            }
+        }
+
         // Clear per-frame input state
         self.keys_pressed.clear();
         self.chars_pressed.clear();
@@ -553,6 +773,30 @@ impl ViewerState {
         false
     }
 
+    /// Update direction and smoothed speed for a move to `new_idx`.
+    /// Jumps (Home/End) reset the speed and point the way there is left to go.
+    fn track_motion(&mut self, new_idx: usize, jump: bool, files_len: usize, now: Instant) {
+        let step = new_idx as i64 - self.current_index as i64;
+        let elapsed = now.duration_since(self.last_nav).as_secs_f32();
+        self.last_nav = now;
+
+        if jump {
+            self.nav_direction = if new_idx + 1 >= files_len && new_idx > 0 { -1 } else { 1 };
+            self.nav_speed = 0.0;
+            return;
+        }
+
+        let direction = step.signum() as i32;
+        // A pause or a change of direction starts over
+        let instant = if elapsed > 1.0 { 0.0 } else { step.unsigned_abs() as f32 / elapsed.max(0.001) };
+        self.nav_speed = if direction == self.nav_direction {
+            0.5 * self.nav_speed + 0.5 * instant
+        } else {
+            instant
+        };
+        self.nav_direction = direction;
+    }
+
     fn mark_current_file(&self) {
         let current_path = {
             let files_guard = self.files.read().unwrap();
@@ -619,6 +863,77 @@ impl ViewerState {
             ViewMode::Single => self.render_single(frame, fb_w, fb_h),
             ViewMode::Grid => self.render_grid(frame, fb_w, fb_h),
         }
+
+        if self.show_metadata && !self.show_help {
+            self.render_metadata_panel(frame, fb_w, fb_h);
+        }
+    }
+
+    /// Cached metadata of file `idx`, once a worker has read it.
+    fn metadata_of(&self, idx: usize) -> Option<Arc<FileMetadata>> {
+        let path = self.files.read().unwrap().get(idx).cloned()?;
+        let (lock, _) = &*self.shared;
+        lock.lock().unwrap().get_metadata(&path)
+    }
+
+    /// Lines of the metadata panel; `true` marks a group heading.
+    fn metadata_lines(&self) -> Vec<(String, bool)> {
+        let Some(file) = self.metadata_of(self.current_index) else {
+            return vec![("Loading...".to_string(), false)];
+        };
+        let Some(ref meta) = file.metadata else {
+            return vec![("No metadata".to_string(), false)];
+        };
+        let mut lines = Vec::new();
+        let mut group = "";
+        for field in &meta.fields {
+            if field.group != group {
+                group = field.group;
+                lines.push((group.to_string(), true));
+            }
+            lines.push((format!("{}: {}", field.name, field.value), false));
+        }
+        lines
+    }
+
+    /// Panel lines that fit in a window `fb_h` pixels high, below the title.
+    fn metadata_rows(&self, fb_h: u32) -> usize {
+        let line_h = 7 * self.font_size + 4;
+        (fb_h.saturating_sub(16) / line_h).saturating_sub(1).max(1) as usize
+    }
+
+    /// Every metadata field of the current image, in a scrollable panel
+    /// down the right-hand side.
+    fn render_metadata_panel(&self, frame: &mut [u32], fb_w: u32, fb_h: u32) {
+        let text_scale = self.font_size;
+        let char_w = 6 * text_scale;
+        let line_h = (7 * text_scale + 4) as i32;
+        let panel_w = (fb_w * 2 / 5).max((50 * char_w + 20).min(fb_w));
+        let x = fb_w as i32 - panel_w as i32;
+        fill_rect(frame, fb_w, fb_h, x, 0, panel_w, fb_h, (0, 0, 0, 200));
+
+        let lines = self.metadata_lines();
+        let rows = self.metadata_rows(fb_h);
+        let max_chars = ((panel_w - 20) / char_w) as usize;
+        let title = format!(
+            "Metadata ({}-{} of {})",
+            (self.metadata_scroll + 1).min(lines.len()),
+            (self.metadata_scroll + rows).min(lines.len()),
+            lines.len()
+        );
+        draw_text(frame, fb_w, fb_h, &title, x + 10, 8, text_scale, (255, 255, 255, 255));
+
+        let mut y = 8 + line_h;
+        for (line, heading) in lines.iter().skip(self.metadata_scroll).take(rows) {
+            let text: String = line.chars().take(max_chars).collect();
+            let (indent, color) = if *heading {
+                (0, (120, 180, 255, 255))
+            } else {
+                (char_w as i32, (220, 220, 220, 255))
+            };
+            draw_text(frame, fb_w, fb_h, &text, x + 10 + indent, y, text_scale, color);
+            y += line_h;
+        }
     }
 
     fn render_grid(&self, frame: &mut [u32], fb_w: u32, fb_h: u32) {
@@ -677,9 +992,8 @@ impl ViewerState {
                 
                 blit_scaled_rotated(
                     frame, fb_w, fb_h, 
-                    &dec.rgba_bytes, dec.width, dec.height,
-                    dx, dy, scale, 
-                    0 // No rotation in grid for now
+                    &dec.pixels, dec.width, dec.height,
+                    Blit { x0: dx, y0: dy, scale, rotation: 0, opaque: dec.opaque }, // No rotation in grid for now
                 );
             } else {
                 // Placeholder for loading/missing
@@ -743,30 +1057,31 @@ impl ViewerState {
             let mut lines = vec![line1, line2, line3];
             let mut dupe_color = None;
 
-            if let Some(ref dupe_map) = self.duplicate_info {
-                if let Some(path) = path_opt {
-                    if let Ok(map) = dupe_map.read() {
-                        if let Some(info) = map.get(&path) {
-                            if info.is_original {
-                                let count = map.values().filter(|v| v.original_path == info.original_path && !v.is_original).count();
-                                lines.push(format!("-- ORIGINAL IMAGE -- ({} copies found)", count));
-                                dupe_color = Some((100, 255, 100, 255)); // Greenish
-                            } else {
-                                lines.push(format!("DUPLICATE of: {}", info.original_path.file_name().unwrap_or_default().to_string_lossy()));
-                                lines.push(format!("Distance: {}", info.distance));
-                                dupe_color = Some((255, 100, 100, 255)); // Reddish
-                            }
-                        }
-                    }
+            if let Some(ref dupe_map) = self.duplicate_info
+                && let Some(path) = &path_opt
+                && let Ok(map) = dupe_map.read()
+                && let Some(info) = map.get(path)
+            {
+                if info.is_original {
+                    lines.push(original_line(&map, info));
+                    dupe_color = Some((100, 255, 100, 255)); // Greenish
+                } else if info.exact {
+                    lines.push(format!("EXACT COPY of: {}", info.original_path.file_name().unwrap_or_default().to_string_lossy()));
+                    lines.push("Distance: 0 (byte-identical)".to_string());
+                    dupe_color = Some((255, 180, 60, 255)); // Orange
+                } else {
+                    lines.push(format!("DUPLICATE of: {}", info.original_path.file_name().unwrap_or_default().to_string_lossy()));
+                    lines.push(distance_line(info));
+                    dupe_color = Some((255, 100, 100, 255)); // Reddish
                 }
             }
 
-            if let Some(dec) = state.get_thumbnail(self.current_index) {
-                if let Some(ref meta) = dec.metadata {
-                    if let Some(v) = &meta.datetime { lines.push(format!("Time: {}", v)); }
-                    if let Some(v) = &meta.model { lines.push(format!("Camera: {}", v)); }
-                    if let Some(v) = &meta.gps { lines.push(format!("GPS: {}", v)); }
-                }
+            if let Some(file) = path_opt.as_deref().and_then(|p| state.get_metadata(p))
+                && let Some(ref meta) = file.metadata
+            {
+                if let Some(v) = &meta.datetime { lines.push(format!("Time: {}", v)); }
+                if let Some(v) = &meta.model { lines.push(format!("Camera: {}", v)); }
+                if let Some(v) = &meta.gps { lines.push(format!("GPS: {}", v)); }
             }
 
             let text_scale = self.font_size;
@@ -786,44 +1101,151 @@ impl ViewerState {
         }
     }
 
-    fn render_single(&self, frame: &mut [u32], fb_w: u32, fb_h: u32) {
-        let sw = fb_w as f32;
-        let sh = fb_h as f32;
+    /// Histogram of the image on screen in the bottom-right corner: red,
+    /// green and blue bars with luma as a line on top, and how much is
+    /// clipped at either end.
+    fn render_histogram(&self, frame: &mut [u32], fb_w: u32, fb_h: u32, hist: Option<&Histogram>) {
+        const GRAPH_W: u32 = 256;
+        const GRAPH_H: u32 = 100;
+        let text_scale = self.font_size;
+        let line_h = 7 * text_scale + 4;
+        let box_w = GRAPH_W.max(18 * 6 * text_scale) + 20;
+        let box_h = GRAPH_H + 20 + 2 * line_h;
+        let bx = fb_w as i32 - box_w as i32 - 10;
+        let by = fb_h as i32 - box_h as i32 - 10;
+        fill_rect(frame, fb_w, fb_h, bx, by, box_w, box_h, (0, 0, 0, 178));
+
+        let white = (255, 255, 255, 255);
+        let Some(hist) = hist else {
+            draw_text(frame, fb_w, fb_h, "Loading...", bx + 10, by + 10, text_scale, white);
+            return;
+        };
 
-        if let Some(ref dec) = self.current_decoded {
-            // Adjust dimensions for rotation
-            let (img_w, img_h) = if self.rotation % 2 == 1 {
-                (dec.height as f32, dec.width as f32)
-            } else {
-                (dec.width as f32, dec.height as f32)
-            };
+        let (gx, base) = (bx + 10, by + 10 + GRAPH_H as i32);
+        let peak = hist.peak() as f32;
+        let bar = |count: u32| ((count as f32 / peak).min(1.0) * GRAPH_H as f32).round() as u32;
+        for v in 0..256 {
+            let x = gx + v as i32;
+            for (channel, color) in [(RED, (255, 60, 60, 110)), (GREEN, (60, 255, 60, 110)), (BLUE, (60, 60, 255, 110))] {
+                let h = bar(hist.bins[channel][v]);
+                fill_rect(frame, fb_w, fb_h, x, base - h as i32, 1, h, color);
+            }
+            let h = bar(hist.bins[LUMA][v]);
+            fill_rect(frame, fb_w, fb_h, x, base - h as i32, 1, 2.min(h), (230, 230, 230, 255));
+        }
 
-            let scale = if self.zoom == 0.0 {
-                fit_scale(img_w, img_h, sw, sh)
-            } else {
-                self.zoom
-            };
+        let shadows = format!("Shadows {:.2}%", hist.percent(hist.clipped_shadows));
+        let highlights = format!("Highlights {:.2}%", hist.percent(hist.clipped_highlights));
+        let ty = base + 6;
+        draw_text(frame, fb_w, fb_h, &shadows, gx, ty, text_scale, (140, 140, 255, 255));
+        draw_text(frame, fb_w, fb_h, &highlights, gx, ty + line_h as i32, text_scale, (255, 120, 120, 255));
+    }
 
-            let draw_w = img_w * scale;
-            let draw_h = img_h * scale;
-            let x0 = (sw - draw_w) / 2.0 + self.offset_x;
-            let y0 = (sh - draw_h) / 2.0 + self.offset_y;
+    /// Scale and top-left corner of `dec` in single view, in a
+    /// `win_w` x `win_h` window.
+    fn single_layout(&self, dec: &DecodedImage, win_w: f32, win_h: f32) -> (f32, f32, f32) {
+        let (full_w, full_h) = dec.full_size();
+        // Adjust dimensions for rotation
+        let (img_w, img_h) = if self.rotation % 2 == 1 {
+            (full_h as f32, full_w as f32)
+        } else {
+            (full_w as f32, full_h as f32)
+        };
+
+        let scale = if self.zoom == 0.0 {
+            fit_scale(img_w, img_h, win_w, win_h)
+        } else {
+            self.zoom
+        };
+
+        let x0 = (win_w - img_w * scale) / 2.0 + self.offset_x;
+        let y0 = (win_h - img_h * scale) / 2.0 + self.offset_y;
+        (scale, x0, y0)
+    }
+
+    /// Tiles of `level` that cover the window, most central first.
+    fn visible_tiles(
+        &self,
+        tiled: &TiledImage,
+        level: u32,
+        (scale, x0, y0): (f32, f32, f32),
+        win_w: f32,
+        win_h: f32,
+    ) -> Vec<(u32, u32)> {
+        let window = (-x0 / scale, -y0 / scale, (win_w - x0) / scale, (win_h - y0) / scale);
+        let (x0, y0, x1, y1) = unrotate_rect(window, tiled.width as f32, tiled.height as f32, self.rotation);
+        tiled.tiles_in(level, x0, y0, x1, y1)
+    }
 
+    /// Draw a tiled image: its overview, then the cached tiles on screen,
+    /// coarser levels first so the finest available ends up on top.
+    fn render_tiled(&self, frame: &mut [u32], fb_w: u32, fb_h: u32, dec: &DecodedImage, tiled: &TiledImage) {
+        let layout = self.single_layout(dec, fb_w as f32, fb_h as f32);
+        let (scale, x0, y0) = layout;
+        let (full_w, full_h) = (tiled.width as f32, tiled.height as f32);
+        let mut draw = |rect, pixels: &[u32], width, height, opaque, level: u32| {
+            let (rx, ry) = rotated_origin(rect, full_w, full_h, self.rotation);
             blit_scaled_rotated(
                 frame, fb_w, fb_h,
-                &dec.rgba_bytes, dec.width, dec.height,
-                x0, y0, scale,
-                self.rotation,
+                pixels, width, height,
+                Blit {
+                    x0: x0 + rx * scale,
+                    y0: y0 + ry * scale,
+                    scale: scale * (1u32 << level) as f32,
+                    rotation: self.rotation,
+                    opaque,
+                },
             );
+        };
+
+        let overview_scale = (1u32 << tiled.overview_level) as f32;
+        let overview_rect = (0.0, 0.0, dec.width as f32 * overview_scale, dec.height as f32 * overview_scale);
+        draw(overview_rect, &dec.pixels, dec.width, dec.height, dec.opaque, tiled.overview_level);
+
+        let mut tiles = Vec::new();
+        {
+            let (lock, _) = &*self.shared;
+            let state = lock.lock().unwrap();
+            for level in (tiled.level_for_scale(scale)..tiled.overview_level).rev() {
+                for (x, y) in self.visible_tiles(tiled, level, layout, fb_w as f32, fb_h as f32) {
+                    if let Some(tile) = state.get_tile(&TileKey { idx: self.displayed_index, level, x, y }) {
+                        tiles.push((level, x, y, tile));
+                    }
+                }
+            }
+        }
+        for (level, x, y, tile) in tiles {
+            let rect = tiled.tile_rect(level, x, y, tile.width, tile.height);
+            draw(rect, &tile.pixels, tile.width, tile.height, tile.opaque, level);
+        }
+    }
+
+    fn render_single(&self, frame: &mut [u32], fb_w: u32, fb_h: u32) {
+        if let Some(ref dec) = self.current_decoded {
+            let (scale, x0, y0) = self.single_layout(dec, fb_w as f32, fb_h as f32);
+
+            match dec.tiled {
+                Some(ref tiled) => self.render_tiled(frame, fb_w, fb_h, dec, tiled),
+                None => blit_scaled_rotated(
+                    frame, fb_w, fb_h,
+                    &dec.pixels, dec.width, dec.height,
+                    Blit { x0, y0, scale, rotation: self.rotation, opaque: dec.opaque },
+                ),
+            }
+
+            if self.show_clipping {
+                let (full_w, full_h) = dec.full_size();
+                let (w, h) = if self.rotation % 2 == 1 { (full_h, full_w) } else { (full_w, full_h) };
+                let x1 = (x0 + w as f32 * scale).ceil() as i32;
+                let y1 = (y0 + h as f32 * scale).ceil() as i32;
+                mark_clipping(frame, fb_w, fb_h, x0 as i32, y0 as i32, x1, y1);
+            }
 
             // Info overlay
             if self.show_info {
-                let display_zoom = if self.zoom == 0.0 {
-                    fit_scale(img_w, img_h, sw, sh) * 100.0
-                } else {
-                    self.zoom * 100.0
-                };
-                let raw_size = (dec.width as u64) * (dec.height as u64) * 4;
+                let display_zoom = scale * 100.0;
+                let (full_w, full_h) = dec.full_size();
+                let raw_size = (full_w as u64) * (full_h as u64) * 4;
                 let ratio = if raw_size > 0 {
                     dec.file_size as f64 / raw_size as f64
                 } else {
@@ -838,19 +1260,23 @@ impl ViewerState {
                     "Loading...".to_string()
                 };
                 
-                let line1 = format!(
+                let mut line1 = format!(
                     "[{}/{}]",
                     self.current_index + 1,
                     files_len,
                 );
+                if self.page_count > 1 {
+                    line1.push_str(&format!(" page {}/{}", self.page + 1, self.page_count));
+                }
                 let line2 = format!(
                     "{}",
                     filename,
                 );
                 let line3 = format!(
-                    "{}x{} | {} | {:.1} KB | ratio {:.2} | zoom {:.0}%",
-                    dec.width,
-                    dec.height,
+                    "{}x{}{} | {} | {:.1} KB | ratio {:.2} | zoom {:.0}%",
+                    full_w,
+                    full_h,
+                    if self.showing_preview { " (preview)" } else { "" },
                     dec.format_name,
                     dec.file_size as f64 / 1024.0,
                     ratio,
@@ -875,37 +1301,44 @@ impl ViewerState {
                      let files_guard = self.files.read().unwrap();
                      if self.current_index < files_guard.len() {
                          let path = &files_guard[self.current_index];
-                         if let Ok(map) = dupe_map.read() {
-                             if let Some(info) = map.get(path) {
-                                 if info.is_original {
-                                     let count = map.values().filter(|v| v.original_path == info.original_path && !v.is_original).count();
-                                    lines.push(format!("-- ORIGINAL IMAGE -- ({} copies found)", count));
-                                    dupe_color = Some((100, 255, 100, 255)); // Greenish
-                                } else {
-                                    lines.push(format!("DUPLICATE of: {}", info.original_path.file_name().unwrap_or_default().to_string_lossy()));
-                                    lines.push(format!("Distance: {}", info.distance));
-                                    dupe_color = Some((255, 100, 100, 255)); // Reddish
-                                }
+                         if let Ok(map) = dupe_map.read()
+                             && let Some(info) = map.get(path)
+                         {
+                             if info.is_original {
+                                lines.push(original_line(&map, info));
+                                dupe_color = Some((100, 255, 100, 255)); // Greenish
+                            } else if info.exact {
+                                lines.push(format!("EXACT COPY of: {}", info.original_path.file_name().unwrap_or_default().to_string_lossy()));
+                                lines.push("Distance: 0 (byte-identical)".to_string());
+                                dupe_color = Some((255, 180, 60, 255)); // Orange
+                            } else {
+                                lines.push(format!("DUPLICATE of: {}", info.original_path.file_name().unwrap_or_default().to_string_lossy()));
+                                lines.push(distance_line(info));
+                                dupe_color = Some((255, 100, 100, 255)); // Reddish
                             }
                         }
                     }
                 }
 
-                if let Some(ref dec) = self.current_decoded {
-                    if let Some(ref meta) = dec.metadata {
-                        if let Some(v) = &meta.datetime { lines.push(format!("Time: {}", v)); }
-                        if let Some(v) = &meta.model { lines.push(format!("Camera: {}", v)); }
-                        
-                        // Combine settings
-                        let mut settings = String::new();
-                        if let Some(iso) = &meta.iso { settings.push_str(&format!("ISO {} ", iso)); }
-                        if let Some(exp) = &meta.exposure_time { settings.push_str(&format!("{} ", exp)); }
-                        if let Some(f) = &meta.f_number { settings.push_str(&format!("{} ", f)); }
-                        if !settings.is_empty() { lines.push(format!("Settings: {}", settings.trim())); }
-
-                        if let Some(v) = &meta.focal_length { lines.push(format!("Focal: {}", v)); }
-                        if let Some(v) = &meta.gps { lines.push(format!("GPS: {}", v)); }
-                    }
+                let displayed_metadata = files_guard.get(self.displayed_index).and_then(|path| {
+                    let (lock, _) = &*self.shared;
+                    lock.lock().unwrap().get_metadata(path)
+                });
+                if let Some(file) = displayed_metadata
+                    && let Some(ref meta) = file.metadata
+                {
+                    if let Some(v) = &meta.datetime { lines.push(format!("Time: {}", v)); }
+                    if let Some(v) = &meta.model { lines.push(format!("Camera: {}", v)); }
+                    
+                    // Combine settings
+                    let mut settings = String::new();
+                    if let Some(iso) = &meta.iso { settings.push_str(&format!("ISO {} ", iso)); }
+                    if let Some(exp) = &meta.exposure_time { settings.push_str(&format!("{} ", exp)); }
+                    if let Some(f) = &meta.f_number { settings.push_str(&format!("{} ", f)); }
+                    if !settings.is_empty() { lines.push(format!("Settings: {}", settings.trim())); }
+
+                    if let Some(v) = &meta.focal_length { lines.push(format!("Focal: {}", v)); }
+                    if let Some(v) = &meta.gps { lines.push(format!("GPS: {}", v)); }
                 }
 
                 let text_scale = self.font_size;
@@ -919,13 +1352,29 @@ impl ViewerState {
                     draw_text(frame, fb_w, fb_h, line, 10, 4 + line_h * i as i32, text_scale, color);
                 }
             }
+
+            if self.show_histogram {
+                self.render_histogram(frame, fb_w, fb_h, dec.histogram.as_deref());
+            }
         }
 
         // Check for Error or Loading state overlays
         if let Some(ref err) = self.error_message {
             let text_scale = self.font_size;
             draw_text(frame, fb_w, fb_h, err, 20, fb_h as i32 / 2, text_scale, (255, 80, 80, 255));
-        } else if self.displayed_index != self.current_index || self.current_decoded.is_none() {
+        } else if self.showing_preview && self.displayed_index == self.current_index {
+            // Subtle tag in the bottom-left corner: the image is a low-res stand-in
+            let text_scale = self.font_size;
+            let label = "PREVIEW";
+            let tw = (label.len() as u32 * 6 * text_scale) + 12;
+            let th = 7 * text_scale + 10;
+            let ty = fb_h as i32 - th as i32 - 10;
+            fill_rect(frame, fb_w, fb_h, 10, ty, tw, th, (0, 0, 0, 128));
+            draw_text(frame, fb_w, fb_h, label, 16, ty + 5, text_scale, (200, 200, 200, 160));
+        } else if self.displayed_index != self.current_index
+            || self.current_decoded.is_none()
+            || self.page_pending()
+        {
              // ... existing loading log ...
             let text_scale = self.font_size;
             let tx = (fb_w as i32) / 2 - 30;
@@ -949,3 +1398,23 @@ impl ViewerState {
         }
     }
 }
+
+/// Overlay line for an original: how many duplicates it has, and how many
+/// of those are byte-identical.
+fn original_line(map: &HashMap<PathBuf, DuplicateInfo>, info: &DuplicateInfo) -> String {
+    let copies = map.values().filter(|v| v.original_path == info.original_path && !v.is_original);
+    let (count, exact) = copies.fold((0, 0), |(n, e), v| (n + 1, e + v.exact as usize));
+    if exact > 0 {
+        format!("-- ORIGINAL IMAGE -- ({} copies found, {} exact)", count, exact)
+    } else {
+        format!("-- ORIGINAL IMAGE -- ({} copies found)", count)
+    }
+}
+
+/// "Distance: N", with the rotation or mirroring that matched, if any.
+fn distance_line(info: &DuplicateInfo) -> String {
+    match info.transform {
+        Orientation::NoTransforms => format!("Distance: {}", info.distance),
+        transform => format!("Distance: {} ({})", info.distance, transform_name(transform)),
+    }
+}